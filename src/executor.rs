@@ -0,0 +1,995 @@
+use std::borrow::Borrow;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{btree_map, HashMap, VecDeque};
+use std::error::Error;
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use prettytable;
+
+use crate::query_planner::{
+    AccessPath, Accumulator, AggregateSubcommand, JoinStrategy,
+    JoinSubcommand, WhereSubcommand,
+};
+use crate::sql::JoinKind;
+use crate::storage::{corrupt, Decoder, Encoder};
+use crate::table::{Chamber, ColumnType, Row, Table};
+
+/// A step of an executing plan, which produces its rows one at a time, as
+/// they're pulled from it: `open` gets it ready to (opening the operators it
+/// pulls from), each `next` produces its next row (until there are none),
+/// and `close` lets go of whatever it was holding on to. Most operators hold
+/// no more than a row at a time; the ones that can't produce anything until
+/// they've seen all of their input (a sort, or aggregation) take it in when
+/// they're opened.
+crate trait Operator {
+    type Row;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>>;
+    fn next(&mut self) -> Result<Option<Self::Row>, Box<dyn Error>>;
+    fn close(&mut self);
+}
+
+crate type BoxedOperator<'a, R> = Box<dyn Operator<Row = R> + 'a>;
+
+/// The rows along an access path of a table.
+crate struct Scan<'a> {
+    table: &'a Table,
+    access_path: AccessPath,
+    rows: Option<Box<dyn Iterator<Item = &'a Row> + 'a>>,
+}
+
+impl<'a> Scan<'a> {
+    crate fn new(table: &'a Table, access_path: AccessPath) -> Self {
+        Self {
+            table,
+            access_path,
+            rows: None,
+        }
+    }
+}
+
+impl<'a> Operator for Scan<'a> {
+    type Row = &'a Row;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.rows = Some(self.access_path.rows(self.table));
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<&'a Row>, Box<dyn Error>> {
+        Ok(self.rows.as_mut().and_then(|rows| rows.next()))
+    }
+
+    fn close(&mut self) {
+        self.rows = None;
+    }
+}
+
+/// The rows that satisfy a predicate.
+crate struct Filter<'a, R> {
+    input: BoxedOperator<'a, R>,
+    predicate: Box<dyn Fn(&R) -> bool + 'a>,
+}
+
+impl<'a, R> Filter<'a, R> {
+    crate fn new<P>(input: BoxedOperator<'a, R>, predicate: P) -> Self
+    where
+        P: Fn(&R) -> bool + 'a,
+    {
+        Self {
+            input,
+            predicate: Box::new(predicate),
+        }
+    }
+}
+
+impl<'a, R> Operator for Filter<'a, R> {
+    type Row = R;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<R>, Box<dyn Error>> {
+        while let Some(row) = self.input.next()? {
+            if (self.predicate)(&row) {
+                return Ok(Some(row));
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+/// Each row transformed into another (as by a projection).
+crate struct Map<'a, R, S> {
+    input: BoxedOperator<'a, R>,
+    function: Box<dyn Fn(R) -> S + 'a>,
+}
+
+impl<'a, R, S> Map<'a, R, S> {
+    crate fn new<F>(input: BoxedOperator<'a, R>, function: F) -> Self
+    where
+        F: Fn(R) -> S + 'a,
+    {
+        Self {
+            input,
+            function: Box::new(function),
+        }
+    }
+}
+
+impl<'a, R, S> Operator for Map<'a, R, S> {
+    type Row = S;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<S>, Box<dyn Error>> {
+        Ok(self.input.next()?.map(&self.function))
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+type Comparison<'a, R> = Box<dyn Fn(&R, &R) -> Ordering + 'a>;
+
+/// The rows in order. (The sort is stable, so rows that compare equal stay
+/// in the order they came in.)
+crate struct Sort<'a, R> {
+    input: BoxedOperator<'a, R>,
+    compare: Comparison<'a, R>,
+    sorted: Option<std::vec::IntoIter<R>>,
+}
+
+impl<'a, R> Sort<'a, R> {
+    crate fn new<C>(input: BoxedOperator<'a, R>, compare: C) -> Self
+    where
+        C: Fn(&R, &R) -> Ordering + 'a,
+    {
+        Self {
+            input,
+            compare: Box::new(compare),
+            sorted: None,
+        }
+    }
+}
+
+impl<'a, R> Operator for Sort<'a, R> {
+    type Row = R;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.input.open()?;
+        let mut rows = Vec::new();
+        while let Some(row) = self.input.next()? {
+            rows.push(row);
+        }
+        let compare = &self.compare;
+        rows.sort_by(|left, right| compare(left, right));
+        self.sorted = Some(rows.into_iter());
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<R>, Box<dyn Error>> {
+        Ok(self.sorted.as_mut().and_then(|sorted| sorted.next()))
+    }
+
+    fn close(&mut self) {
+        self.sorted = None;
+        self.input.close();
+    }
+}
+
+/// At most `limit` rows, after skipping the first `offset`. Once it has
+/// produced them, it stops pulling rows from its input.
+crate struct Limit<'a, R> {
+    input: BoxedOperator<'a, R>,
+    limit: usize,
+    offset: usize,
+    // how many rows have been pulled from the input
+    pulled: usize,
+}
+
+impl<'a, R> Limit<'a, R> {
+    crate fn new(
+        input: BoxedOperator<'a, R>,
+        limit: usize,
+        offset: usize,
+    ) -> Self {
+        Self {
+            input,
+            limit,
+            offset,
+            pulled: 0,
+        }
+    }
+}
+
+impl<'a, R> Operator for Limit<'a, R> {
+    type Row = R;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.pulled = 0;
+        self.input.open()
+    }
+
+    fn next(&mut self) -> Result<Option<R>, Box<dyn Error>> {
+        while self.pulled < self.offset.saturating_add(self.limit) {
+            self.pulled += 1;
+            match self.input.next()? {
+                Some(row) if self.pulled > self.offset => return Ok(Some(row)),
+                Some(_) => {}
+                None => {
+                    self.pulled = usize::max_value();
+                    break;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn close(&mut self) {
+        self.input.close();
+    }
+}
+
+// Outer joins pad their unmatched rows with references to this
+static NULL: Chamber = Chamber::Null;
+
+// The value a chamber is hashed by in a hash join: keys and integers that
+// compare equal have to hash the same, and NULL is equal to nothing
+fn hash_join_key(chamber: &Chamber) -> Option<Chamber> {
+    match *chamber {
+        Chamber::Null => None,
+        Chamber::Key(k) => Some(Chamber::Integer(k as isize)),
+        ref chamber => Some(chamber.clone()),
+    }
+}
+
+// A left row joined with a right row, if they satisfy the ON condition
+fn try_join<'a>(
+    on: &WhereSubcommand,
+    left: &[&'a Chamber],
+    right: &'a Row,
+) -> Option<Vec<&'a Chamber>> {
+    let mut joined = left.to_vec();
+    joined.extend(right.0.iter());
+    if on.evaluate(&joined[..]) {
+        Some(joined)
+    } else {
+        None
+    }
+}
+
+enum JoinState<'a> {
+    // Inner, left, and cross joins pull a left row at a time, and look for
+    // its partners among the table's rows: through `buckets` (the table's
+    // rows by their join column) for a hash join, or by walking `rights` in
+    // step with the left rows for a merge join
+    ByLeft {
+        buckets: HashMap<Chamber, Vec<&'a Row>>,
+        rights: Peekable<btree_map::Values<'a, usize, Row>>,
+    },
+    // Right joins go through the table's rows, which takes having all of
+    // the left rows at hand: `buckets` has their positions by their join
+    // column for a hash join, and a merge join has walked past the first
+    // `merged` of them
+    ByRight {
+        left_rows: Vec<Vec<&'a Chamber>>,
+        buckets: HashMap<Chamber, Vec<usize>>,
+        rights: btree_map::Values<'a, usize, Row>,
+        merged: usize,
+    },
+}
+
+/// The rows pulled from `left` (of width `left_width`) joined with the rows
+/// of a table. Inner and left joins keep the order of the left rows, and
+/// right joins that of the table.
+crate struct Join<'a> {
+    left: BoxedOperator<'a, Vec<&'a Chamber>>,
+    join: JoinSubcommand<'a>,
+    left_width: usize,
+    // the joined rows waiting to be pulled (all of them from the same left
+    // row, or right row for a right join)
+    pending: VecDeque<Vec<&'a Chamber>>,
+    state: Option<JoinState<'a>>,
+}
+
+impl<'a> Join<'a> {
+    crate fn new(
+        left: BoxedOperator<'a, Vec<&'a Chamber>>,
+        join: JoinSubcommand<'a>,
+        left_width: usize,
+    ) -> Self {
+        Self {
+            left,
+            join,
+            left_width,
+            pending: VecDeque::new(),
+            state: None,
+        }
+    }
+}
+
+impl<'a> Operator for Join<'a> {
+    type Row = Vec<&'a Chamber>;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.left.open()?;
+        let table = self.join.table;
+        let mut state = match self.join.kind {
+            JoinKind::Right => {
+                let mut left_rows = Vec::new();
+                while let Some(left) = self.left.next()? {
+                    left_rows.push(left);
+                }
+                JoinState::ByRight {
+                    left_rows,
+                    buckets: HashMap::new(),
+                    rights: table.rows.values(),
+                    merged: 0,
+                }
+            }
+            _ => JoinState::ByLeft {
+                buckets: HashMap::new(),
+                rights: table.rows.values().peekable(),
+            },
+        };
+        if let JoinStrategy::Hash {
+            left_offset,
+            right_offset,
+        } = self.join.strategy
+        {
+            match &mut state {
+                JoinState::ByLeft { buckets, .. } => {
+                    for right in table.rows.values() {
+                        let key = hash_join_key(&right.0[right_offset]);
+                        if let Some(key) = key {
+                            let bucket = buckets.entry(key);
+                            bucket.or_insert_with(Vec::new).push(right)
+                        }
+                    }
+                }
+                JoinState::ByRight {
+                    left_rows, buckets, ..
+                } => {
+                    for (i, left) in left_rows.iter().enumerate() {
+                        if let Some(key) = hash_join_key(left[left_offset]) {
+                            buckets.entry(key).or_insert_with(Vec::new).push(i)
+                        }
+                    }
+                }
+            }
+        }
+        self.state = Some(state);
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<&'a Chamber>>, Box<dyn Error>> {
+        let Join {
+            left,
+            join,
+            left_width,
+            pending,
+            state,
+        } = self;
+        let on = &join.on;
+        loop {
+            if let Some(joined) = pending.pop_front() {
+                return Ok(Some(joined));
+            }
+            match state.as_mut().expect("join should have been opened") {
+                JoinState::ByLeft { buckets, rights } => {
+                    let left = match left.next()? {
+                        Some(left) => left,
+                        None => return Ok(None),
+                    };
+                    let matches: Vec<_> = match join.strategy {
+                        JoinStrategy::NestedLoop => join
+                            .table
+                            .rows
+                            .values()
+                            .filter_map(|right| try_join(on, &left, right))
+                            .collect(),
+                        JoinStrategy::Hash { left_offset, .. } => {
+                            hash_join_key(left[left_offset])
+                                .and_then(|key| buckets.get(&key))
+                                .map(|bucket| {
+                                    bucket
+                                        .iter()
+                                        .filter_map(|right| {
+                                            try_join(on, &left, right)
+                                        })
+                                        .collect()
+                                })
+                                .unwrap_or_default()
+                        }
+                        JoinStrategy::Merge {
+                            left_offset,
+                            right_offset,
+                        } => {
+                            let key = left[left_offset];
+                            while let Some(right) = rights.peek() {
+                                match right.0[right_offset].compare(key) {
+                                    Some(Ordering::Less) => {
+                                        rights.next();
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            // (the right keys are unique, so there's at most
+                            // one partner, which may also partner the next
+                            // left row)
+                            rights
+                                .peek()
+                                .and_then(|right| try_join(on, &left, right))
+                                .into_iter()
+                                .collect()
+                        }
+                    };
+                    // (a left join keeps a row without partners, padded with
+                    // NULLs)
+                    if matches.is_empty() && join.kind == JoinKind::Left {
+                        let mut padded = left.clone();
+                        padded.extend(
+                            (0..join.table.schema.layout.len()).map(|_| &NULL),
+                        );
+                        pending.push_back(padded);
+                    }
+                    pending.extend(matches);
+                }
+                JoinState::ByRight {
+                    left_rows,
+                    buckets,
+                    rights,
+                    merged,
+                } => {
+                    let right = match rights.next() {
+                        Some(right) => right,
+                        None => return Ok(None),
+                    };
+                    let matches: Vec<_> = match join.strategy {
+                        JoinStrategy::NestedLoop => left_rows
+                            .iter()
+                            .filter_map(|left| try_join(on, left, right))
+                            .collect(),
+                        JoinStrategy::Hash { right_offset, .. } => {
+                            hash_join_key(&right.0[right_offset])
+                                .and_then(|key| buckets.get(&key))
+                                .map(|bucket| {
+                                    bucket
+                                        .iter()
+                                        .filter_map(|&i| {
+                                            try_join(on, &left_rows[i], right)
+                                        })
+                                        .collect()
+                                })
+                                .unwrap_or_default()
+                        }
+                        JoinStrategy::Merge {
+                            left_offset,
+                            right_offset,
+                        } => {
+                            let key = &right.0[right_offset];
+                            let mut matches = Vec::new();
+                            while let Some(left) = left_rows.get(*merged) {
+                                match left[left_offset].compare(key) {
+                                    Some(Ordering::Less) => {}
+                                    Some(Ordering::Equal) => matches
+                                        .extend(try_join(on, left, right)),
+                                    _ => break,
+                                }
+                                *merged += 1;
+                            }
+                            matches
+                        }
+                    };
+                    if matches.is_empty() {
+                        let mut padded = (0..*left_width)
+                            .map(|_| &NULL)
+                            .collect::<Vec<_>>();
+                        padded.extend(right.0.iter());
+                        pending.push_back(padded);
+                    }
+                    pending.extend(matches);
+                }
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        self.pending.clear();
+        self.state = None;
+        self.left.close();
+    }
+}
+
+/// A row per group of the rows pulled from `input`: its first `group_width`
+/// columns are the group's, followed by the value of each aggregate, which
+/// folds the column after them in turn (so that aggregate `i` folds column
+/// `group_width + i`).
+crate struct Aggregate<'a> {
+    input: BoxedOperator<'a, Vec<&'a Chamber>>,
+    group_width: usize,
+    aggregates: Vec<AggregateSubcommand>,
+    groups: Option<std::vec::IntoIter<Vec<Chamber>>>,
+}
+
+impl<'a> Aggregate<'a> {
+    crate fn new(
+        input: BoxedOperator<'a, Vec<&'a Chamber>>,
+        group_width: usize,
+        aggregates: Vec<AggregateSubcommand>,
+    ) -> Self {
+        Self {
+            input,
+            group_width,
+            aggregates,
+            groups: None,
+        }
+    }
+}
+
+impl<'a> Operator for Aggregate<'a> {
+    type Row = Vec<Chamber>;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.input.open()?;
+        let group_width = self.group_width;
+        // Groups are kept in order of first appearance (rather than hash
+        // order), so that results are deterministic
+        let mut group_indices: HashMap<Vec<&'a Chamber>, usize> =
+            HashMap::new();
+        let mut groups: Vec<(Vec<&'a Chamber>, Vec<Accumulator>)> =
+            Vec::new();
+        while let Some(row) = self.input.next()? {
+            let key = row[..group_width].to_vec();
+            let aggregates = &self.aggregates;
+            let index = *group_indices.entry(key.clone()).or_insert_with(|| {
+                let accumulators = aggregates
+                    .iter()
+                    .map(AggregateSubcommand::accumulator)
+                    .collect();
+                groups.push((key, accumulators));
+                groups.len() - 1
+            });
+            for (i, accumulator) in groups[index].1.iter_mut().enumerate() {
+                accumulator.add(row[group_width + i])?;
+            }
+        }
+        if groups.is_empty() && group_width == 0 {
+            // Without a GROUP BY, there's a (single) group even when there
+            // are no rows
+            let accumulators = self
+                .aggregates
+                .iter()
+                .map(AggregateSubcommand::accumulator)
+                .collect();
+            groups.push((Vec::new(), accumulators));
+        }
+        let group_rows = groups
+            .into_iter()
+            .map(|(key, accumulators)| {
+                key.into_iter()
+                    .cloned()
+                    .chain(accumulators.into_iter().map(Accumulator::finish))
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        self.groups = Some(group_rows.into_iter());
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<Chamber>>, Box<dyn Error>> {
+        Ok(self.groups.as_mut().and_then(|groups| groups.next()))
+    }
+
+    fn close(&mut self) {
+        self.groups = None;
+        self.input.close();
+    }
+}
+
+// How many rows an operator has produced, and how long it's taken, shared
+// between the operator and the `Instruments` that read it
+type Actual = Rc<Cell<(usize, Duration)>>;
+
+// Counts the rows an operator produces, and the time it spends producing
+// them (including opening, and the time spent in the operators it pulls
+// from), for EXPLAIN ANALYZE
+struct Instrumented<'a, R> {
+    inner: BoxedOperator<'a, R>,
+    actual: Actual,
+}
+
+impl<'a, R> Instrumented<'a, R> {
+    fn time<T, S>(&mut self, step: S) -> T
+    where
+        S: FnOnce(&mut BoxedOperator<'a, R>) -> T,
+    {
+        let start = Instant::now();
+        let result = step(&mut self.inner);
+        let (rows, time) = self.actual.get();
+        self.actual.set((rows, time + start.elapsed()));
+        result
+    }
+}
+
+impl<'a, R> Operator for Instrumented<'a, R> {
+    type Row = R;
+
+    fn open(&mut self) -> Result<(), Box<dyn Error>> {
+        self.time(|inner| inner.open())
+    }
+
+    fn next(&mut self) -> Result<Option<R>, Box<dyn Error>> {
+        let row = self.time(|inner| inner.next())?;
+        if row.is_some() {
+            let (rows, time) = self.actual.get();
+            self.actual.set((rows + 1, time));
+        }
+        Ok(row)
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+/// Attaches instruments to operators as a plan is built (from the bottom),
+/// if it's going to be analyzed.
+crate struct Instruments {
+    actuals: Option<Vec<Actual>>,
+}
+
+impl Instruments {
+    crate fn on() -> Self {
+        Self {
+            actuals: Some(Vec::new()),
+        }
+    }
+
+    crate fn off() -> Self {
+        Self { actuals: None }
+    }
+
+    crate fn attach<'a, R: 'a>(
+        &mut self,
+        operator: BoxedOperator<'a, R>,
+    ) -> BoxedOperator<'a, R> {
+        match &mut self.actuals {
+            Some(actuals) => {
+                let actual = Rc::new(Cell::new((0, Duration::default())));
+                actuals.push(actual.clone());
+                Box::new(Instrumented {
+                    inner: operator,
+                    actual,
+                })
+            }
+            None => operator,
+        }
+    }
+
+    /// What each instrumented operator did, from the top of the plan.
+    crate fn readings(&self) -> Vec<(usize, Duration)> {
+        match &self.actuals {
+            Some(actuals) => {
+                actuals.iter().rev().map(|actual| actual.get()).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The rows of a query, produced by its plan as they're pulled. The plan is
+/// opened when the first row is asked for, and closed after the last (or
+/// an error, or when the rows are dropped).
+crate struct Rows<'a, R> {
+    operator: BoxedOperator<'a, R>,
+    open: bool,
+    done: bool,
+}
+
+impl<'a, R> Rows<'a, R> {
+    crate fn new(operator: BoxedOperator<'a, R>) -> Self {
+        Self {
+            operator,
+            open: false,
+            done: false,
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.open {
+            self.operator.close();
+        }
+        self.open = false;
+        self.done = true;
+    }
+}
+
+impl<'a, R> Iterator for Rows<'a, R> {
+    type Item = Result<R, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if !self.open {
+            self.open = true;
+            if let Err(error) = self.operator.open() {
+                self.finish();
+                return Some(Err(error));
+            }
+        }
+        match self.operator.next() {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.finish();
+                None
+            }
+            Err(error) => {
+                self.finish();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl<'a, R> Drop for Rows<'a, R> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+crate struct ResultColumn {
+    crate name: String,
+    crate column_type: ColumnType,
+}
+
+/// The rows of a query along with the names and types of its columns,
+/// holding on to nothing of the database it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResultSet {
+    crate columns: Vec<ResultColumn>,
+    crate rows: Vec<Vec<Chamber>>,
+}
+
+impl ResultSet {
+    /// Pulls all of the rows (unless one of them is an error).
+    crate fn collect<R>(
+        columns: Vec<ResultColumn>,
+        rows: Rows<'_, R>,
+    ) -> Result<Self, Box<dyn Error>>
+    where
+        R: IntoIterator,
+        R::Item: Borrow<Chamber>,
+    {
+        let mut owned_rows = Vec::new();
+        for row in rows {
+            owned_rows.push(
+                row?.into_iter()
+                    .map(|chamber| chamber.borrow().clone())
+                    .collect(),
+            );
+        }
+        Ok(Self {
+            columns,
+            rows: owned_rows,
+        })
+    }
+
+    /// Serializes the result set (the same way the database file is), so
+    /// that it can be kept somewhere other than in memory.
+    #[allow(dead_code)]
+    crate fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.usize(self.columns.len());
+        for column in &self.columns {
+            encoder.string(&column.name);
+            encoder.column_type(column.column_type);
+        }
+        encoder.usize(self.rows.len());
+        for row in &self.rows {
+            encoder.usize(row.len());
+            for chamber in row {
+                encoder.chamber(chamber);
+            }
+        }
+        encoder.bytes
+    }
+
+    /// Deserializes what `encode` serialized.
+    #[allow(dead_code)]
+    crate fn decode(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut decoder = Decoder::new(bytes);
+        let mut columns = Vec::new();
+        for _ in 0..decoder.count()? {
+            columns.push(ResultColumn {
+                name: decoder.string()?,
+                column_type: decoder.column_type()?,
+            });
+        }
+        let mut rows = Vec::new();
+        for _ in 0..decoder.count()? {
+            let width = decoder.count()?;
+            if width != columns.len() {
+                return Err(corrupt("row of the wrong width"));
+            }
+            let mut row = Vec::with_capacity(width);
+            for _ in 0..width {
+                row.push(decoder.chamber()?);
+            }
+            rows.push(row);
+        }
+        if !decoder.is_done() {
+            return Err(corrupt("extra data after rows"));
+        }
+        Ok(Self { columns, rows })
+    }
+
+    crate fn display(&self) -> String {
+        let mut buf = Vec::new();
+        let mut display_table = prettytable::Table::new();
+        display_table.set_format(
+            *prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE,
+        );
+        let mut headers = prettytable::row::Row::empty();
+        for column in &self.columns {
+            headers.add_cell(prettytable::cell::Cell::new(&column.name));
+        }
+        display_table.set_titles(headers);
+        for chambers in &self.rows {
+            let mut display_row = prettytable::row::Row::empty();
+            for chamber in chambers {
+                display_row.add_cell(
+                    prettytable::cell::Cell::new(&chamber.display()),
+                );
+            }
+            display_table.add_row(display_row);
+        }
+        display_table
+            .print(&mut buf)
+            .expect("should print to buffer");
+        String::from_utf8(buf).expect("pretty table should be valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // How many times an operator has been opened, pulled from, and closed
+    type Log = Rc<Cell<(usize, usize, usize)>>;
+
+    // Produces the numbers it's given, keeping track of what's been asked of
+    // it
+    struct Numbers {
+        numbers: Vec<usize>,
+        log: Log,
+    }
+
+    impl Operator for Numbers {
+        type Row = usize;
+
+        fn open(&mut self) -> Result<(), Box<dyn Error>> {
+            let (opens, pulls, closes) = self.log.get();
+            self.log.set((opens + 1, pulls, closes));
+            Ok(())
+        }
+
+        fn next(&mut self) -> Result<Option<usize>, Box<dyn Error>> {
+            let (opens, pulls, closes) = self.log.get();
+            self.log.set((opens, pulls + 1, closes));
+            Ok(self.numbers.get(pulls).cloned())
+        }
+
+        fn close(&mut self) {
+            let (opens, pulls, closes) = self.log.get();
+            self.log.set((opens, pulls, closes + 1));
+        }
+    }
+
+    fn numbers(count: usize) -> (BoxedOperator<'static, usize>, Log) {
+        let log = Rc::new(Cell::new((0, 0, 0)));
+        let numbers = Numbers {
+            numbers: (0..count).collect(),
+            log: log.clone(),
+        };
+        (Box::new(numbers), log)
+    }
+
+    #[test]
+    fn concerning_rows() {
+        let (operator, log) = numbers(3);
+        let mut rows = Rows::new(operator);
+        assert_eq!(log.get(), (0, 0, 0));
+        assert_eq!(rows.next().unwrap().unwrap(), 0);
+        assert_eq!(log.get(), (1, 1, 0));
+        drop(rows);
+        assert_eq!(log.get(), (1, 1, 1));
+
+        let (operator, log) = numbers(3);
+        let rows = Rows::new(operator);
+        assert_eq!(
+            rows.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![0, 1, 2]
+        );
+        assert_eq!(log.get(), (1, 4, 1));
+
+        // (rows that are never asked for never open their plan)
+        let (operator, log) = numbers(3);
+        drop(Rows::new(operator));
+        assert_eq!(log.get(), (0, 0, 0));
+    }
+
+    #[test]
+    fn concerning_limits() {
+        let (operator, log) = numbers(100);
+        let filtered = Filter::new(operator, |&n: &usize| n % 2 == 1);
+        let limited = Limit::new(Box::new(filtered), 2, 1);
+        let rows = Rows::new(Box::new(limited));
+        assert_eq!(rows.collect::<Result<Vec<_>, _>>().unwrap(), vec![3, 5]);
+        // Once it has the limit's worth of rows, it stops pulling
+        assert_eq!(log.get(), (1, 6, 1));
+
+        let (operator, log) = numbers(2);
+        let limited = Limit::new(operator, 5, 1);
+        let rows = Rows::new(Box::new(limited));
+        assert_eq!(rows.collect::<Result<Vec<_>, _>>().unwrap(), vec![1]);
+        assert_eq!(log.get(), (1, 3, 1));
+    }
+
+    #[test]
+    fn concerning_sorts() {
+        let (operator, log) = numbers(4);
+        let sorted = Sort::new(operator, |a: &usize, b: &usize| b.cmp(a));
+        let mut rows = Rows::new(Box::new(sorted));
+        assert_eq!(rows.next().unwrap().unwrap(), 3);
+        // (a sort has to see all of its input before producing anything)
+        assert_eq!(log.get(), (1, 5, 0));
+        assert_eq!(
+            rows.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec![2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn concerning_encoding_result_sets() {
+        let result_set = ResultSet {
+            columns: vec![
+                ResultColumn {
+                    name: "title".to_owned(),
+                    column_type: ColumnType::String,
+                },
+                ResultColumn {
+                    name: "SUM(year)".to_owned(),
+                    column_type: ColumnType::Integer,
+                },
+            ],
+            rows: vec![
+                vec![Chamber::String("Kindred".to_owned()), Chamber::Null],
+                vec![Chamber::String("Dawn".to_owned()), Chamber::Integer(-1)],
+            ],
+        };
+        let bytes = result_set.encode();
+        assert_eq!(ResultSet::decode(&bytes).unwrap(), result_set);
+        assert!(ResultSet::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let empty = ResultSet {
+            columns: Vec::new(),
+            rows: Vec::new(),
+        };
+        assert_eq!(ResultSet::decode(&empty.encode()).unwrap(), empty);
+    }
+}
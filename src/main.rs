@@ -4,105 +4,855 @@
 #[macro_use]
 extern crate nom;
 
+mod executor;
 mod query_planner;
 mod sql;
+mod storage;
 mod table;
+mod wal;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use rustyline::{self, error::ReadlineError};
 
-use crate::query_planner::{SelectCommand, WhereSubcommand};
-use crate::sql::{parse_statement, ColumnClause, Statement};
-use crate::table::{Chamber, ColumnType, Row, Table, TableSchema};
+use crate::executor::{Instruments, Map, ResultColumn, ResultSet, Rows};
+use crate::query_planner::{
+    column_names_to_offsets, AggregateCommand, JoinCommand, PlanNode,
+    SelectCommand, SortSubcommand, ValueSubcommand, WhereSubcommand,
+};
+use crate::sql::{
+    parse_statement, ColumnClause, ColumnConstraint, ColumnDefinition,
+    ColumnExpression, Condition, ReferentialAction, SelectStatement,
+    Statement,
+};
+use crate::table::{
+    Chamber, Column, ColumnType, Constraints, ForeignKey, Table, TableSchema,
+};
+use crate::wal::{Change, Log};
 
 pub struct Database {
     crate tables: HashMap<String, Table>,
+    // the file the database is kept in, and the log of the changes made
+    // since it was last written (unless the database is only in memory)
+    crate path: Option<PathBuf>,
+    crate log: Option<Log>,
+}
+
+// The write-ahead log goes beside the database file, with ".wal" added to
+// its name
+fn log_path(path: &Path) -> PathBuf {
+    let mut log_path = path.as_os_str().to_owned();
+    log_path.push(".wal");
+    PathBuf::from(log_path)
 }
 
 impl Database {
     pub fn new() -> Self {
         Self {
             tables: HashMap::new(),
+            path: None,
+            log: None,
+        }
+    }
+
+    /// Opens the database in the file at `path` (or a new, empty one if
+    /// there's no file there yet), replaying the changes in its log that
+    /// were made after the last checkpoint.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_owned();
+        let (tables, checkpoint) = match fs::read(&path) {
+            Ok(file) => storage::read_tables(&file)?,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => {
+                (HashMap::new(), 0)
+            }
+            Err(error) => return Err(From::from(error)),
+        };
+        let (log, statements) = Log::open(&log_path(&path), checkpoint)?;
+        let mut db = Self {
+            tables,
+            path: Some(path),
+            log: Some(log),
+        };
+        for change in statements.into_iter().flatten() {
+            change.apply(&mut db.tables)?;
+        }
+        Ok(db)
+    }
+
+    /// Writes the whole database to its file, which then has all of the
+    /// changes in the log, so the log is emptied.
+    pub fn checkpoint(&mut self) -> Result<(), Box<dyn Error>> {
+        let (path, log) = match (&self.path, &mut self.log) {
+            (Some(path), Some(log)) => (path, log),
+            _ => {
+                return Err(From::from(
+                    "database is only in memory; there's nothing to \
+                     checkpoint",
+                ))
+            }
+        };
+        let file =
+            storage::write_tables(&self.tables, log.last_sequence_number());
+        storage::write_file(path, &file)?;
+        // (only now that the new file is sure to survive a crash)
+        log.clear()
+    }
+
+    /// Makes a statement's changes, after recording them in the log, if
+    /// there is one: once that's done, they survive a crash. The changes
+    /// must all have been checked already, as there's no taking them back
+    /// once they're logged (and a change that couldn't be made would keep
+    /// the database from being opened again).
+    crate fn commit(
+        &mut self,
+        changes: Vec<Change>,
+    ) -> Result<(), Box<dyn Error>> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        if let Some(log) = &mut self.log {
+            log.append(&changes)?;
+        }
+        for change in changes {
+            change
+                .apply(&mut self.tables)
+                .expect("changes should be checked before they're committed");
+        }
+        Ok(())
+    }
+
+    /// The foreign key columns (as table name and column offset) referencing
+    /// the named table.
+    crate fn references_to(
+        &self,
+        table_name: &str,
+    ) -> Vec<(String, usize, ReferentialAction)> {
+        let mut references = Vec::new();
+        for (name, table) in &self.tables {
+            for (offset, column) in table.schema.layout.iter().enumerate() {
+                if let Some(foreign_key) = &column.constraints.references {
+                    if foreign_key.table_name == table_name {
+                        references.push((
+                            name.clone(),
+                            offset,
+                            foreign_key.on_delete,
+                        ));
+                    }
+                }
+            }
         }
+        // (in a deterministic order, for the sake of error messages)
+        references.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        references
     }
 
-    pub fn add_table(&mut self, name: &str, table: Table) {
-        self.tables.insert(name.to_owned(), table);
+    /// Refuses to drop or truncate a table that other tables reference.
+    fn check_unreferenced(
+        &self,
+        table_name: &str,
+        verb: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let references = self.references_to(table_name);
+        match references.iter().find(|(name, ..)| name != table_name) {
+            Some((name, offset, _)) => Err(From::from(format!(
+                "can't {} table {}: column {}.{} references it",
+                verb,
+                table_name,
+                name,
+                self.tables[name].schema.layout[*offset].name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that a value for a foreign key column is the primary key of a
+    /// row in the referenced table (or NULL).
+    crate fn validate_reference(
+        &self,
+        column: &Column,
+        chamber: &Chamber,
+    ) -> Result<(), Box<dyn Error>> {
+        let foreign_key = match &column.constraints.references {
+            Some(foreign_key) => foreign_key,
+            None => return Ok(()),
+        };
+        let referenced = &self.tables[&foreign_key.table_name];
+        let exists = match *chamber {
+            Chamber::Null => true,
+            Chamber::Integer(i) => {
+                i >= 0 && referenced.rows.contains_key(&(i as usize))
+            }
+            _ => false,
+        };
+        if !exists {
+            return Err(From::from(format!(
+                "value {} in column {} violates FOREIGN KEY constraint \
+                 (no such row in {})",
+                chamber.display(),
+                column.name,
+                foreign_key.table_name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Deletes the rows of a table satisfying a condition, returning how many
+    /// there were. Rows referencing them are deleted too, or have their
+    /// references set to NULL, as their foreign keys declare; nothing is
+    /// deleted if any would have to RESTRICT the deletion.
+    crate fn delete_where(
+        &mut self,
+        table_name: &str,
+        filter: &WhereSubcommand,
+    ) -> Result<usize, Box<dyn Error>> {
+        let table = get_table(self, table_name)?;
+        let doomed = table
+            .rows
+            .iter()
+            .filter(|(_, row)| filter.evaluate(*row))
+            .map(|(&pk, _)| pk)
+            .collect::<BTreeSet<_>>();
+        let count = doomed.len();
+
+        // Follows references from the rows being deleted to find the rows
+        // that cascade, that need NULLs, and that restrict
+        let mut deletions = HashMap::new();
+        deletions.insert(table_name.to_owned(), doomed.clone());
+        let mut nullifications = Vec::new();
+        let mut restrictions = Vec::new();
+        let mut worklist = vec![(table_name.to_owned(), doomed)];
+        while let Some((referenced_name, pks)) = worklist.pop() {
+            let references = self.references_to(&referenced_name);
+            for (name, offset, on_delete) in references {
+                let referencing_pks = self.tables[&name]
+                    .rows
+                    .iter()
+                    .filter(|(_, row)| match row.0[offset] {
+                        Chamber::Integer(i) => {
+                            i >= 0 && pks.contains(&(i as usize))
+                        }
+                        _ => false,
+                    })
+                    .map(|(&pk, _)| pk)
+                    .collect::<Vec<_>>();
+                match on_delete {
+                    ReferentialAction::Restrict => restrictions.extend(
+                        referencing_pks.into_iter().map(|pk| {
+                            (referenced_name.clone(), name.clone(), offset, pk)
+                        }),
+                    ),
+                    ReferentialAction::Cascade => {
+                        let deleted = deletions
+                            .entry(name.clone())
+                            .or_insert_with(BTreeSet::new);
+                        let newly_deleted = referencing_pks
+                            .into_iter()
+                            .filter(|&pk| deleted.insert(pk))
+                            .collect::<BTreeSet<_>>();
+                        if !newly_deleted.is_empty() {
+                            worklist.push((name, newly_deleted));
+                        }
+                    }
+                    ReferentialAction::SetNull => nullifications.extend(
+                        referencing_pks
+                            .into_iter()
+                            .map(|pk| (name.clone(), offset, pk)),
+                    ),
+                }
+            }
+        }
+
+        let is_deleted = |name: &String, pk: usize| {
+            deletions.get(name).map_or(false, |pks| pks.contains(&pk))
+        };
+        // (a restricting row doesn't count if it's going too)
+        for (referenced_name, name, offset, pk) in restrictions {
+            if !is_deleted(&name, pk) {
+                return Err(From::from(format!(
+                    "deleting from {} violates FOREIGN KEY constraint on \
+                     column {}.{}",
+                    referenced_name,
+                    name,
+                    self.tables[&name].schema.layout[offset].name
+                )));
+            }
+        }
+        // (a row can have more than one of its references set to NULL)
+        let mut nullified = BTreeMap::new();
+        for (name, offset, pk) in nullifications {
+            if !is_deleted(&name, pk) {
+                let null = ValueSubcommand::Constant(Chamber::Null);
+                nullified
+                    .entry((name, pk))
+                    .or_insert_with(Vec::new)
+                    .push((offset, null));
+            }
+        }
+        let mut changes = Vec::new();
+        for ((name, pk), assignments) in nullified {
+            let rows = self.tables[&name]
+                .updated_where(&assignments, |row| row.pk() == pk)?;
+            changes.extend(rows.into_iter().map(|row| Change::Put {
+                table_name: name.clone(),
+                row,
+            }));
+        }
+        for (name, pks) in deletions {
+            changes.extend(pks.into_iter().map(|pk| Change::Delete {
+                table_name: name.clone(),
+                pk,
+            }));
+        }
+        self.commit(changes)?;
+        Ok(count)
     }
 }
 
 #[derive(Debug)]
-pub enum QueryOk<'a> {
-    Select(Vec<Vec<&'a Chamber>>),
+pub enum QueryOk {
+    Select(ResultSet),
     Insert(usize),
+    CreateTable,
+    CreateIndex,
+    DropTable(usize),
+    Truncate(usize),
+    Delete(usize),
+    Update(usize),
+    Explain(PlanNode),
+    Analyze(usize),
 }
 
-fn execute_statement<'db>(
-    db: &'db mut Database,
+fn where_subcommand(
+    schema: &TableSchema,
+    where_clause: Option<Condition>,
+) -> Result<WhereSubcommand, Box<dyn Error>> {
+    match where_clause {
+        Some(condition) => WhereSubcommand::new(schema, condition),
+        None => Ok(WhereSubcommand::new_unconditional()),
+    }
+}
+
+fn column_constraints(
+    db: &Database,
+    table_name: &str,
+    schema: &TableSchema,
+    column: ColumnDefinition,
+) -> Result<Constraints, Box<dyn Error>> {
+    let mut constraints = Constraints::default();
+    for constraint in column.constraints {
+        match constraint {
+            ColumnConstraint::NotNull => constraints.not_null = true,
+            ColumnConstraint::Unique => constraints.unique = true,
+            ColumnConstraint::Default(value) => {
+                constraints.default = Some(value)
+            }
+            ColumnConstraint::Check(condition) => {
+                let check = WhereSubcommand::new(schema, condition)?;
+                // (a column can have several CHECKs, all of which must hold)
+                constraints.check = Some(match constraints.check.take() {
+                    Some(previous) => WhereSubcommand::And(
+                        Box::new(previous),
+                        Box::new(check),
+                    ),
+                    None => check,
+                });
+            }
+            ColumnConstraint::References {
+                table_name: referenced_name,
+                column_name,
+                on_delete,
+            } => {
+                // (a table can refer to itself)
+                let referenced_schema = if referenced_name == table_name {
+                    schema
+                } else {
+                    &get_table(db, &referenced_name)?.schema
+                };
+                let offset = column_names_to_offsets(
+                    referenced_schema,
+                    &[column_name.clone()],
+                )?[0];
+                if referenced_schema.layout[offset].column_type
+                    != ColumnType::Key
+                {
+                    return Err(From::from(format!(
+                        "foreign key column {} can only reference a primary \
+                         key, not {}.{}",
+                        column.name, referenced_name, column_name
+                    )));
+                }
+                if column.column_type != ColumnType::Integer {
+                    return Err(From::from(format!(
+                        "foreign key column {} must be INTEGER",
+                        column.name
+                    )));
+                }
+                constraints.references = Some(ForeignKey {
+                    table_name: referenced_name,
+                    on_delete,
+                });
+            }
+        }
+    }
+    if constraints.not_null {
+        if let Some(ForeignKey {
+            on_delete: ReferentialAction::SetNull,
+            ..
+        }) = constraints.references
+        {
+            return Err(From::from(format!(
+                "column {} can't be both NOT NULL and ON DELETE SET NULL",
+                column.name
+            )));
+        }
+    }
+    Ok(constraints)
+}
+
+fn get_table<'db>(
+    db: &'db Database,
+    table_name: &str,
+) -> Result<&'db Table, Box<dyn Error>> {
+    db.tables
+        .get(table_name)
+        .ok_or_else(|| From::from(format!("no table named {}", table_name)))
+}
+
+fn plan_join<'db>(
+    db: &'db Database,
+    statement: SelectStatement,
+) -> Result<JoinCommand<'db>, Box<dyn Error>> {
+    if !statement.group_by.is_empty() || statement.having.is_some() {
+        return Err(From::from("aggregates over joins aren't supported yet"));
+    }
+    let first = get_table(db, &statement.table_name)?;
+    let qualifier = statement.table_alias.unwrap_or(statement.table_name);
+    let mut command = JoinCommand::new(first, qualifier);
+    for join in statement.joins {
+        let table = get_table(db, &join.table_name)?;
+        let qualifier = join.table_alias.unwrap_or(join.table_name);
+        command = command.join(join.kind, table, qualifier, join.on)?;
+    }
+    command = command.reordered_by_cost();
+    if let ColumnClause::Expressions(expressions) = statement.column_names {
+        let mut column_names = Vec::new();
+        for expression in expressions {
+            match expression {
+                ColumnExpression::Column(column_name) => {
+                    column_names.push(column_name)
+                }
+                ColumnExpression::Aggregate { .. } => {
+                    return Err(From::from(
+                        "aggregates over joins aren't supported yet",
+                    ))
+                }
+            }
+        }
+        command = command.projecting(column_names)?;
+    }
+    if let Some(condition) = statement.where_clause {
+        command = command.filtered_by(condition)?;
+    }
+    if !statement.order_by.is_empty() {
+        command = command.sorted_by(statement.order_by)?;
+    }
+    if let Some(limit) = statement.limit {
+        command = command.limited_to(limit.count, limit.offset);
+    }
+    Ok(command)
+}
+
+// A SELECT statement's columns, and its rows, which are produced (with any
+// errors met along the way) only as they're pulled
+type SelectRows<'db> = (Vec<ResultColumn>, Rows<'db, Vec<Chamber>>);
+
+// A SELECT statement, planned (but not yet executed)
+enum SelectPlan<'db> {
+    // with the name of the table
+    Scan(SelectCommand<'db>, String),
+    Join(JoinCommand<'db>),
+    Aggregate(AggregateCommand<'db>, String),
+}
+
+impl<'db> SelectPlan<'db> {
+    fn explain(&self) -> PlanNode {
+        match self {
+            SelectPlan::Scan(command, table_name) => {
+                command.explain(table_name)
+            }
+            SelectPlan::Join(command) => command.explain(),
+            SelectPlan::Aggregate(command, table_name) => {
+                command.explain(table_name)
+            }
+        }
+    }
+
+    // (the scanned and joined rows are copied out of their tables as
+    // they're pulled, so as to come out the same as the aggregated ones)
+    fn rows(self) -> SelectRows<'db> {
+        let owned = |row: Vec<&Chamber>| -> Vec<Chamber> {
+            row.into_iter().cloned().collect()
+        };
+        match self {
+            SelectPlan::Scan(command, _) => {
+                let columns = command.columns();
+                let operator = command.into_operator(&mut Instruments::off());
+                (columns, Rows::new(Box::new(Map::new(operator, owned))))
+            }
+            SelectPlan::Join(command) => {
+                let columns = command.columns();
+                let operator = command.into_operator(&mut Instruments::off());
+                (columns, Rows::new(Box::new(Map::new(operator, owned))))
+            }
+            SelectPlan::Aggregate(command, _) => {
+                (command.columns(), command.rows())
+            }
+        }
+    }
+
+    // (EXPLAIN ANALYZE discards the rows)
+    fn analyze(self) -> Result<PlanNode, Box<dyn Error>> {
+        Ok(match self {
+            SelectPlan::Scan(command, table_name) => {
+                command.analyze(&table_name).1
+            }
+            SelectPlan::Join(command) => command.analyze().1,
+            SelectPlan::Aggregate(command, table_name) => {
+                command.analyze(&table_name)?.1
+            }
+        })
+    }
+}
+
+fn plan_select<'db>(
+    db: &'db Database,
+    statement: SelectStatement,
+) -> Result<SelectPlan<'db>, Box<dyn Error>> {
+    if !statement.joins.is_empty() || statement.table_alias.is_some() {
+        return Ok(SelectPlan::Join(plan_join(db, statement)?));
+    }
+    let table = get_table(db, &statement.table_name)?;
+    let column_expressions = match statement.column_names {
+        ColumnClause::Star => table
+            .schema
+            .layout
+            .iter()
+            .map(|column| ColumnExpression::Column(column.name.clone()))
+            .collect(),
+        ColumnClause::Expressions(expressions) => expressions,
+    };
+
+    let where_subcommand =
+        where_subcommand(&table.schema, statement.where_clause)?;
+
+    if column_expressions.iter().any(|e| e.is_aggregate())
+        || !statement.group_by.is_empty()
+        || statement.having.is_some()
+    {
+        if !statement.order_by.is_empty() {
+            return Err(From::from(
+                "ORDER BY isn't supported with aggregates",
+            ));
+        }
+        let mut command = AggregateCommand::new(
+            &table,
+            column_expressions,
+            where_subcommand,
+            statement.group_by,
+            statement.having,
+        )?;
+        if let Some(limit) = statement.limit {
+            command = command.limited_to(limit.count, limit.offset);
+        }
+        return Ok(SelectPlan::Aggregate(command, statement.table_name));
+    }
+
+    let column_names = column_expressions
+        .into_iter()
+        .map(|expression| match expression {
+            ColumnExpression::Column(column_name) => column_name,
+            ColumnExpression::Aggregate { .. } => unreachable!(),
+        })
+        .collect();
+
+    let mut command =
+        SelectCommand::new(&table, column_names, where_subcommand)?;
+    if !statement.order_by.is_empty() {
+        command = command.sorted_by(SortSubcommand::new(
+            &table.schema,
+            statement.order_by,
+        )?);
+    }
+    if let Some(limit) = statement.limit {
+        command = command.limited_to(limit.count, limit.offset);
+    }
+    Ok(SelectPlan::Scan(command, statement.table_name))
+}
+
+fn select<'db>(
+    db: &'db Database,
+    statement: SelectStatement,
+) -> Result<SelectRows<'db>, Box<dyn Error>> {
+    Ok(plan_select(db, statement)?.rows())
+}
+
+fn execute_statement(
+    db: &mut Database,
     statement: Statement,
-) -> Result<QueryOk<'db>, Box<dyn Error>> {
+) -> Result<QueryOk, Box<dyn Error>> {
     match statement {
         Statement::Select(statement) => {
-            let table = db.tables
-                .get(&statement.table_name)
-                .ok_or(format!(
+            let (columns, rows) = select(db, statement)?;
+            Ok(QueryOk::Select(ResultSet::collect(columns, rows)?))
+        }
+        Statement::Explain(statement) => {
+            let plan = plan_select(db, statement.statement)?;
+            Ok(QueryOk::Explain(if statement.analyze {
+                plan.analyze()?
+            } else {
+                plan.explain()
+            }))
+        }
+        Statement::Insert(statement) => {
+            let table = get_table(db, &statement.table_name)?;
+            let offsets = match statement.column_names {
+                Some(column_names) => {
+                    column_names_to_offsets(&table.schema, &column_names)?
+                }
+                // (the values go in the columns after the primary key, in
+                // order; columns left out of a column list get their
+                // defaults)
+                None => (1..table.schema.layout.len()).collect(),
+            };
+            if statement.values.len() > offsets.len() {
+                return Err(From::from("INSERT has more values than columns"));
+            }
+            if statement.values.len() < offsets.len() {
+                return Err(From::from(
+                    "INSERT has fewer values than columns",
+                ));
+            }
+            let mut row = table.schema.default_row();
+            let mut assigned = vec![false; row.0.len()];
+            for (offset, value) in offsets.into_iter().zip(statement.values) {
+                let column = &table.schema.layout[offset];
+                if column.column_type == ColumnType::Key {
+                    return Err(From::from(format!(
+                        "can't insert into primary key column {}",
+                        column.name
+                    )));
+                }
+                if assigned[offset] {
+                    return Err(From::from(format!(
+                        "column {} is specified more than once",
+                        column.name
+                    )));
+                }
+                assigned[offset] = true;
+                row.0[offset] = value;
+            }
+            for (column, chamber) in table.schema.layout.iter().zip(&row.0) {
+                db.validate_reference(column, chamber)?;
+            }
+            let row = table.prepare_insert(row)?;
+            db.commit(vec![Change::Put {
+                table_name: statement.table_name,
+                row,
+            }])?;
+            Ok(QueryOk::Insert(1))
+        }
+        Statement::CreateTable(statement) => {
+            let mut schema = TableSchema::new();
+            for column in &statement.columns {
+                schema.add_column(column.name.clone(), column.column_type)?;
+            }
+            for (i, column) in statement.columns.into_iter().enumerate() {
+                let constraints = column_constraints(
+                    db,
+                    &statement.table_name,
+                    &schema,
+                    column,
+                )?;
+                schema.constrain(i + 1, constraints)?;
+            }
+            if db.tables.contains_key(&statement.table_name) {
+                return Err(From::from(format!(
+                    "table {} already exists",
+                    statement.table_name
+                )));
+            }
+            db.commit(vec![Change::CreateTable {
+                table_name: statement.table_name,
+                schema,
+            }])?;
+            Ok(QueryOk::CreateTable)
+        }
+        Statement::CreateIndex(statement) => {
+            let table = get_table(db, &statement.table_name)?;
+            let column_offsets = column_names_to_offsets(
+                &table.schema,
+                &statement.column_names,
+            )?;
+            for (i, offset) in column_offsets.iter().enumerate() {
+                if column_offsets[..i].contains(offset) {
+                    return Err(From::from(format!(
+                        "column {} is specified more than once",
+                        statement.column_names[i]
+                    )));
+                }
+            }
+            table.validate_index(
+                &statement.index_name,
+                &column_offsets,
+                statement.unique,
+            )?;
+            db.commit(vec![Change::CreateIndex {
+                table_name: statement.table_name,
+                index_name: statement.index_name,
+                column_offsets,
+                unique: statement.unique,
+                kind: statement.kind,
+            }])?;
+            Ok(QueryOk::CreateIndex)
+        }
+        Statement::DropTable(statement) => {
+            db.check_unreferenced(&statement.table_name, "drop")?;
+            if db.tables.contains_key(&statement.table_name) {
+                db.commit(vec![Change::DropTable {
+                    table_name: statement.table_name,
+                }])?;
+                Ok(QueryOk::DropTable(1))
+            } else if statement.if_exists {
+                Ok(QueryOk::DropTable(0))
+            } else {
+                Err(From::from(format!(
                     "no table named {}",
                     statement.table_name
-                ))?;
-            let column_names = match statement.column_names {
-                ColumnClause::Star => table
-                    .schema
-                    .layout
-                    .iter()
-                    .map(|column| &column.name)
-                    .cloned()
+                )))
+            }
+        }
+        Statement::Truncate(statement) => {
+            db.check_unreferenced(&statement.table_name, "truncate")?;
+            let count = get_table(db, &statement.table_name)?.rows.len();
+            db.commit(vec![Change::Truncate {
+                table_name: statement.table_name,
+            }])?;
+            Ok(QueryOk::Truncate(count))
+        }
+        Statement::Delete(statement) => {
+            let table = get_table(db, &statement.table_name)?;
+            let filter =
+                where_subcommand(&table.schema, statement.where_clause)?;
+            Ok(QueryOk::Delete(
+                db.delete_where(&statement.table_name, &filter)?,
+            ))
+        }
+        Statement::Update(statement) => {
+            let table = get_table(db, &statement.table_name)?;
+            let mut assignments = Vec::new();
+            for assignment in statement.assignments {
+                let offset = column_names_to_offsets(
+                    &table.schema,
+                    &[assignment.column_name],
+                )?[0];
+                let value =
+                    ValueSubcommand::new(&table.schema, assignment.value)?;
+                assignments.push((offset, value));
+            }
+            let filter =
+                where_subcommand(&table.schema, statement.where_clause)?;
+            let rows =
+                table.updated_where(&assignments, filter.operationalize())?;
+            for row in &rows {
+                for (offset, _) in &assignments {
+                    let column = &table.schema.layout[*offset];
+                    db.validate_reference(column, &row.0[*offset])?;
+                }
+            }
+            let count = rows.len();
+            let table_name = &statement.table_name;
+            db.commit(
+                rows.into_iter()
+                    .map(|row| Change::Put {
+                        table_name: table_name.clone(),
+                        row,
+                    })
                     .collect(),
-                ColumnClause::Names(names) => names,
-            };
+            )?;
+            Ok(QueryOk::Update(count))
+        }
+        Statement::Analyze(statement) => match statement.table_name {
+            Some(table_name) => {
+                get_table(db, &table_name)?;
+                db.commit(vec![Change::Analyze { table_name }])?;
+                Ok(QueryOk::Analyze(1))
+            }
+            None => {
+                let changes: Vec<_> = db
+                    .tables
+                    .keys()
+                    .map(|table_name| Change::Analyze {
+                        table_name: table_name.clone(),
+                    })
+                    .collect();
+                let count = changes.len();
+                db.commit(changes)?;
+                Ok(QueryOk::Analyze(count))
+            }
+        },
+    }
+}
 
-            let where_subcommand = match statement.where_clause {
-                Some(where_clause) => WhereSubcommand::new_column_equality(
-                    &table.schema,
-                    where_clause.column_name,
-                    where_clause.value,
-                )?,
-                None => WhereSubcommand::new_unconditional(),
-            };
+// The REPL prints a SELECT's rows as they're pulled, a page at a time (so
+// as to hold on to no more than a page of them)
+const ROWS_PER_PAGE: usize = 100;
 
-            let command = SelectCommand::new_table_scan(
-                &table,
-                column_names,
-                where_subcommand,
-            );
-            Ok(QueryOk::Select(command.execute()))
+fn print_rows(columns: Vec<ResultColumn>, rows: Rows<'_, Vec<Chamber>>) {
+    let mut page = ResultSet {
+        columns,
+        rows: Vec::new(),
+    };
+    let mut printed = false;
+    for row in rows {
+        match row {
+            Ok(row) => page.rows.push(row),
+            Err(error) => {
+                if !page.rows.is_empty() {
+                    print!("{}", page.display());
+                }
+                println!("{}", error);
+                return;
+            }
         }
-        Statement::Insert(statement) => {
-            let table = db.tables.get_mut(&statement.table_name).ok_or(
-                format!("no table named {}", statement.table_name),
-            )?;
-            let pk_chamber = Chamber::Key(0);
-            let mut chambers = vec![pk_chamber];
-            chambers.extend(statement.values);
-            table.insert(Row(chambers))?;
-            Ok(QueryOk::Insert(1))
+        if page.rows.len() == ROWS_PER_PAGE {
+            print!("{}", page.display());
+            page.rows.clear();
+            printed = true;
         }
     }
+    if !printed || !page.rows.is_empty() {
+        print!("{}", page.display());
+    }
 }
 
 fn main() {
     println!("Welcome to Epilogue (pre-α)!");
-    let mut schema = TableSchema::new();
-    schema.add_column("title".to_owned(), ColumnType::String);
-    schema.add_column("year".to_owned(), ColumnType::Integer);
-    let books = Table::new(schema);
-    let mut db = Database::new();
-    db.add_table("books", books);
-    println!(
-        "There is a table 'books' with string column 'title' \
-         and integer column 'year'."
-    );
+    // (the database is kept in the file named on the command line, if any)
+    let mut db = match env::args().nth(1) {
+        Some(path) => match Database::open(&path) {
+            Ok(db) => db,
+            Err(err) => {
+                println!("Couldn't open {}: {}", path, err);
+                return;
+            }
+        },
+        None => Database::new(),
+    };
     // TODO: completion
     let mut line_reader = rustyline::Editor::<()>::new();
     loop {
@@ -111,16 +861,20 @@ fn main() {
             Ok(line) => {
                 line_reader.add_history_entry(line.as_ref());
                 match parse_statement(&line) {
+                    Ok((_remainder, Statement::Select(statement))) => {
+                        match select(&db, statement) {
+                            Ok((columns, rows)) => print_rows(columns, rows),
+                            Err(err) => println!("Err({:?})", err),
+                        }
+                    }
                     Ok((_remainder, statement)) => {
                         let query_result =
                             execute_statement(&mut db, statement);
-                        if let Ok(QueryOk::Select(selectrows)) = query_result {
-                            // TODO: use prettytable
-                            for selectrow in selectrows {
-                                println!("{:?}", selectrow);
+                        match query_result {
+                            Ok(QueryOk::Explain(plan)) => {
+                                print!("{}", plan.display())
                             }
-                        } else {
-                            println!("{:?}", query_result);
+                            _ => println!("{:?}", query_result),
                         }
                     }
                     Err(err) => {
@@ -145,4 +899,1129 @@ fn main() {
             }
         }
     }
+    if db.path.is_some() {
+        if let Err(err) = db.checkpoint() {
+            println!("Couldn't checkpoint the database: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execute(db: &mut Database, sql: &str) -> Result<(), Box<dyn Error>> {
+        let (_remainder, statement) =
+            parse_statement(sql).map_err(|e| format!("{:?}", e))?;
+        execute_statement(db, statement)?;
+        Ok(())
+    }
+
+    #[test]
+    fn concerning_creating_a_table() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Blindsight', 2006);")
+            .unwrap();
+        let books = &db.tables["books"];
+        let column_names = books
+            .schema
+            .layout
+            .iter()
+            .map(|column| column.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(column_names, vec!["pk", "title", "year"]);
+        assert_eq!(books.rows.len(), 1);
+    }
+
+    #[test]
+    fn concerning_creating_a_table_that_already_exists() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING);").unwrap();
+        assert!(
+            execute(&mut db, "CREATE TABLE books (year INTEGER);").is_err()
+        );
+    }
+
+    #[test]
+    fn concerning_creating_a_table_with_duplicate_column_names() {
+        let mut db = Database::new();
+        assert!(
+            execute(
+                &mut db,
+                "CREATE TABLE books (title STRING, title STRING);"
+            ).is_err()
+        );
+        assert!(!db.tables.contains_key("books"));
+    }
+
+    #[test]
+    fn concerning_dropping_a_table() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING);").unwrap();
+        execute(&mut db, "DROP TABLE books;").unwrap();
+        assert!(!db.tables.contains_key("books"));
+        assert!(execute(&mut db, "DROP TABLE books;").is_err());
+        execute(&mut db, "DROP TABLE IF EXISTS books;").unwrap();
+        execute(&mut db, "CREATE TABLE books (year INTEGER);").unwrap();
+    }
+
+    #[test]
+    fn concerning_truncating_a_table() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING);").unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Anathem');").unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Seveneves');").unwrap();
+        let (_, statement) = parse_statement("TRUNCATE books;").unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Truncate(count) => assert_eq!(count, 2),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(db.tables["books"].rows.is_empty());
+        execute(&mut db, "INSERT INTO books VALUES ('Reamde');").unwrap();
+        assert_eq!(db.tables["books"].rows.keys().collect::<Vec<_>>(), [&1]);
+    }
+
+    #[test]
+    fn concerning_deleting_rows() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Accelerando', 2005);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Glasshouse', 2006);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Halting State', 2007);")
+            .unwrap();
+        let (_, statement) =
+            parse_statement("DELETE FROM books WHERE year = 2006;").unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Delete(count) => assert_eq!(count, 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+        execute(&mut db, "INSERT INTO books VALUES ('Rule 34', 2011);")
+            .unwrap();
+        assert_eq!(
+            db.tables["books"].rows.keys().collect::<Vec<_>>(),
+            [&1, &3, &4]
+        );
+        execute(&mut db, "DELETE FROM books;").unwrap();
+        assert!(db.tables["books"].rows.is_empty());
+    }
+
+    #[test]
+    fn concerning_updating_rows() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Snow Crash', 1992);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Cryptonomicon', 1998);")
+            .unwrap();
+        let (_, statement) = parse_statement(
+            "UPDATE books SET year = 1999 WHERE title = 'Cryptonomicon';",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Update(count) => assert_eq!(count, 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(db.tables["books"].rows[&2].0[2], Chamber::Integer(1999));
+        assert!(execute(&mut db, "UPDATE books SET pk = 3;").is_err());
+        assert!(execute(&mut db, "UPDATE books SET author = 'NS';").is_err());
+        assert!(execute(&mut db, "UPDATE books SET year = 'soon';").is_err());
+
+        execute(&mut db, "UPDATE books SET year = (year - 1900) * 2 + pk;")
+            .unwrap();
+        assert_eq!(
+            db.tables["books"]
+                .rows
+                .values()
+                .map(|row| row.0[2].clone())
+                .collect::<Vec<_>>(),
+            vec![Chamber::Integer(185), Chamber::Integer(200)]
+        );
+        // (every value is computed from the row as it was)
+        execute(&mut db, "UPDATE books SET title = 'Anathem', year = year;")
+            .unwrap();
+        assert_eq!(db.tables["books"].rows[&1].0[2], Chamber::Integer(185));
+        let error = |db: &mut Database, sql| {
+            execute(db, sql).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = title + 1;"),
+            "can't apply + to String column title"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = year / (pk - pk);"),
+            "division by zero"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = copies;"),
+            "no column named copies"
+        );
+    }
+
+    #[test]
+    fn concerning_compound_where_clauses() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Dune', 1965);").unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Neuromancer', 1984);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Hyperion', 1989);")
+            .unwrap();
+        execute(
+            &mut db,
+            "DELETE FROM books WHERE NOT (year = 1965 OR title = 'Hyperion');",
+        ).unwrap();
+        assert_eq!(
+            db.tables["books"].rows.keys().collect::<Vec<_>>(),
+            [&1, &3]
+        );
+        // (no primary key is negative, but any can be compared to one)
+        execute(&mut db, "DELETE FROM books WHERE pk < -1 OR pk = -3;")
+            .unwrap();
+        assert_eq!(db.tables["books"].rows.len(), 2);
+        execute(&mut db, "DELETE FROM books WHERE pk > -1 AND pk <> -5;")
+            .unwrap();
+        assert!(db.tables["books"].rows.is_empty());
+    }
+
+    #[test]
+    fn concerning_aggregate_queries() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Emma', 1815);").unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Persuasion', 1817);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Middlemarch', 1871);")
+            .unwrap();
+        let (_, statement) = parse_statement(
+            "SELECT COUNT(*), MIN(year), MAX(title) FROM books \
+             WHERE year < 1850;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![
+                    Chamber::Integer(2),
+                    Chamber::Integer(1815),
+                    Chamber::String("Persuasion".to_owned()),
+                ]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(
+            execute(&mut db, "SELECT title, COUNT(*) FROM books;").is_err()
+        );
+    }
+
+    #[test]
+    fn concerning_grouped_queries() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        for &(title, year) in &[
+            ("The Selfish Gene", 1976),
+            ("Godel, Escher, Bach", 1979),
+            ("The Extended Phenotype", 1982),
+            ("Metamagical Themas", 1985),
+            ("The Blind Watchmaker", 1986),
+            ("Climbing Mount Improbable", 1996),
+            ("Unweaving the Rainbow", 1998),
+        ] {
+            execute(
+                &mut db,
+                &format!("INSERT INTO books VALUES ('{}', {});", title, year),
+            ).unwrap();
+        }
+        execute(&mut db, "UPDATE books SET year = 1986 WHERE year = 1985;")
+            .unwrap();
+        let (_, statement) = parse_statement(
+            "SELECT year, COUNT(*) FROM books WHERE year > 1980 \
+             GROUP BY year HAVING COUNT(*) > 1;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![Chamber::Integer(1986), Chamber::Integer(2)]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn concerning_joins() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE authors (name STRING);").unwrap();
+        execute(&mut db, "CREATE TABLE books (title STRING, author INTEGER);")
+            .unwrap();
+        for name in &["Richard Dawkins", "Douglas Hofstadter", "Carl Sagan"] {
+            execute(
+                &mut db,
+                &format!("INSERT INTO authors VALUES ('{}');", name),
+            ).unwrap();
+        }
+        for &(title, author) in &[
+            ("The Selfish Gene", 1),
+            ("Godel, Escher, Bach", 2),
+            ("The Blind Watchmaker", 1),
+            ("Flatland", 4),
+        ] {
+            execute(
+                &mut db,
+                &format!(
+                    "INSERT INTO books VALUES ('{}', {});",
+                    title, author
+                ),
+            ).unwrap();
+        }
+        let string = |s: &str| Chamber::String(s.to_owned());
+
+        let (_, statement) = parse_statement(
+            "SELECT b.title FROM books b JOIN authors a ON b.author = a.pk \
+             WHERE a.name = 'Richard Dawkins' ORDER BY title;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![
+                    vec![string("The Blind Watchmaker")],
+                    vec![string("The Selfish Gene")],
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let (_, statement) = parse_statement(
+            "SELECT name, title FROM books \
+             RIGHT JOIN authors ON author = authors.pk WHERE pk > 2;",
+        ).unwrap();
+        let error = execute_statement(&mut db, statement).err().unwrap();
+        assert_eq!(error.to_string(), "column reference pk is ambiguous");
+
+        let (_, statement) = parse_statement(
+            "SELECT name, title FROM books \
+             RIGHT JOIN authors ON author = authors.pk \
+             WHERE authors.pk > 2;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![string("Carl Sagan"), Chamber::Null]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let (_, statement) = parse_statement(
+            "SELECT * FROM authors CROSS JOIN books LIMIT 1;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![
+                    Chamber::Key(1),
+                    string("Richard Dawkins"),
+                    Chamber::Key(1),
+                    string("The Selfish Gene"),
+                    Chamber::Integer(1),
+                ]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn concerning_nulls() {
+        let mut db = Database::new();
+        execute(&mut db, "CREATE TABLE books (title STRING, year INTEGER);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Beowulf', NULL);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Dune', 1965);").unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Emma', 1815);").unwrap();
+        execute(&mut db, "UPDATE books SET year = NULL WHERE year < 1900;")
+            .unwrap();
+
+        let (_, statement) = parse_statement(
+            "SELECT title FROM books WHERE year IS NULL OR year <> 1965;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![
+                    vec![Chamber::String("Beowulf".to_owned())],
+                    vec![Chamber::String("Emma".to_owned())],
+                ]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        // rows for which the condition is UNKNOWN aren't deleted
+        let (_, statement) =
+            parse_statement("DELETE FROM books WHERE NOT year = 1965;")
+                .unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Delete(count) => assert_eq!(count, 0),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let (_, statement) =
+            parse_statement("SELECT COUNT(*), COUNT(year) FROM books;")
+                .unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![Chamber::Integer(3), Chamber::Integer(1)]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn concerning_column_constraints() {
+        let mut db = Database::new();
+        execute(
+            &mut db,
+            "CREATE TABLE books (title STRING NOT NULL UNIQUE, \
+             year INTEGER DEFAULT 2018 CHECK (year > 1400) \
+             CHECK (year < 3000));",
+        ).unwrap();
+        execute(&mut db, "INSERT INTO books (title) VALUES ('Circe');")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('Middlemarch', 1871);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO books VALUES ('The Iliad', NULL);")
+            .unwrap();
+        assert_eq!(
+            db.tables["books"].rows[&1].0[2],
+            Chamber::Integer(2018)
+        );
+
+        let error = |db: &mut Database, sql| {
+            execute(db, sql).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&mut db, "INSERT INTO books (year) VALUES (1999);"),
+            "NULL value in column title violates NOT NULL constraint"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Circe', 2019);"),
+            "duplicate value Circe in column title violates UNIQUE constraint"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Beowulf', 1000);"),
+            "row violates CHECK constraint on column year"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = 3001 WHERE year = 1871;"),
+            "row violates CHECK constraint on column year"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books (pk) VALUES (7);"),
+            "can't insert into primary key column pk"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Emma', 1815, 1);"),
+            "INSERT has more values than columns"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Emma');"),
+            "INSERT has fewer values than columns"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO books (title, year) VALUES ('Emma');"),
+            "INSERT has fewer values than columns"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "CREATE TABLE films (year INTEGER DEFAULT 'soon');"
+            ),
+            "DEFAULT for Integer column year can't be String value soon"
+        );
+    }
+
+    #[test]
+    fn concerning_foreign_keys() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE authors (name STRING);",
+            "CREATE TABLE books (title STRING, \
+             author INTEGER REFERENCES authors(pk) ON DELETE CASCADE);",
+            "CREATE TABLE reviews (stars INTEGER, \
+             book INTEGER REFERENCES books ON DELETE SET NULL);",
+            "CREATE TABLE loans (book INTEGER REFERENCES books(pk));",
+            "INSERT INTO authors VALUES ('Ursula K. Le Guin');",
+            "INSERT INTO authors VALUES ('Octavia E. Butler');",
+            "INSERT INTO books VALUES ('The Dispossessed', 1);",
+            "INSERT INTO books VALUES ('Kindred', 2);",
+            "INSERT INTO books VALUES ('The Lathe of Heaven', 1);",
+            "INSERT INTO books VALUES ('Anonymous', NULL);",
+            "INSERT INTO reviews VALUES (5, 1);",
+            "INSERT INTO reviews VALUES (4, 2);",
+            "INSERT INTO loans VALUES (3);",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let error = |db: &mut Database, sql| {
+            execute(db, sql).unwrap_err().to_string()
+        };
+        let pks = |db: &Database, table_name: &str| {
+            db.tables[table_name].rows.keys().cloned().collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Dawn', 3);"),
+            "value 3 in column author violates FOREIGN KEY constraint \
+             (no such row in authors)"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE books SET author = 7 WHERE pk = 2;"),
+            "value 7 in column author violates FOREIGN KEY constraint \
+             (no such row in authors)"
+        );
+        // (an update that doesn't match any rows doesn't violate anything)
+        execute(&mut db, "UPDATE books SET author = 7 WHERE pk = 9;").unwrap();
+
+        // The Lathe of Heaven is on loan, so Le Guin's books can't cascade
+        assert_eq!(
+            error(&mut db, "DELETE FROM authors WHERE pk = 1;"),
+            "deleting from books violates FOREIGN KEY constraint on column \
+             loans.book"
+        );
+        assert_eq!(pks(&db, "authors"), vec![1, 2]);
+        assert_eq!(pks(&db, "books"), vec![1, 2, 3, 4]);
+
+        execute(&mut db, "DELETE FROM loans;").unwrap();
+        let (_, statement) =
+            parse_statement("DELETE FROM authors WHERE pk = 1;").unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Delete(count) => assert_eq!(count, 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(pks(&db, "authors"), vec![2]);
+        assert_eq!(pks(&db, "books"), vec![2, 4]);
+        assert_eq!(db.tables["reviews"].rows[&1].0[2], Chamber::Null);
+        assert_eq!(db.tables["reviews"].rows[&2].0[2], Chamber::Integer(2));
+
+        assert_eq!(
+            error(&mut db, "DROP TABLE authors;"),
+            "can't drop table authors: column books.author references it"
+        );
+        assert_eq!(
+            error(&mut db, "TRUNCATE books;"),
+            "can't truncate table books: column loans.book references it"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "CREATE TABLE sequels (book INTEGER REFERENCES movies);"
+            ),
+            "no table named movies"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "CREATE TABLE sequels \
+                 (title STRING REFERENCES books(title));"
+            ),
+            "foreign key column title can only reference a primary key, \
+             not books.title"
+        );
+        assert_eq!(
+            error(
+                &mut db,
+                "CREATE TABLE sequels (book INTEGER NOT NULL \
+                 REFERENCES books ON DELETE SET NULL);"
+            ),
+            "column book can't be both NOT NULL and ON DELETE SET NULL"
+        );
+    }
+
+    #[test]
+    fn concerning_self_referencing_foreign_keys() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE people (name STRING, \
+             parent INTEGER REFERENCES people ON DELETE CASCADE);",
+            "INSERT INTO people VALUES ('Eve', NULL);",
+            "INSERT INTO people VALUES ('Cain', 1);",
+            "INSERT INTO people VALUES ('Enoch', 2);",
+            "INSERT INTO people VALUES ('Seth', 1);",
+            "INSERT INTO people VALUES ('Lilith', NULL);",
+            "DELETE FROM people WHERE name = 'Eve';",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        assert_eq!(
+            db.tables["people"].rows.keys().cloned().collect::<Vec<_>>(),
+            vec![5]
+        );
+        execute(&mut db, "DROP TABLE people;").unwrap();
+    }
+
+    #[test]
+    fn concerning_explain() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE authors (name STRING);",
+            "CREATE TABLE books (title STRING, year INTEGER, \
+             author INTEGER REFERENCES authors);",
+            "CREATE INDEX years ON books (year);",
+            "INSERT INTO authors VALUES ('Ursula K. Le Guin');",
+            "INSERT INTO authors VALUES ('Octavia E. Butler');",
+            "INSERT INTO books VALUES ('The Dispossessed', 1974, 1);",
+            "INSERT INTO books VALUES ('Kindred', 1979, 2);",
+            "INSERT INTO books VALUES ('Parable of the Sower', 1993, 2);",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let explain = |db: &mut Database, sql| {
+            let (_, statement) = parse_statement(sql).unwrap();
+            match execute_statement(db, statement).unwrap() {
+                QueryOk::Explain(plan) => plan,
+                other => panic!("unexpected result {:?}", other),
+            }
+        };
+
+        let plan = explain(
+            &mut db,
+            "EXPLAIN SELECT title FROM books WHERE year > 1975 \
+             AND title <> 'Kindred' ORDER BY year DESC LIMIT 1;",
+        );
+        assert_eq!(
+            plan.display(),
+            "\
++--------------------------------------------------------+
+| plan                                                   |
++--------------------------------------------------------+
+| Project title                                          |
+| -> Limit 1                                             |
+|    -> Sort year DESC                                   |
+|       -> Filter year > 1975 AND title <> 'Kindred'     |
+|          -> Index Scan books USING years (year > 1975) |
++--------------------------------------------------------+
+"
+        );
+
+        let plan = explain(
+            &mut db,
+            "EXPLAIN SELECT a.name, b.title FROM authors AS a \
+             LEFT JOIN books AS b ON b.author = a.pk WHERE b.pk = 3;",
+        );
+        assert_eq!(
+            plan.display(),
+            "\
++-------------------------------------------+
+| plan                                      |
++-------------------------------------------+
+| Project a.name, b.title                   |
+| -> Filter b.pk = 3                        |
+|    -> Hash Left Join b ON b.author = a.pk |
+|       -> Table Scan a                     |
+|       -> Table Scan b                     |
++-------------------------------------------+
+"
+        );
+
+        let plan = explain(
+            &mut db,
+            "EXPLAIN SELECT author, COUNT(*) FROM books GROUP BY author \
+             HAVING COUNT(*) > 1;",
+        );
+        assert_eq!(
+            plan.display(),
+            "\
++------------------------------------------+
+| plan                                     |
++------------------------------------------+
+| Project author, COUNT(*)                 |
+| -> Filter COUNT(*) > 1                   |
+|    -> Aggregate COUNT(*) GROUP BY author |
+|       -> Project author, pk              |
+|          -> Table Scan books             |
++------------------------------------------+
+"
+        );
+
+        // (following the first children down)
+        let actual_rows = |plan: &PlanNode| {
+            let mut rows = Vec::new();
+            let mut node = Some(plan);
+            while let Some(step) = node {
+                rows.push(step.actual.map(|(rows, _)| rows));
+                node = step.children.first();
+            }
+            rows
+        };
+        // an unsorted scan stops as soon as it's found enough rows
+        let plan = explain(
+            &mut db,
+            "EXPLAIN ANALYZE SELECT title FROM books WHERE year > 1975 \
+             LIMIT 1;",
+        );
+        assert_eq!(
+            actual_rows(&plan),
+            vec![Some(1), Some(1), Some(1), Some(1)]
+        );
+        let plan = explain(
+            &mut db,
+            "EXPLAIN ANALYZE SELECT author, COUNT(*) FROM books \
+             GROUP BY author HAVING COUNT(*) > 1;",
+        );
+        assert_eq!(
+            actual_rows(&plan),
+            vec![Some(1), Some(1), Some(2), Some(3), Some(3)]
+        );
+        let plan = explain(
+            &mut db,
+            "EXPLAIN ANALYZE SELECT * FROM authors AS a \
+             CROSS JOIN books AS b ORDER BY b.year;",
+        );
+        assert_eq!(
+            actual_rows(&plan),
+            vec![Some(6), Some(6), Some(6), Some(2)]
+        );
+        // (the rows of the right side of a join aren't counted separately)
+        assert_eq!(plan.children[0].children[0].children[1].actual, None);
+        assert!(plan.display().contains("| rows | time "));
+    }
+
+    #[test]
+    fn concerning_analyze() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE authors (name STRING);",
+            "CREATE TABLE books (title STRING, year INTEGER, \
+             author INTEGER REFERENCES authors);",
+            "CREATE INDEX years ON books (year);",
+            "INSERT INTO authors VALUES ('Ursula K. Le Guin');",
+            "INSERT INTO authors VALUES ('Octavia E. Butler');",
+            "INSERT INTO books VALUES ('The Dispossessed', 1974, 1);",
+            "INSERT INTO books VALUES ('Kindred', 1979, 2);",
+            "INSERT INTO books VALUES ('Parable of the Sower', 1993, 2);",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let query = |db: &mut Database, sql| {
+            let (_, statement) = parse_statement(sql).unwrap();
+            match execute_statement(db, statement).unwrap() {
+                QueryOk::Explain(plan) => plan.display(),
+                other => format!("{:?}", other),
+            }
+        };
+        let by_year = "EXPLAIN SELECT title FROM books WHERE year = 1979;";
+        let joined = "EXPLAIN SELECT b.title, a.name FROM books AS b \
+                      INNER JOIN authors AS a ON b.author = a.pk;";
+        assert!(query(&mut db, by_year).contains("Index Scan books"));
+        assert!(
+            query(&mut db, joined)
+                .contains("Hash Inner Join a ON b.author = a.pk")
+        );
+
+        assert_eq!(query(&mut db, "ANALYZE;"), "Analyze(2)");
+        let statistics = db.tables["books"].statistics.as_ref().unwrap();
+        assert_eq!(statistics.row_count, 3);
+        // (with so few rows, reading them all beats going through an index)
+        assert!(query(&mut db, by_year).contains("Table Scan books"));
+        assert_eq!(
+            query(&mut db, joined),
+            "\
++-----------------------------------------+
+| plan                                    |
++-----------------------------------------+
+| Project b.title, a.name                 |
+| -> Hash Inner Join b ON b.author = a.pk |
+|    -> Table Scan a                      |
+|    -> Table Scan b                      |
++-----------------------------------------+
+"
+        );
+
+        assert_eq!(query(&mut db, "ANALYZE books;"), "Analyze(1)");
+        let (_, statement) = parse_statement("ANALYZE shelves;").unwrap();
+        assert_eq!(
+            execute_statement(&mut db, statement)
+                .unwrap_err()
+                .to_string(),
+            "no table named shelves"
+        );
+    }
+
+    #[test]
+    fn concerning_indices() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE books (title STRING, year INTEGER);",
+            "INSERT INTO books VALUES ('Kindred', 1979);",
+            "INSERT INTO books VALUES ('Dawn', 1987);",
+            "CREATE INDEX years ON books (year);",
+            "CREATE UNIQUE INDEX titles ON books (title);",
+            "CREATE INDEX hashes ON books USING HASH (year);",
+            "INSERT INTO books VALUES ('Imago', 1989);",
+            "INSERT INTO books VALUES ('Fledgling', 2005);",
+            "UPDATE books SET year = 1988 WHERE title = 'Imago';",
+            "DELETE FROM books WHERE year = 1979;",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let (_, statement) = parse_statement(
+            "SELECT title FROM books WHERE year >= 1988 AND year < 2000;",
+        ).unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![Chamber::String("Imago".to_owned())]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let (_, statement) =
+            parse_statement("SELECT title FROM books WHERE year = 2005;")
+                .unwrap();
+        match execute_statement(&mut db, statement).unwrap() {
+            QueryOk::Select(result_set) => assert_eq!(
+                result_set.rows,
+                vec![vec![Chamber::String("Fledgling".to_owned())]]
+            ),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let error = |db: &mut Database, sql| {
+            execute(db, sql).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(&mut db, "INSERT INTO books VALUES ('Dawn', 2000);"),
+            "duplicate key (Dawn) violates UNIQUE index titles"
+        );
+        // (a key that an UPDATE moves one row off is free for another)
+        execute(&mut db, "CREATE UNIQUE INDEX eras ON books (year);").unwrap();
+        execute(&mut db, "UPDATE books SET year = year + 1;").unwrap();
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = 1989 WHERE pk = 2;"),
+            "duplicate key (1989) violates UNIQUE index eras"
+        );
+        assert_eq!(
+            error(&mut db, "UPDATE books SET year = year - year;"),
+            "duplicate key (0) violates UNIQUE index eras"
+        );
+        assert_eq!(
+            error(&mut db, "CREATE INDEX years ON books (title);"),
+            "index years already exists"
+        );
+        assert_eq!(
+            error(&mut db, "CREATE INDEX pairs ON books (year, year);"),
+            "column year is specified more than once"
+        );
+        assert_eq!(
+            error(&mut db, "CREATE INDEX authors ON books (author);"),
+            "no column named author"
+        );
+    }
+
+    #[test]
+    fn concerning_streaming_results() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE books (title STRING, copies INTEGER);",
+            "INSERT INTO books VALUES ('Kindred', 9223372036854775807);",
+            "INSERT INTO books VALUES ('Dawn', 1);",
+            "INSERT INTO books VALUES ('Imago', 2);",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let stream = |db, sql| match parse_statement(sql).unwrap().1 {
+            Statement::Select(statement) => select(db, statement).unwrap(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        let (_, mut rows) = stream(&db, "SELECT title FROM books;");
+        assert_eq!(
+            rows.next().unwrap().unwrap(),
+            vec![Chamber::String("Kindred".to_owned())]
+        );
+        // (the rest of the rows are never produced)
+        drop(rows);
+
+        // Errors come with the rows, rather than with the query
+        let (columns, mut rows) =
+            stream(&db, "SELECT SUM(copies) FROM books;");
+        assert_eq!(columns[0].name, "SUM(copies)");
+        assert_eq!(
+            rows.next().unwrap().unwrap_err().to_string(),
+            "integer overflow in SUM"
+        );
+        assert!(rows.next().is_none());
+    }
+
+    #[test]
+    fn concerning_result_sets() {
+        let mut db = Database::new();
+        for sql in &[
+            "CREATE TABLE books (title STRING, copies INTEGER);",
+            "INSERT INTO books VALUES ('Kindred', 9223372036854775807);",
+            "INSERT INTO books VALUES ('Dawn', 1);",
+            "INSERT INTO books VALUES ('Imago', 1);",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let select = |db: &mut Database, sql| {
+            let (_, statement) = parse_statement(sql).unwrap();
+            match execute_statement(db, statement) {
+                Ok(QueryOk::Select(result_set)) => Ok(result_set),
+                Ok(other) => panic!("unexpected result {:?}", other),
+                Err(error) => Err(error.to_string()),
+            }
+        };
+
+        let before = select(&mut db, "SELECT * FROM books LIMIT 1;").unwrap();
+        assert_eq!(
+            before.columns,
+            vec![
+                ResultColumn {
+                    name: "pk".to_owned(),
+                    column_type: ColumnType::Key,
+                },
+                ResultColumn {
+                    name: "title".to_owned(),
+                    column_type: ColumnType::String,
+                },
+                ResultColumn {
+                    name: "copies".to_owned(),
+                    column_type: ColumnType::Integer,
+                },
+            ]
+        );
+        // (a result set can be kept while the database changes)
+        execute(&mut db, "UPDATE books SET title = 'Wild Seed';").unwrap();
+        let after = select(&mut db, "SELECT * FROM books LIMIT 1;").unwrap();
+        assert_ne!(before, after);
+        assert_eq!(
+            before.rows,
+            vec![vec![
+                Chamber::Key(1),
+                Chamber::String("Kindred".to_owned()),
+                Chamber::Integer(9223372036854775807),
+            ]]
+        );
+
+        let grouped = select(
+            &mut db,
+            "SELECT copies, COUNT(*), MAX(title) FROM books \
+             WHERE copies < 2 GROUP BY copies;",
+        ).unwrap();
+        assert_eq!(
+            grouped
+                .columns
+                .iter()
+                .map(|column| (column.name.as_str(), column.column_type))
+                .collect::<Vec<_>>(),
+            vec![
+                ("copies", ColumnType::Integer),
+                ("COUNT(*)", ColumnType::Integer),
+                ("MAX(title)", ColumnType::String),
+            ]
+        );
+        assert_eq!(
+            grouped.display(),
+            "+--------+----------+------------+\n\
+             | copies | COUNT(*) | MAX(title) |\n\
+             +--------+----------+------------+\n\
+             | 1      | 2        | Wild Seed  |\n\
+             +--------+----------+------------+\n"
+        );
+        assert_eq!(
+            select(&mut db, "SELECT SUM(copies) FROM books;").unwrap_err(),
+            "integer overflow in SUM"
+        );
+    }
+
+    #[test]
+    fn concerning_persistence() {
+        let path = env::temp_dir()
+            .join(format!("epilogue-persistence-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(log_path(&path));
+        let select = |db: &mut Database, sql| {
+            let (_, statement) = parse_statement(sql).unwrap();
+            match execute_statement(db, statement).unwrap() {
+                QueryOk::Select(result_set) => result_set,
+                other => panic!("unexpected result {:?}", other),
+            }
+        };
+
+        let mut db = Database::open(&path).unwrap();
+        assert!(db.tables.is_empty());
+        for sql in &[
+            "CREATE TABLE authors (name STRING NOT NULL UNIQUE);",
+            "CREATE TABLE books (title STRING DEFAULT 'Untitled', \
+             year INTEGER CHECK (year > 1800), \
+             author INTEGER REFERENCES authors (pk) ON DELETE CASCADE);",
+            "CREATE INDEX years ON books (year);",
+            "INSERT INTO authors VALUES ('Octavia Butler');",
+            "INSERT INTO authors VALUES ('Ursula K. Le Guin');",
+            "INSERT INTO books VALUES ('Kindred', 1979, 1);",
+            "INSERT INTO books VALUES ('The Dispossessed', 1974, 2);",
+            "INSERT INTO books (year, author) VALUES (1987, 1);",
+            "DELETE FROM books WHERE year = 1987;",
+            "ANALYZE;",
+        ] {
+            execute(&mut db, sql).unwrap();
+        }
+        let query = "SELECT title, name FROM books \
+                     JOIN authors ON author = authors.pk ORDER BY year;";
+        let before = select(&mut db, query);
+        db.checkpoint().unwrap();
+        assert_eq!(fs::metadata(log_path(&path)).unwrap().len(), 0);
+        drop(db);
+
+        let mut db = Database::open(&path).unwrap();
+        assert_eq!(select(&mut db, query), before);
+        assert!(db.tables["books"].statistics.is_some());
+        let error = |db: &mut Database, sql| {
+            execute(db, sql).unwrap_err().to_string()
+        };
+        assert_eq!(
+            error(
+                &mut db,
+                "INSERT INTO books (title, year, author) \
+                 VALUES ('Frankenstein', 1, 1);"
+            ),
+            "row violates CHECK constraint on column year"
+        );
+        assert_eq!(
+            error(&mut db, "INSERT INTO authors VALUES ('Octavia Butler');"),
+            "duplicate value Octavia Butler in column name violates UNIQUE \
+             constraint"
+        );
+        // (statements that fail are checked before anything is logged, so
+        // they don't keep the database from being opened again)
+        assert_eq!(
+            error(&mut db, "CREATE INDEX years ON books (title);"),
+            "index years already exists"
+        );
+        // (primary keys aren't reused, even after a reopening)
+        execute(&mut db, "INSERT INTO books VALUES ('Dawn', 1987, 1);")
+            .unwrap();
+        assert_eq!(
+            select(&mut db, "SELECT pk FROM books WHERE year = 1987;").rows,
+            vec![vec![Chamber::Key(4)]]
+        );
+        execute(&mut db, "DELETE FROM authors WHERE pk = 1;").unwrap();
+        assert_eq!(
+            select(&mut db, "SELECT title FROM books;").rows,
+            vec![vec![Chamber::String("The Dispossessed".to_owned())]]
+        );
+        // (which were logged, and so survive without a checkpoint)
+        drop(db);
+        let mut db = Database::open(&path).unwrap();
+        assert_eq!(
+            select(&mut db, "SELECT title FROM books;").rows,
+            vec![vec![Chamber::String("The Dispossessed".to_owned())]]
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(log_path(&path)).unwrap();
+
+        assert_eq!(
+            Database::new().checkpoint().unwrap_err().to_string(),
+            "database is only in memory; there's nothing to checkpoint"
+        );
+    }
+
+    #[test]
+    fn concerning_crash_recovery() {
+        let directory = env::temp_dir()
+            .join(format!("epilogue-recovery-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir(&directory).unwrap();
+        let path = directory.join("database");
+        let contents = |db: &mut Database| {
+            let mut table_names =
+                db.tables.keys().cloned().collect::<Vec<_>>();
+            table_names.sort();
+            table_names
+                .iter()
+                .map(|table_name| {
+                    let sql = format!("SELECT * FROM {};", table_name);
+                    let (_, statement) = parse_statement(&sql).unwrap();
+                    match execute_statement(db, statement).unwrap() {
+                        QueryOk::Select(result_set) => result_set,
+                        other => panic!("unexpected result {:?}", other),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut db = Database::open(&path).unwrap();
+        execute(&mut db, "CREATE TABLE authors (name STRING UNIQUE);")
+            .unwrap();
+        execute(&mut db, "INSERT INTO authors VALUES ('Octavia Butler');")
+            .unwrap();
+        db.checkpoint().unwrap();
+        let file = fs::read(&path).unwrap();
+        // what the database holds after each statement since the
+        // checkpoint, and how long the log is then
+        let mut states = vec![(contents(&mut db), 0)];
+        for sql in &[
+            "CREATE TABLE books (title STRING, \
+             author INTEGER REFERENCES authors ON DELETE SET NULL, \
+             editor INTEGER REFERENCES authors ON DELETE SET NULL);",
+            "INSERT INTO authors VALUES ('Ursula K. Le Guin');",
+            "INSERT INTO books VALUES ('Kindred', 1, 1);",
+            "INSERT INTO books VALUES ('The Dispossessed', 2, 1);",
+            "CREATE UNIQUE INDEX titles ON books (title);",
+            "UPDATE books SET title = 'Dawn' WHERE author = 1;",
+            "DELETE FROM authors WHERE pk = 1;",
+            "ANALYZE books;",
+            "CREATE TABLE shelves (label STRING);",
+            "INSERT INTO shelves VALUES ('fiction');",
+            "TRUNCATE shelves;",
+            "DROP TABLE shelves;",
+        ] {
+            execute(&mut db, sql).unwrap();
+            let length = fs::metadata(log_path(&path)).unwrap().len();
+            states.push((contents(&mut db), length));
+        }
+        drop(db);
+        let log = fs::read(log_path(&path)).unwrap();
+        assert_eq!(log.len() as u64, states.last().unwrap().1);
+
+        // (a crash can leave any prefix of the log behind)
+        let crashed = directory.join("crashed");
+        for end in 0..=log.len() {
+            fs::write(&crashed, &file).unwrap();
+            fs::write(log_path(&crashed), &log[..end]).unwrap();
+            let mut db = Database::open(&crashed).unwrap();
+            let (expected, _) = states
+                .iter()
+                .rev()
+                .find(|(_, length)| *length <= end as u64)
+                .unwrap();
+            assert_eq!(&contents(&mut db), expected, "log cut at {}", end);
+
+            // (and what's appended after that is kept)
+            execute(&mut db, "INSERT INTO authors VALUES ('N. K. Jemisin');")
+                .unwrap();
+            let after = contents(&mut db);
+            drop(db);
+            let mut db = Database::open(&crashed).unwrap();
+            assert_eq!(contents(&mut db), after, "log cut at {}", end);
+        }
+
+        // (crashing after a checkpoint is written, but before the log is
+        // emptied, doesn't replay anything twice)
+        let mut db = Database::open(&path).unwrap();
+        let expected = contents(&mut db);
+        let file = storage::write_tables(
+            &db.tables,
+            db.log.as_ref().unwrap().last_sequence_number(),
+        );
+        storage::write_file(&path, &file).unwrap();
+        drop(db);
+        let mut db = Database::open(&path).unwrap();
+        assert_eq!(contents(&mut db), expected);
+        drop(db);
+        fs::remove_dir_all(&directory).unwrap();
+    }
 }
@@ -1,210 +1,3935 @@
-use std::collections::btree_map;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::ops::Bound;
+use std::time::Duration;
 
-use crate::table::{Chamber, Row, Table, TableSchema};
+use prettytable;
 
-#[derive(Debug)]
-crate struct WhereSubcommand {
-    // XXX TODO: starting out with supporting just a single `column = value`
-    // condition, but should eventually expand to conj-/dis-junctions, &c.
-    //
+use crate::executor::{
+    Aggregate, BoxedOperator, Filter, Instruments, Join, Limit, Map,
+    ResultColumn, Rows, Scan, Sort,
+};
+use crate::sql::{
+    AggregateFunction, ArithmeticOperator, ColumnExpression,
+    ComparisonOperator, Condition, JoinKind, SortKey, SortOrder,
+    ValueExpression,
+};
+use crate::table::{
+    Chamber, ColumnType, IndexEntries, IndexKey, Row, Table, TableSchema,
+    TableStatistics,
+};
+
+#[derive(Clone, Debug)]
+crate enum WhereSubcommand {
+    Unconditional,
     // Use a column offset (don't want to overload the word "index") instead of
     // a name so that we can operate on Row directly rather than looking up the
     // name
-    crate column_offset: usize,
-    crate value: Chamber,
-    // XXX this is a BAD design decision and I should FEEL BAD (and rework it
-    // later)
-    crate unconditional: bool,
+    Comparison {
+        column_offset: usize,
+        operator: ComparisonOperator,
+        value: Chamber,
+    },
+    ColumnComparison {
+        left_offset: usize,
+        operator: ComparisonOperator,
+        right_offset: usize,
+    },
+    And(Box<WhereSubcommand>, Box<WhereSubcommand>),
+    Or(Box<WhereSubcommand>, Box<WhereSubcommand>),
+    Not(Box<WhereSubcommand>),
+    IsNull {
+        column_offset: usize,
+    },
+}
+
+/// Something whose chambers can be looked up by column offset: either a
+/// table's row, or a row stitched together from several tables' rows by a
+/// join.
+crate trait Tuple {
+    fn chamber(&self, offset: usize) -> &Chamber;
+}
+
+impl Tuple for Row {
+    fn chamber(&self, offset: usize) -> &Chamber {
+        &self.0[offset]
+    }
 }
 
+impl Tuple for [&Chamber] {
+    fn chamber(&self, offset: usize) -> &Chamber {
+        self[offset]
+    }
+}
+
+impl Tuple for [Chamber] {
+    fn chamber(&self, offset: usize) -> &Chamber {
+        &self[offset]
+    }
+}
+
+/// Offsets are returned in the order the names were given (duplicates
+/// included), so that they can double as a projection.
 crate fn column_names_to_offsets(
     schema: &TableSchema,
     column_names: &[String],
 ) -> Result<Vec<usize>, Box<dyn Error>> {
-    let offsets = schema
-        .layout
+    column_names
         .iter()
-        .enumerate()
-        .filter_map(|(i, column)| {
-            if column_names.contains(&column.name) {
-                Some(i)
-            } else {
-                None
-            }
+        .map(|column_name| {
+            schema
+                .layout
+                .iter()
+                .position(|column| &column.name == column_name)
+                .ok_or(From::from(format!("no column named {}", column_name)))
         })
-        .collect::<Vec<_>>();
-    if offsets.len() == column_names.len() {
-        Ok(offsets)
-    } else {
-        // TODO: more precise error message
-        Err(From::from("some column names not found"))
+        .collect()
+}
+
+crate type Resolution = Result<(usize, ColumnType), Box<dyn Error>>;
+
+/// Maps a column expression to the offset (and type) of its value in the rows
+/// that a condition will be evaluated against.
+crate type Resolver<'r> = dyn FnMut(&ColumnExpression) -> Resolution + 'r;
+
+crate fn resolve_table_column(
+    schema: &TableSchema,
+    column: &ColumnExpression,
+) -> Resolution {
+    match column {
+        ColumnExpression::Column(column_name) => {
+            let offset =
+                column_names_to_offsets(schema, &[column_name.clone()])?[0];
+            Ok((offset, schema.layout[offset].column_type))
+        }
+        ColumnExpression::Aggregate { .. } => Err(From::from(format!(
+            "aggregate {} isn't allowed here",
+            column.display()
+        ))),
     }
 }
 
 impl WhereSubcommand {
+    crate fn new(
+        schema: &TableSchema,
+        condition: Condition,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::compile(condition, &mut |column| {
+            resolve_table_column(schema, column)
+        })
+    }
+
+    crate fn compile(
+        condition: Condition,
+        resolve: &mut Resolver<'_>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(match condition {
+            Condition::Comparison {
+                column,
+                operator,
+                value,
+            } => {
+                let (column_offset, column_type) = resolve(&column)?;
+                let value = match (column_type, value) {
+                    // the parser can't tell keys from integers, but we can
+                    // (and a negative integer, which no key equals, is
+                    // compared by value, as less than every key)
+                    (ColumnType::Key, Chamber::Integer(i)) => {
+                        if i >= 0 {
+                            Chamber::Key(i as usize)
+                        } else {
+                            Chamber::Integer(i)
+                        }
+                    }
+                    (column_type, value) => match value.column_type() {
+                        Some(value_type) if value_type != column_type => {
+                            return Err(From::from(format!(
+                                "can't compare {:?} column {} to {:?} \
+                                 value {}",
+                                column_type,
+                                column.display(),
+                                value_type,
+                                value.display()
+                            )));
+                        }
+                        // (NULL can be compared with any column, if never
+                        // truthfully)
+                        _ => value,
+                    },
+                };
+                WhereSubcommand::Comparison {
+                    column_offset,
+                    operator,
+                    value,
+                }
+            }
+            Condition::ColumnComparison {
+                left,
+                operator,
+                right,
+            } => {
+                let (left_offset, left_type) = resolve(&left)?;
+                let (right_offset, right_type) = resolve(&right)?;
+                let comparable = match (left_type, right_type) {
+                    // (a foreign key can be an integer column)
+                    (ColumnType::Key, ColumnType::Integer)
+                    | (ColumnType::Integer, ColumnType::Key) => true,
+                    (left_type, right_type) => left_type == right_type,
+                };
+                if !comparable {
+                    return Err(From::from(format!(
+                        "can't compare {:?} column {} to {:?} column {}",
+                        left_type,
+                        left.display(),
+                        right_type,
+                        right.display()
+                    )));
+                }
+                WhereSubcommand::ColumnComparison {
+                    left_offset,
+                    operator,
+                    right_offset,
+                }
+            }
+            Condition::And(left, right) => WhereSubcommand::And(
+                Box::new(Self::compile(*left, resolve)?),
+                Box::new(Self::compile(*right, resolve)?),
+            ),
+            Condition::Or(left, right) => WhereSubcommand::Or(
+                Box::new(Self::compile(*left, resolve)?),
+                Box::new(Self::compile(*right, resolve)?),
+            ),
+            Condition::Not(negated) => WhereSubcommand::Not(Box::new(
+                Self::compile(*negated, resolve)?,
+            )),
+            Condition::IsNull(column) => WhereSubcommand::IsNull {
+                column_offset: resolve(&column)?.0,
+            },
+        })
+    }
+
     crate fn new_unconditional() -> Self {
-        Self {
-            column_offset: 0,           // dummy value
-            value: Chamber::Integer(0), // dummy value
-            unconditional: true,
+        WhereSubcommand::Unconditional
+    }
+
+    crate fn is_unconditional(&self) -> bool {
+        match self {
+            WhereSubcommand::Unconditional => true,
+            _ => false,
         }
     }
 
+    #[cfg(test)]
     crate fn new_column_equality(
         schema: &TableSchema,
         column_name: String,
         value: Chamber,
     ) -> Result<Self, Box<dyn Error>> {
-        Ok(Self {
-            column_offset: column_names_to_offsets(schema, &[column_name])?[0],
+        Self::new_comparison(
+            schema,
+            column_name,
+            ComparisonOperator::Equal,
             value,
-            unconditional: false,
-        })
+        )
+    }
+
+    #[cfg(test)]
+    crate fn new_comparison(
+        schema: &TableSchema,
+        column_name: String,
+        operator: ComparisonOperator,
+        value: Chamber,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new(
+            schema,
+            Condition::Comparison {
+                column: ColumnExpression::Column(column_name),
+                operator,
+                value,
+            },
+        )
+    }
+
+    /// Evaluates the condition in SQL's three-valued logic, with `None`
+    /// standing for UNKNOWN (the result of comparing with NULL).
+    crate fn truth<T: Tuple + ?Sized>(&self, row: &T) -> Option<bool> {
+        match self {
+            WhereSubcommand::Unconditional => Some(true),
+            WhereSubcommand::Comparison {
+                column_offset,
+                operator,
+                value,
+            } => operator.apply(row.chamber(*column_offset), value),
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                operator,
+                right_offset,
+            } => operator
+                .apply(row.chamber(*left_offset), row.chamber(*right_offset)),
+            WhereSubcommand::And(left, right) => {
+                match (left.truth(row), right.truth(row)) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                }
+            }
+            WhereSubcommand::Or(left, right) => {
+                match (left.truth(row), right.truth(row)) {
+                    (Some(true), _) | (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                }
+            }
+            WhereSubcommand::Not(negated) => negated.truth(row).map(|t| !t),
+            WhereSubcommand::IsNull { column_offset } => {
+                Some(row.chamber(*column_offset).is_null())
+            }
+        }
+    }
+
+    /// Whether a row satisfies the condition (which it doesn't if the
+    /// condition is UNKNOWN).
+    crate fn evaluate<T: Tuple + ?Sized>(&self, row: &T) -> bool {
+        self.truth(row) == Some(true)
     }
 
     crate fn operationalize(self) -> impl Fn(&Row) -> bool + 'static {
-        move |row| {
-            if self.unconditional {
-                return true;
+        move |row| self.evaluate(row)
+    }
+
+    /// The pairs of column offsets that a condition requires to be equal
+    /// (i.e., the `left = right` comparisons in its top-level conjunction).
+    crate fn equalities(&self) -> Vec<(usize, usize)> {
+        match self {
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                operator: ComparisonOperator::Equal,
+                right_offset,
+            } => vec![(*left_offset, *right_offset)],
+            WhereSubcommand::And(left, right) => {
+                let mut equalities = left.equalities();
+                equalities.extend(right.equalities());
+                equalities
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The comparisons of columns with (non-NULL) values in a condition's
+    /// top-level conjunction, which an index might be able to look up.
+    crate fn comparisons(&self) -> Vec<(usize, ComparisonOperator, &Chamber)> {
+        match self {
+            WhereSubcommand::Comparison {
+                column_offset,
+                operator,
+                value,
+            } if !value.is_null() => vec![(*column_offset, *operator, value)],
+            WhereSubcommand::And(left, right) => {
+                let mut comparisons = left.comparisons();
+                comparisons.extend(right.comparisons());
+                comparisons
+            }
+            _ => vec![],
+        }
+    }
+
+    /// The conjunction of some conditions (which is unconditional if there
+    /// are none).
+    crate fn all(conditions: Vec<WhereSubcommand>) -> Self {
+        conditions
+            .into_iter()
+            .fold(WhereSubcommand::Unconditional, |all, condition| match all {
+                WhereSubcommand::Unconditional => condition,
+                all => {
+                    WhereSubcommand::And(Box::new(all), Box::new(condition))
+                }
+            })
+    }
+
+    /// The conditions in a condition's top-level conjunction.
+    crate fn conjuncts(&self) -> Vec<&WhereSubcommand> {
+        match self {
+            WhereSubcommand::Unconditional => vec![],
+            WhereSubcommand::And(left, right) => {
+                let mut conjuncts = left.conjuncts();
+                conjuncts.extend(right.conjuncts());
+                conjuncts
+            }
+            condition => vec![condition],
+        }
+    }
+
+    /// The offsets of the columns that a condition refers to.
+    crate fn column_offsets(&self) -> Vec<usize> {
+        match self {
+            WhereSubcommand::Unconditional => vec![],
+            WhereSubcommand::Comparison { column_offset, .. }
+            | WhereSubcommand::IsNull { column_offset } => {
+                vec![*column_offset]
+            }
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                right_offset,
+                ..
+            } => vec![*left_offset, *right_offset],
+            WhereSubcommand::And(left, right)
+            | WhereSubcommand::Or(left, right) => {
+                let mut column_offsets = left.column_offsets();
+                column_offsets.extend(right.column_offsets());
+                column_offsets
+            }
+            WhereSubcommand::Not(negated) => negated.column_offsets(),
+        }
+    }
+
+    /// The same condition on columns that have moved: the column at each
+    /// offset `o` is now at `offsets[o]`.
+    crate fn remap(&self, offsets: &[usize]) -> Self {
+        match self {
+            WhereSubcommand::Unconditional => WhereSubcommand::Unconditional,
+            WhereSubcommand::Comparison {
+                column_offset,
+                operator,
+                value,
+            } => WhereSubcommand::Comparison {
+                column_offset: offsets[*column_offset],
+                operator: *operator,
+                value: value.clone(),
+            },
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                operator,
+                right_offset,
+            } => WhereSubcommand::ColumnComparison {
+                left_offset: offsets[*left_offset],
+                operator: *operator,
+                right_offset: offsets[*right_offset],
+            },
+            WhereSubcommand::And(left, right) => WhereSubcommand::And(
+                Box::new(left.remap(offsets)),
+                Box::new(right.remap(offsets)),
+            ),
+            WhereSubcommand::Or(left, right) => WhereSubcommand::Or(
+                Box::new(left.remap(offsets)),
+                Box::new(right.remap(offsets)),
+            ),
+            WhereSubcommand::Not(negated) => {
+                WhereSubcommand::Not(Box::new(negated.remap(offsets)))
+            }
+            WhereSubcommand::IsNull { column_offset } => {
+                WhereSubcommand::IsNull {
+                    column_offset: offsets[*column_offset],
+                }
+            }
+        }
+    }
+
+    /// The condition as EXPLAIN shows it, given the names of the columns
+    /// that its offsets refer to.
+    crate fn display(&self, column_names: &[String]) -> String {
+        // (AND binds tighter than OR, so only ORs inside ANDs, and compound
+        // conditions inside NOTs, need parentheses)
+        let parenthesized = |condition: &WhereSubcommand| match condition {
+            WhereSubcommand::And(..) | WhereSubcommand::Or(..) => {
+                format!("({})", condition.display(column_names))
+            }
+            _ => condition.display(column_names),
+        };
+        match self {
+            WhereSubcommand::Unconditional => "TRUE".to_owned(),
+            WhereSubcommand::Comparison {
+                column_offset,
+                operator,
+                value,
+            } => format!(
+                "{} {} {}",
+                column_names[*column_offset],
+                operator.display(),
+                display_literal(value)
+            ),
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                operator,
+                right_offset,
+            } => format!(
+                "{} {} {}",
+                column_names[*left_offset],
+                operator.display(),
+                column_names[*right_offset]
+            ),
+            WhereSubcommand::And(left, right) => {
+                let operand = |condition: &WhereSubcommand| match condition {
+                    WhereSubcommand::Or(..) => parenthesized(condition),
+                    _ => condition.display(column_names),
+                };
+                format!("{} AND {}", operand(left), operand(right))
+            }
+            WhereSubcommand::Or(left, right) => format!(
+                "{} OR {}",
+                left.display(column_names),
+                right.display(column_names)
+            ),
+            WhereSubcommand::Not(negated) => match **negated {
+                WhereSubcommand::IsNull { column_offset } => {
+                    format!("{} IS NOT NULL", column_names[column_offset])
+                }
+                ref negated => format!("NOT {}", parenthesized(negated)),
+            },
+            WhereSubcommand::IsNull { column_offset } => {
+                format!("{} IS NULL", column_names[*column_offset])
             }
-            let pred = row.0[self.column_offset] == self.value;
-            pred
         }
     }
 }
 
-crate struct SelectCommand<'a> {
-    crate column_offsets: Vec<usize>,
-    crate view: btree_map::Values<'a, usize, Row>,
-    crate filter: Box<dyn Fn(&Row) -> bool>,
+// A value as it would be written in SQL
+fn display_literal(chamber: &Chamber) -> String {
+    match chamber {
+        Chamber::String(string) => format!("'{}'", string),
+        _ => chamber.display(),
+    }
 }
 
-impl<'a> SelectCommand<'a> {
-    crate fn new_table_scan(
-        table: &'a Table,
-        column_names: Vec<String>,
-        where_clause: WhereSubcommand,
-    ) -> Self {
-        Self {
-            column_offsets: column_names_to_offsets(
-                &table.schema,
-                &column_names,
-            ).unwrap(),
-            view: table.rows.values(),
-            filter: Box::new(where_clause.operationalize()),
+#[derive(Debug)]
+crate struct SortSubcommand {
+    crate keys: Vec<(usize, SortOrder)>,
+}
+
+impl SortSubcommand {
+    crate fn new(
+        schema: &TableSchema,
+        sort_keys: Vec<SortKey>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::compile(sort_keys, &mut |column| {
+            resolve_table_column(schema, column)
+        })
+    }
+
+    crate fn compile(
+        sort_keys: Vec<SortKey>,
+        resolve: &mut Resolver<'_>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut keys = Vec::new();
+        for sort_key in sort_keys {
+            let column = ColumnExpression::Column(sort_key.column_name);
+            keys.push((resolve(&column)?.0, sort_key.order));
         }
+        Ok(Self { keys })
     }
 
-    crate fn execute(self) -> Vec<Vec<&'a Chamber>> {
-        // XXX: `for` loops are so pedestrian
-        // XXX: overloading the word "result"?
-        let mut results = Vec::new();
-        for row in self.view {
-            if (self.filter)(row) {
-                let mut result = Vec::new();
-                for (i, chamber) in row.0.iter().enumerate() {
-                    if self.column_offsets.contains(&i) {
-                        result.push(chamber);
-                    }
+    crate fn compare<T: Tuple + ?Sized>(
+        &self,
+        left: &T,
+        right: &T,
+    ) -> Ordering {
+        for &(offset, order) in &self.keys {
+            // Chambers in the same column have the same type and are thus
+            // comparable, except for NULLs, which sort as if greater than any
+            // other value
+            let (left, right) = (left.chamber(offset), right.chamber(offset));
+            let ordering = left.sort_cmp(right);
+            let ordering = match order {
+                SortOrder::Ascending => ordering,
+                SortOrder::Descending => ordering.reverse(),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// The sort keys as EXPLAIN shows them.
+    crate fn display(&self, column_names: &[String]) -> String {
+        self.keys
+            .iter()
+            .map(|&(offset, order)| match order {
+                SortOrder::Ascending => column_names[offset].clone(),
+                SortOrder::Descending => {
+                    format!("{} DESC", column_names[offset])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    crate fn operationalize(
+        self,
+    ) -> impl Fn(&Row, &Row) -> Ordering + 'static {
+        move |left, right| self.compare(left, right)
+    }
+}
+
+/// A value that an UPDATE assigns, with its columns resolved to offsets.
+#[derive(Clone, Debug)]
+crate enum ValueSubcommand {
+    Constant(Chamber),
+    Column(usize),
+    Arithmetic(
+        Box<ValueSubcommand>,
+        ArithmeticOperator,
+        Box<ValueSubcommand>,
+    ),
+}
+
+impl ValueSubcommand {
+    crate fn new(
+        schema: &TableSchema,
+        expression: ValueExpression,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(match expression {
+            ValueExpression::Literal(value) => {
+                ValueSubcommand::Constant(value)
+            }
+            ValueExpression::Column(column_name) => ValueSubcommand::Column(
+                column_names_to_offsets(schema, &[column_name])?[0],
+            ),
+            ValueExpression::Arithmetic(left, operator, right) => {
+                // (strings can be told apart before any row is looked at)
+                for operand in &[&left, &right] {
+                    let string = match &***operand {
+                        ValueExpression::Literal(Chamber::String(_)) => {
+                            "value"
+                        }
+                        ValueExpression::Column(column_name) => {
+                            let offset = column_names_to_offsets(
+                                schema,
+                                &[column_name.clone()],
+                            )?[0];
+                            match schema.layout[offset].column_type {
+                                ColumnType::String => "column",
+                                _ => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+                    return Err(From::from(format!(
+                        "can't apply {} to String {} {}",
+                        operator.display(),
+                        string,
+                        operand.display()
+                    )));
                 }
-                results.push(result);
+                ValueSubcommand::Arithmetic(
+                    Box::new(Self::new(schema, *left)?),
+                    operator,
+                    Box::new(Self::new(schema, *right)?),
+                )
+            }
+        })
+    }
+
+    crate fn evaluate(&self, row: &Row) -> Result<Chamber, Box<dyn Error>> {
+        match self {
+            ValueSubcommand::Constant(value) => Ok(value.clone()),
+            ValueSubcommand::Column(offset) => Ok(row.0[*offset].clone()),
+            ValueSubcommand::Arithmetic(left, operator, right) => {
+                operator.apply(&left.evaluate(row)?, &right.evaluate(row)?)
             }
         }
-        results
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::table::*;
+/// A step of a query plan, as EXPLAIN shows it: what it does, and the steps
+/// it takes its rows from (the first of which, for a join, is the left side).
+#[derive(Debug)]
+pub struct PlanNode {
+    crate operation: String,
+    crate detail: String,
+    crate children: Vec<PlanNode>,
+    // For EXPLAIN ANALYZE, how many rows the step produced, and the time it
+    // took to produce them (including the time spent in the steps below it)
+    crate actual: Option<(usize, Duration)>,
+}
 
-    fn example_table() -> Table {
-        let mut schema = TableSchema::new();
-        schema.add_column("title".to_owned(), ColumnType::String);
-        schema.add_column("year".to_owned(), ColumnType::Integer);
-        let mut table = Table::new(schema);
-        table
-            .insert(Row(vec![
-                Chamber::Key(0),
-                Chamber::String("Men Trapped In Men's Bodies".to_owned()),
-                Chamber::Integer(2013),
-            ]))
-            .unwrap();
-        table
-            .insert(Row(vec![
-                Chamber::Key(0),
-                Chamber::String("Galileo's Middle Finger".to_owned()),
-                Chamber::Integer(2015),
-            ]))
-            .unwrap();
-        table
-            .insert(Row(vec![
-                Chamber::Key(0),
-                Chamber::String("Thing Explainer".to_owned()),
-                Chamber::Integer(2015),
-            ]))
-            .unwrap();
-        table
+impl PlanNode {
+    crate fn new(
+        operation: &str,
+        detail: String,
+        children: Vec<PlanNode>,
+    ) -> Self {
+        Self {
+            operation: operation.to_owned(),
+            detail,
+            children,
+            actual: None,
+        }
     }
 
-    #[test]
-    fn concerning_converting_column_names_to_offsets() {
-        let table = example_table();
-        assert_eq!(
-            column_names_to_offsets(
-                &table.schema,
-                &["title".to_owned(), "year".to_owned()]
-            ).unwrap(),
-            vec![1, 2]
-        );
+    /// Records what the steps down the chain of first children from this one
+    /// actually did (from the top).
+    crate fn record(&mut self, actuals: Vec<(usize, Duration)>) {
+        let mut node = Some(self);
+        for actual in actuals {
+            let step = node.expect("plan should have a step per actual");
+            step.actual = Some(actual);
+            node = step.children.first_mut();
+        }
     }
 
-    #[test]
-    fn concerning_select_by_primary_key() {
-        let table = example_table();
-        let where_clause = WhereSubcommand::new_column_equality(
-            &table.schema,
-            "pk".to_owned(),
-            Chamber::Key(2),
-        ).unwrap();
-        let select_command = SelectCommand::new_table_scan(
-            &table,
-            vec!["title".to_owned()],
-            where_clause,
-        );
-        let result_rows = select_command.execute();
-        assert_eq!(result_rows.len(), 1);
-        assert_eq!(
-            &Chamber::String("Galileo's Middle Finger".to_owned()),
-            result_rows[0][0],
-        );
+    fn add_rows(&self, depth: usize, display_table: &mut prettytable::Table) {
+        let mut display_row = prettytable::row::Row::empty();
+        let indentation = match depth {
+            0 => String::new(),
+            _ => format!("{}-> ", "   ".repeat(depth - 1)),
+        };
+        display_row.add_cell(prettytable::cell::Cell::new(&format!(
+            "{}{} {}",
+            indentation, self.operation, self.detail
+        )));
+        if let Some((rows, time)) = self.actual {
+            let milliseconds =
+                time.as_secs() as f64 * 1e3 + time.subsec_nanos() as f64 / 1e6;
+            display_row
+                .add_cell(prettytable::cell::Cell::new(&rows.to_string()));
+            display_row.add_cell(prettytable::cell::Cell::new(&format!(
+                "{:.3} ms",
+                milliseconds
+            )));
+        }
+        display_table.add_row(display_row);
+        for child in &self.children {
+            child.add_rows(depth + 1, display_table);
+        }
     }
 
-    #[test]
-    fn concerning_select_by_integer() {
-        let table = example_table();
-        let where_clause = WhereSubcommand::new_column_equality(
-            &table.schema,
-            "year".to_owned(),
-            Chamber::Integer(2015),
-        ).unwrap();
-        let select_command = SelectCommand::new_table_scan(
-            &table,
-            vec!["title".to_owned()],
-            where_clause,
+    crate fn display(&self) -> String {
+        let mut buf = Vec::new();
+        let mut display_table = prettytable::Table::new();
+        display_table.set_format(
+            *prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE,
         );
-        let result_rows = select_command.execute();
-        assert_eq!(result_rows.len(), 2);
-        assert_eq!(
-            vec![
-                vec![&Chamber::String(
-                    "Galileo's Middle Finger".to_owned(),
-                )],
-                vec![&Chamber::String("Thing Explainer".to_owned())],
-            ],
-            result_rows
+        let mut headers = prettytable::row::Row::empty();
+        headers.add_cell(prettytable::cell::Cell::new("plan"));
+        if self.actual.is_some() {
+            headers.add_cell(prettytable::cell::Cell::new("rows"));
+            headers.add_cell(prettytable::cell::Cell::new("time"));
+        }
+        display_table.set_titles(headers);
+        self.add_rows(0, &mut display_table);
+        display_table
+            .print(&mut buf)
+            .expect("should print to buffer");
+        String::from_utf8(buf).expect("pretty table should be valid UTF-8")
+    }
+}
+
+fn display_limit(limit: usize, offset: usize) -> String {
+    match offset {
+        0 => limit.to_string(),
+        _ => format!("{} OFFSET {}", limit, offset),
+    }
+}
+
+// The cost model's units: reading the next row of a table scan, and looking
+// up a row by its primary key (as each row found through an index is)
+const SEQUENTIAL_ROW_COST: f64 = 1.0;
+const RANDOM_ROW_COST: f64 = 2.0;
+// the guess at how many rows satisfy a comparison of two columns other than
+// an equality, which statistics about each column on its own can't help with
+const DEFAULT_INEQUALITY_SELECTIVITY: f64 = 1.0 / 3.0;
+
+// The estimated fraction of a table's rows that aren't NULL in a column
+fn non_null_fraction(statistics: &TableStatistics, offset: usize) -> f64 {
+    if statistics.row_count == 0 {
+        return 0.0;
+    }
+    let nulls = statistics.columns[offset].null_count as f64;
+    1.0 - nulls / statistics.row_count as f64
+}
+
+// The estimated fraction of a table's rows with the given value in a column.
+// A value at several of the histogram's boundaries is common enough for the
+// histogram to say how common; the rest of the rows are assumed to be shared
+// evenly among the other values.
+fn equality_selectivity(
+    statistics: &TableStatistics,
+    offset: usize,
+    value: &Chamber,
+) -> f64 {
+    let column = &statistics.columns[offset];
+    let histogram = &column.histogram;
+    let (mut common_values, mut common_bounds) = (0, 0);
+    let mut start = 0;
+    while start < histogram.len() {
+        let repeats = histogram[start..]
+            .iter()
+            .take_while(|bound| *bound == &histogram[start])
+            .count();
+        if repeats > 1 {
+            if histogram[start].sort_cmp(value) == Ordering::Equal {
+                return repeats as f64 / histogram.len() as f64
+                    * non_null_fraction(statistics, offset);
+            }
+            common_values += 1;
+            common_bounds += repeats;
+        }
+        start += repeats;
+    }
+    if column.distinct_count <= common_values {
+        return 0.0;
+    }
+    let uncommon = 1.0 - common_bounds as f64 / histogram.len() as f64;
+    uncommon / (column.distinct_count - common_values) as f64
+        * non_null_fraction(statistics, offset)
+}
+
+// The estimated fraction of a table's rows with a value in a column within
+// the given bounds, by how many of the histogram's boundaries are (with a
+// range between two boundaries taken to hold half a bucket)
+fn range_selectivity(
+    statistics: &TableStatistics,
+    offset: usize,
+    lower: &Bound<Chamber>,
+    upper: &Bound<Chamber>,
+) -> f64 {
+    let histogram = &statistics.columns[offset].histogram;
+    if histogram.is_empty() {
+        return 0.0;
+    }
+    let within = histogram
+        .iter()
+        .filter(|bound| {
+            let above = match lower {
+                Bound::Included(value) => {
+                    bound.sort_cmp(value) != Ordering::Less
+                }
+                Bound::Excluded(value) => {
+                    bound.sort_cmp(value) == Ordering::Greater
+                }
+                Bound::Unbounded => true,
+            };
+            let below = match upper {
+                Bound::Included(value) => {
+                    bound.sort_cmp(value) != Ordering::Greater
+                }
+                Bound::Excluded(value) => {
+                    bound.sort_cmp(value) == Ordering::Less
+                }
+                Bound::Unbounded => true,
+            };
+            above && below
+        })
+        .count();
+    (within as f64).max(0.5) / histogram.len() as f64
+        * non_null_fraction(statistics, offset)
+}
+
+// The estimated fraction of rows satisfying a condition, given the statistics
+// of the table of the column at each offset, and its offset in that table
+fn selectivity<'s>(
+    condition: &WhereSubcommand,
+    statistics: &dyn Fn(usize) -> (&'s TableStatistics, usize),
+) -> f64 {
+    match condition {
+        WhereSubcommand::Unconditional => 1.0,
+        WhereSubcommand::Comparison {
+            column_offset,
+            operator,
+            value,
+        } => {
+            let (table, offset) = statistics(*column_offset);
+            let range = |lower, upper| {
+                range_selectivity(table, offset, &lower, &upper)
+            };
+            let value = value.clone();
+            match operator {
+                // (comparisons with NULL are never true)
+                _ if value.is_null() => 0.0,
+                ComparisonOperator::Equal => {
+                    equality_selectivity(table, offset, &value)
+                }
+                ComparisonOperator::NotEqual => {
+                    non_null_fraction(table, offset)
+                        - equality_selectivity(table, offset, &value)
+                }
+                ComparisonOperator::Less => {
+                    range(Bound::Unbounded, Bound::Excluded(value))
+                }
+                ComparisonOperator::LessOrEqual => {
+                    range(Bound::Unbounded, Bound::Included(value))
+                }
+                ComparisonOperator::Greater => {
+                    range(Bound::Excluded(value), Bound::Unbounded)
+                }
+                ComparisonOperator::GreaterOrEqual => {
+                    range(Bound::Included(value), Bound::Unbounded)
+                }
+            }
+        }
+        WhereSubcommand::ColumnComparison {
+            left_offset,
+            operator: ComparisonOperator::Equal,
+            right_offset,
+        } => {
+            // (each value on the side with fewer of them is assumed to match
+            // some value on the other side)
+            let distinct_count = |column_offset| {
+                let (table, offset) = statistics(column_offset);
+                table.columns[offset].distinct_count
+            };
+            let distinct_count = distinct_count(*left_offset)
+                .max(distinct_count(*right_offset))
+                .max(1);
+            1.0 / distinct_count as f64
+        }
+        WhereSubcommand::ColumnComparison { .. } => {
+            DEFAULT_INEQUALITY_SELECTIVITY
+        }
+        WhereSubcommand::And(left, right) => {
+            selectivity(left, statistics) * selectivity(right, statistics)
+        }
+        WhereSubcommand::Or(left, right) => {
+            let left = selectivity(left, statistics);
+            let right = selectivity(right, statistics);
+            left + right - left * right
+        }
+        WhereSubcommand::Not(negated) => {
+            1.0 - selectivity(negated, statistics)
+        }
+        WhereSubcommand::IsNull { column_offset } => {
+            let (table, offset) = statistics(*column_offset);
+            1.0 - non_null_fraction(table, offset)
+        }
+    }
+}
+
+/// How a `SelectCommand` finds the rows that might satisfy its filter.
+#[derive(Debug, PartialEq)]
+crate enum AccessPath {
+    TableScan,
+    // Rows are keyed on their primary key, so these don't need an index
+    PrimaryKeyLookup {
+        pk: usize,
+    },
+    PrimaryKeyRange {
+        lower: Bound<usize>,
+        upper: Bound<usize>,
+    },
+    // The rows whose keys in the index (by offset into the table's indices)
+    // start with `prefix`, followed by a value within the bounds
+    IndexScan {
+        index_offset: usize,
+        prefix: Vec<Chamber>,
+        lower: Bound<Chamber>,
+        upper: Bound<Chamber>,
+    },
+    // The rows with the given key in a hash index
+    HashLookup {
+        index_offset: usize,
+        key: Vec<Chamber>,
+    },
+}
+
+impl AccessPath {
+    /// Picks the path with the least estimated cost if the table has been
+    /// analyzed. Otherwise, rules decide: a primary-key lookup if the
+    /// condition fixes the primary key, or else the index that narrows down
+    /// the rows satisfying a condition the most (equalities on leading
+    /// columns of the index first, then a range on the column after them,
+    /// with a hash index winning over a B-tree for the same equalities), or
+    /// else a primary-key range or a table scan.
+    crate fn plan(table: &Table, where_clause: &WhereSubcommand) -> Self {
+        let candidates = Self::candidates(table, where_clause).into_iter();
+        // (ties go to the earlier candidate)
+        let best = match &table.statistics {
+            Some(statistics) => candidates
+                .map(|(_, path)| (path.cost(table, statistics), path))
+                .min_by(|(a, _), (b, _)| {
+                    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+                })
+                .map(|(_, path)| path),
+            None => candidates
+                .min_by_key(|(score, _)| Reverse(*score))
+                .map(|(_, path)| path),
+        };
+        best.expect("a table scan should always be possible")
+    }
+
+    // The paths that could find the rows satisfying a condition, each with
+    // its rank by the rules
+    fn candidates(
+        table: &Table,
+        where_clause: &WhereSubcommand,
+    ) -> Vec<(usize, AccessPath)> {
+        let mut candidates = vec![(0, AccessPath::TableScan)];
+        let comparisons = where_clause.comparisons();
+        let mut lookup = None;
+        let (mut lower, mut upper) = (Bound::Unbounded, Bound::Unbounded);
+        for (offset, operator, value) in &comparisons {
+            let pk = match (offset, value) {
+                (0, Chamber::Key(pk)) => *pk,
+                _ => continue,
+            };
+            match operator {
+                ComparisonOperator::Equal => {
+                    lookup = lookup.or(Some(pk));
+                }
+                ComparisonOperator::Greater => lower = Bound::Excluded(pk),
+                ComparisonOperator::GreaterOrEqual => {
+                    lower = Bound::Included(pk)
+                }
+                ComparisonOperator::Less => upper = Bound::Excluded(pk),
+                ComparisonOperator::LessOrEqual => upper = Bound::Included(pk),
+                ComparisonOperator::NotEqual => {}
+            }
+        }
+        if let Some(pk) = lookup {
+            let lookup = AccessPath::PrimaryKeyLookup { pk };
+            candidates.push((usize::max_value(), lookup));
+        }
+        // (a range of the rows themselves is as good as a range of an index)
+        if lower != Bound::Unbounded || upper != Bound::Unbounded {
+            candidates.push((1, AccessPath::PrimaryKeyRange { lower, upper }));
+        }
+        for (index_offset, index) in table.indices.iter().enumerate() {
+            let mut prefix = Vec::new();
+            for column_offset in &index.column_offsets {
+                let equality =
+                    comparisons.iter().find(|(offset, operator, _)| {
+                        offset == column_offset
+                            && *operator == ComparisonOperator::Equal
+                    });
+                match equality {
+                    Some((_, _, value)) => prefix.push((*value).clone()),
+                    None => break,
+                }
+            }
+            if let IndexEntries::Hash(_) = index.entries {
+                // (which can only look up whole keys)
+                if prefix.len() == index.column_offsets.len() {
+                    candidates.push((
+                        3 * prefix.len() + 2,
+                        AccessPath::HashLookup {
+                            index_offset,
+                            key: prefix,
+                        },
+                    ));
+                }
+                continue;
+            }
+            let (mut lower, mut upper) = (Bound::Unbounded, Bound::Unbounded);
+            if let Some(column_offset) = index.column_offsets.get(prefix.len())
+            {
+                for (offset, operator, value) in &comparisons {
+                    if offset != column_offset {
+                        continue;
+                    }
+                    let value = (*value).clone();
+                    match operator {
+                        ComparisonOperator::Greater => {
+                            lower = Bound::Excluded(value)
+                        }
+                        ComparisonOperator::GreaterOrEqual => {
+                            lower = Bound::Included(value)
+                        }
+                        ComparisonOperator::Less => {
+                            upper = Bound::Excluded(value)
+                        }
+                        ComparisonOperator::LessOrEqual => {
+                            upper = Bound::Included(value)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            let ranged =
+                lower != Bound::Unbounded || upper != Bound::Unbounded;
+            if !prefix.is_empty() || ranged {
+                candidates.push((
+                    3 * prefix.len() + ranged as usize,
+                    AccessPath::IndexScan {
+                        index_offset,
+                        prefix,
+                        lower,
+                        upper,
+                    },
+                ));
+            }
+        }
+        candidates
+    }
+
+    // The estimated cost of reading the rows that the path leads to
+    fn cost(&self, table: &Table, statistics: &TableStatistics) -> f64 {
+        // (the table knows how many rows it has now; the statistics are for
+        // what fraction of them a path leads to)
+        let rows = table.rows.len() as f64;
+        let descent = (rows + 1.0).log2();
+        let equalities = |column_offsets: &[usize], values: &[Chamber]| {
+            column_offsets
+                .iter()
+                .zip(values)
+                .map(|(&offset, value)| {
+                    equality_selectivity(statistics, offset, value)
+                })
+                .product::<f64>()
+        };
+        match self {
+            AccessPath::TableScan => rows * SEQUENTIAL_ROW_COST,
+            AccessPath::PrimaryKeyLookup { .. } => descent,
+            AccessPath::PrimaryKeyRange { lower, upper } => {
+                let key = |bound: &Bound<usize>| match *bound {
+                    Bound::Included(pk) => Bound::Included(Chamber::Key(pk)),
+                    Bound::Excluded(pk) => Bound::Excluded(Chamber::Key(pk)),
+                    Bound::Unbounded => Bound::Unbounded,
+                };
+                let selectivity =
+                    range_selectivity(statistics, 0, &key(lower), &key(upper));
+                descent + rows * selectivity * SEQUENTIAL_ROW_COST
+            }
+            AccessPath::IndexScan {
+                index_offset,
+                prefix,
+                lower,
+                upper,
+            } => {
+                let index = &table.indices[*index_offset];
+                let column_offsets = &index.column_offsets;
+                let mut selectivity = equalities(column_offsets, prefix);
+                let ranged =
+                    *lower != Bound::Unbounded || *upper != Bound::Unbounded;
+                if ranged {
+                    selectivity *= range_selectivity(
+                        statistics,
+                        column_offsets[prefix.len()],
+                        lower,
+                        upper,
+                    );
+                }
+                descent + rows * selectivity * RANDOM_ROW_COST
+            }
+            AccessPath::HashLookup { index_offset, key } => {
+                let index = &table.indices[*index_offset];
+                let column_offsets = &index.column_offsets;
+                1.0 + rows * equalities(column_offsets, key) * RANDOM_ROW_COST
+            }
+        }
+    }
+
+    crate fn explain(&self, table: &Table, table_name: &str) -> PlanNode {
+        let column_names = table_column_names(table);
+        // (e.g., "year = 2015 AND title > 'A'")
+        let range = |mut terms: Vec<String>,
+                     column_name: &str,
+                     lower: Bound<String>,
+                     upper: Bound<String>| {
+            match lower {
+                Bound::Included(value) => {
+                    terms.push(format!("{} >= {}", column_name, value))
+                }
+                Bound::Excluded(value) => {
+                    terms.push(format!("{} > {}", column_name, value))
+                }
+                Bound::Unbounded => {}
+            }
+            match upper {
+                Bound::Included(value) => {
+                    terms.push(format!("{} <= {}", column_name, value))
+                }
+                Bound::Excluded(value) => {
+                    terms.push(format!("{} < {}", column_name, value))
+                }
+                Bound::Unbounded => {}
+            }
+            terms.join(" AND ")
+        };
+        let literal_bound = |bound: &Bound<Chamber>| match bound {
+            Bound::Included(value) => Bound::Included(display_literal(value)),
+            Bound::Excluded(value) => Bound::Excluded(display_literal(value)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let equalities = |column_offsets: &[usize], values: &[Chamber]| {
+            column_offsets
+                .iter()
+                .zip(values)
+                .map(|(&offset, value)| {
+                    format!(
+                        "{} = {}",
+                        column_names[offset],
+                        display_literal(value)
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let (operation, detail) = match self {
+            AccessPath::TableScan => ("Table Scan", table_name.to_owned()),
+            AccessPath::PrimaryKeyLookup { pk } => (
+                "Primary Key Lookup",
+                format!("{} ({} = {})", table_name, column_names[0], pk),
+            ),
+            AccessPath::PrimaryKeyRange { lower, upper } => {
+                let key_bound = |bound: &Bound<usize>| match bound {
+                    Bound::Included(pk) => Bound::Included(pk.to_string()),
+                    Bound::Excluded(pk) => Bound::Excluded(pk.to_string()),
+                    Bound::Unbounded => Bound::Unbounded,
+                };
+                (
+                    "Primary Key Range",
+                    format!(
+                        "{} ({})",
+                        table_name,
+                        range(
+                            Vec::new(),
+                            &column_names[0],
+                            key_bound(lower),
+                            key_bound(upper)
+                        )
+                    ),
+                )
+            }
+            AccessPath::IndexScan {
+                index_offset,
+                prefix,
+                lower,
+                upper,
+            } => {
+                let index = &table.indices[*index_offset];
+                let terms = equalities(&index.column_offsets, prefix);
+                let conditions = match index.column_offsets.get(prefix.len()) {
+                    Some(&offset) => range(
+                        terms,
+                        &column_names[offset],
+                        literal_bound(lower),
+                        literal_bound(upper),
+                    ),
+                    None => terms.join(" AND "),
+                };
+                (
+                    "Index Scan",
+                    format!(
+                        "{} USING {} ({})",
+                        table_name, index.name, conditions
+                    ),
+                )
+            }
+            AccessPath::HashLookup { index_offset, key } => {
+                let index = &table.indices[*index_offset];
+                (
+                    "Hash Lookup",
+                    format!(
+                        "{} USING {} ({})",
+                        table_name,
+                        index.name,
+                        equalities(&index.column_offsets, key).join(" AND ")
+                    ),
+                )
+            }
+        };
+        PlanNode::new(operation, detail, Vec::new())
+    }
+
+    /// The rows along the path, as they're found: in primary-key order, but
+    /// for an index scan, which goes through the index in key order (and so
+    /// holds on to no more than the entry it's at).
+    crate fn rows<'a>(
+        &self,
+        table: &'a Table,
+    ) -> Box<dyn Iterator<Item = &'a Row> + 'a> {
+        let (index_offset, prefix, lower, upper) = match self {
+            AccessPath::TableScan => return Box::new(table.rows.values()),
+            AccessPath::PrimaryKeyLookup { pk } => {
+                return Box::new(table.rows.get(pk).into_iter())
+            }
+            AccessPath::PrimaryKeyRange { lower, upper } => {
+                // (`BTreeMap::range` panics on bounds that cross)
+                let empty = match (lower, upper) {
+                    (Bound::Included(low), Bound::Included(high)) => {
+                        low > high
+                    }
+                    (Bound::Included(low), Bound::Excluded(high))
+                    | (Bound::Excluded(low), Bound::Included(high))
+                    | (Bound::Excluded(low), Bound::Excluded(high)) => {
+                        low >= high
+                    }
+                    _ => false,
+                };
+                if empty {
+                    return Box::new(std::iter::empty());
+                }
+                return Box::new(
+                    table.rows.range((*lower, *upper)).map(|(_, row)| row),
+                );
+            }
+            AccessPath::IndexScan {
+                index_offset,
+                prefix,
+                lower,
+                upper,
+            } => (*index_offset, prefix, lower, upper),
+            AccessPath::HashLookup { index_offset, key } => {
+                let pks = table.indices[*index_offset]
+                    .entries
+                    .get(&IndexKey(key.clone()));
+                return Box::new(
+                    pks.into_iter().flatten().map(move |pk| &table.rows[pk]),
+                );
+            }
+        };
+        let entries = match &table.indices[index_offset].entries {
+            IndexEntries::BTree(entries) => entries,
+            IndexEntries::Hash(_) => panic!("hash indices can't be scanned"),
+        };
+        let depth = prefix.len();
+        let mut start = prefix.clone();
+        if let Bound::Included(value) | Bound::Excluded(value) = lower {
+            start.push(value.clone());
+        }
+        // Keys sort just before the keys they're a prefix of, so the scan
+        // starts at the first key in range and continues until it leaves the
+        // prefix or passes the upper bound (NULLs, sorting last, are past any
+        // bound)
+        let (prefix, lower, upper) =
+            (prefix.clone(), lower.clone(), upper.clone());
+        let pks = entries
+            .range(IndexKey(start)..)
+            .take_while(move |(key, _)| {
+                key.0[..depth] == prefix[..]
+                    && match (&upper, key.0.get(depth)) {
+                        (Bound::Included(bound), Some(value)) => {
+                            value.sort_cmp(bound) != Ordering::Greater
+                        }
+                        (Bound::Excluded(bound), Some(value)) => {
+                            value.sort_cmp(bound) == Ordering::Less
+                        }
+                        _ => true,
+                    }
+            })
+            .filter(move |(key, _)| match (&lower, key.0.get(depth)) {
+                (Bound::Excluded(bound), Some(value)) => {
+                    value.sort_cmp(bound) == Ordering::Greater
+                }
+                _ => true,
+            })
+            .flat_map(|(_, pks)| pks.iter());
+        Box::new(pks.map(move |pk| &table.rows[pk]))
+    }
+}
+
+crate struct SelectCommand<'a> {
+    crate table: &'a Table,
+    crate column_offsets: Vec<usize>,
+    crate access_path: AccessPath,
+    crate filter: Box<dyn Fn(&Row) -> bool>,
+    // Sorting happens before projection, so that we can sort by columns that
+    // aren't being selected
+    crate sort: Option<Box<dyn Fn(&Row, &Row) -> Ordering>>,
+    crate limit: Option<usize>,
+    crate offset: usize,
+    // what EXPLAIN shows of the filter and the sort (which are closures by
+    // now), if there are any
+    crate filter_display: Option<String>,
+    crate sort_display: Option<String>,
+}
+
+impl<'a> SelectCommand<'a> {
+    /// Scans the table, or an index of it if one fits the WHERE clause.
+    crate fn new(
+        table: &'a Table,
+        column_names: Vec<String>,
+        where_clause: WhereSubcommand,
+    ) -> Result<Self, Box<dyn Error>> {
+        let access_path = AccessPath::plan(table, &where_clause);
+        Self::new_scan(table, access_path, column_names, where_clause)
+    }
+
+    #[cfg(test)]
+    crate fn new_table_scan(
+        table: &'a Table,
+        column_names: Vec<String>,
+        where_clause: WhereSubcommand,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new_scan(
+            table,
+            AccessPath::TableScan,
+            column_names,
+            where_clause,
+        )
+    }
+
+    // (The filter still applies in full to the rows along the access path,
+    // which only narrows down where to look.)
+    fn new_scan(
+        table: &'a Table,
+        access_path: AccessPath,
+        column_names: Vec<String>,
+        where_clause: WhereSubcommand,
+    ) -> Result<Self, Box<dyn Error>> {
+        let filter_display = if where_clause.is_unconditional() {
+            None
+        } else {
+            Some(where_clause.display(&table_column_names(table)))
+        };
+        Ok(Self {
+            table,
+            column_offsets: column_names_to_offsets(
+                &table.schema,
+                &column_names,
+            )?,
+            access_path,
+            filter: Box::new(where_clause.operationalize()),
+            sort: None,
+            limit: None,
+            offset: 0,
+            filter_display,
+            sort_display: None,
+        })
+    }
+
+    crate fn sorted_by(mut self, sort: SortSubcommand) -> Self {
+        self.sort_display =
+            Some(sort.display(&table_column_names(self.table)));
+        self.sort = Some(Box::new(sort.operationalize()));
+        self
+    }
+
+    crate fn limited_to(mut self, limit: usize, offset: usize) -> Self {
+        self.limit = Some(limit);
+        self.offset = offset;
+        self
+    }
+
+    crate fn explain(&self, table_name: &str) -> PlanNode {
+        let mut node = self.access_path.explain(self.table, table_name);
+        if let Some(filter) = &self.filter_display {
+            node = PlanNode::new("Filter", filter.clone(), vec![node]);
+        }
+        if let Some(sort) = &self.sort_display {
+            node = PlanNode::new("Sort", sort.clone(), vec![node]);
+        }
+        if let Some(limit) = self.limit {
+            node = PlanNode::new(
+                "Limit",
+                display_limit(limit, self.offset),
+                vec![node],
+            );
+        }
+        let column_names = table_column_names(self.table);
+        let projected = self
+            .column_offsets
+            .iter()
+            .map(|&offset| column_names[offset].clone())
+            .collect::<Vec<_>>();
+        PlanNode::new("Project", projected.join(", "), vec![node])
+    }
+
+    /// The command's plan as operators, with an instrument attached to each
+    /// step of it if the plan is being analyzed.
+    crate fn into_operator(
+        self,
+        instruments: &mut Instruments,
+    ) -> BoxedOperator<'a, Vec<&'a Chamber>> {
+        let scan = Scan::new(self.table, self.access_path);
+        let mut operator: BoxedOperator<'a, &'a Row> =
+            instruments.attach(Box::new(scan));
+        let filter = self.filter;
+        operator =
+            Box::new(Filter::new(operator, move |row: &&Row| filter(row)));
+        // (an unconditional filter isn't a step of the plan)
+        if self.filter_display.is_some() {
+            operator = instruments.attach(operator);
+        }
+        if let Some(sort) = self.sort {
+            operator = instruments.attach(Box::new(Sort::new(
+                operator,
+                move |left: &&Row, right: &&Row| sort(left, right),
+            )));
+        }
+        // Without a sort, the scan is already in output order, and stops as
+        // soon as the limit has all of its rows
+        if let Some(limit) = self.limit {
+            operator = instruments.attach(Box::new(Limit::new(
+                operator,
+                limit,
+                self.offset,
+            )));
+        }
+        let column_offsets = self.column_offsets;
+        instruments.attach(Box::new(Map::new(operator, move |row: &'a Row| {
+            column_offsets
+                .iter()
+                .map(|&offset| &row.0[offset])
+                .collect()
+        })))
+    }
+
+    /// The names and types of the selected columns.
+    crate fn columns(&self) -> Vec<ResultColumn> {
+        self.column_offsets
+            .iter()
+            .map(|&offset| {
+                let column = &self.table.schema.layout[offset];
+                ResultColumn {
+                    name: column.name.clone(),
+                    column_type: column.column_type,
+                }
+            })
+            .collect()
+    }
+
+    // (all at once, for tests)
+    #[cfg(test)]
+    crate fn execute(self) -> Vec<Vec<&'a Chamber>> {
+        Rows::new(self.into_operator(&mut Instruments::off()))
+            .collect::<Result<_, _>>()
+            .expect("scans shouldn't fail")
+    }
+
+    /// Executes the command, returning its plan along with the rows, with
+    /// what each step of the plan did.
+    crate fn analyze(
+        self,
+        table_name: &str,
+    ) -> (Vec<Vec<&'a Chamber>>, PlanNode) {
+        let mut plan = self.explain(table_name);
+        let mut instruments = Instruments::on();
+        let rows = Rows::new(self.into_operator(&mut instruments))
+            .collect::<Result<_, _>>()
+            .expect("scans shouldn't fail");
+        plan.record(instruments.readings());
+        (rows, plan)
+    }
+}
+
+fn table_column_names(table: &Table) -> Vec<String> {
+    table
+        .schema
+        .layout
+        .iter()
+        .map(|column| column.name.clone())
+        .collect()
+}
+
+#[derive(Debug)]
+crate struct JoinedColumn {
+    // the alias of the column's table, or else the table's name
+    crate qualifier: String,
+    crate name: String,
+    crate column_type: ColumnType,
+}
+
+impl JoinedColumn {
+    /// Whether a column name (qualified as "table.column", or not) refers to
+    /// this column.
+    crate fn matches(&self, column_name: &str) -> bool {
+        let mut parts = column_name.splitn(2, '.');
+        match (parts.next(), parts.next()) {
+            (Some(qualifier), Some(name)) => {
+                qualifier == self.qualifier && name == self.name
+            }
+            (Some(name), None) => name == self.name,
+            _ => false,
+        }
+    }
+}
+
+crate fn resolve_joined_column(
+    columns: &[JoinedColumn],
+    column: &ColumnExpression,
+) -> Resolution {
+    let column_name = match column {
+        ColumnExpression::Column(column_name) => column_name,
+        ColumnExpression::Aggregate { .. } => {
+            return Err(From::from(format!(
+                "aggregate {} isn't allowed here",
+                column.display()
+            )))
+        }
+    };
+    let mut matches = columns
+        .iter()
+        .enumerate()
+        .filter(|(_, joined_column)| joined_column.matches(column_name));
+    match (matches.next(), matches.next()) {
+        (Some((offset, joined_column)), None) => {
+            Ok((offset, joined_column.column_type))
+        }
+        (Some(_), Some(_)) => Err(From::from(format!(
+            "column reference {} is ambiguous",
+            column_name
+        ))),
+        (None, _) => {
+            Err(From::from(format!("no column named {}", column_name)))
+        }
+    }
+}
+
+/// How a join finds the pairs of rows to test its ON condition against.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+crate enum JoinStrategy {
+    // Tests every pair
+    NestedLoop,
+    // Hashes one input by its join column, and probes that with the other's.
+    // Offsets are into the left rows and into the right table's rows.
+    Hash {
+        left_offset: usize,
+        right_offset: usize,
+    },
+    // Walks both inputs in step; they must already be ordered by their join
+    // columns, and the right one's must be unique (as a primary key is)
+    Merge {
+        left_offset: usize,
+        right_offset: usize,
+    },
+}
+
+crate struct JoinSubcommand<'a> {
+    crate kind: JoinKind,
+    crate table: &'a Table,
+    // evaluated against the rows joined so far, extended with a row of
+    // `table`
+    crate on: WhereSubcommand,
+    crate strategy: JoinStrategy,
+}
+
+impl<'a> JoinSubcommand<'a> {
+    /// Picks a strategy for joining `table` to rows (of width `left_width`)
+    /// ordered by the column at `left_order`: a merge join if the ON
+    /// condition equates that column with the table's primary key, a hash
+    /// join if it equates any other pair of columns, and nested loops
+    /// otherwise.
+    crate fn new(
+        kind: JoinKind,
+        table: &'a Table,
+        on: WhereSubcommand,
+        left_width: usize,
+        left_order: usize,
+    ) -> Self {
+        let equalities = on
+            .equalities()
+            .into_iter()
+            .filter_map(|(a, b)| {
+                if a < left_width && b >= left_width {
+                    Some((a, b - left_width))
+                } else if b < left_width && a >= left_width {
+                    Some((b, a - left_width))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let strategy = if let Some(&(left_offset, right_offset)) = equalities
+            .iter()
+            .find(|&&(left, right)| left == left_order && right == 0)
+        {
+            // (the table's rows are always in primary-key order)
+            JoinStrategy::Merge {
+                left_offset,
+                right_offset,
+            }
+        } else if let Some(&(left_offset, right_offset)) = equalities.first() {
+            JoinStrategy::Hash {
+                left_offset,
+                right_offset,
+            }
+        } else {
+            JoinStrategy::NestedLoop
+        };
+        Self {
+            kind,
+            table,
+            on,
+            strategy,
+        }
+    }
+
+    /// The offset of a (primary key) column by which the joined rows are
+    /// ordered, given the same for the left rows. Inner and left joins keep
+    /// the order of the left rows, and right joins that of the table.
+    crate fn order(&self, left_width: usize, left_order: usize) -> usize {
+        match self.kind {
+            JoinKind::Right => left_width,
+            _ => left_order,
+        }
+    }
+}
+
+/// Selects from a table joined with others: each joined row is the
+/// concatenation of a row from each table.
+crate struct JoinCommand<'a> {
+    crate first: &'a Table,
+    crate joins: Vec<JoinSubcommand<'a>>,
+    // the columns of the joined rows
+    crate columns: Vec<JoinedColumn>,
+    // the offset of a primary key column by which the joined rows come out
+    // ordered (which the planner can take advantage of in merge joins)
+    crate order: usize,
+    // If the joins have been reordered, where each of `columns` is in the
+    // rows they join (which are put back in the order of `columns` before
+    // they're filtered)
+    crate reordering: Option<Vec<usize>>,
+    // `None` selects every column
+    crate column_offsets: Option<Vec<usize>>,
+    crate filter: WhereSubcommand,
+    crate sort: Option<SortSubcommand>,
+    crate limit: Option<usize>,
+    crate offset: usize,
+}
+
+impl<'a> JoinCommand<'a> {
+    crate fn new(first: &'a Table, qualifier: String) -> Self {
+        let mut command = Self {
+            first,
+            joins: Vec::new(),
+            columns: Vec::new(),
+            order: 0,
+            reordering: None,
+            column_offsets: None,
+            filter: WhereSubcommand::new_unconditional(),
+            sort: None,
+            limit: None,
+            offset: 0,
+        };
+        command.add_columns(&first.schema, qualifier);
+        command
+    }
+
+    fn add_columns(&mut self, schema: &TableSchema, qualifier: String) {
+        self.columns.extend(schema.layout.iter().map(|column| JoinedColumn {
+            qualifier: qualifier.clone(),
+            name: column.name.clone(),
+            column_type: column.column_type,
+        }));
+    }
+
+    fn resolve(&self, column: &ColumnExpression) -> Resolution {
+        resolve_joined_column(&self.columns, column)
+    }
+
+    /// The ON condition can refer to the columns of `table` and of the tables
+    /// joined before it.
+    crate fn join(
+        mut self,
+        kind: JoinKind,
+        table: &'a Table,
+        qualifier: String,
+        on: Option<Condition>,
+    ) -> Result<Self, Box<dyn Error>> {
+        if self.columns.iter().any(|column| column.qualifier == qualifier) {
+            return Err(From::from(format!(
+                "table name {} is specified more than once",
+                qualifier
+            )));
+        }
+        let left_width = self.columns.len();
+        self.add_columns(&table.schema, qualifier);
+        let on = match (kind, on) {
+            (JoinKind::Cross, None) => WhereSubcommand::new_unconditional(),
+            (JoinKind::Cross, Some(_)) => {
+                return Err(From::from("CROSS JOIN can't have an ON clause"))
+            }
+            (_, Some(condition)) => {
+                let columns = &self.columns;
+                WhereSubcommand::compile(condition, &mut |column| {
+                    resolve_joined_column(columns, column)
+                })?
+            }
+            (kind, None) => {
+                return Err(From::from(format!(
+                    "{} needs an ON clause",
+                    kind.display()
+                )))
+            }
+        };
+        let join =
+            JoinSubcommand::new(kind, table, on, left_width, self.order);
+        self.order = join.order(left_width, self.order);
+        self.joins.push(join);
+        Ok(self)
+    }
+
+    /// Reorders a chain of inner (and cross) joins of tables that all have
+    /// statistics, to keep down the estimated numbers of rows joined along
+    /// the way: starting from the smallest table, each step joins whichever
+    /// table is estimated to lead to the fewest rows, testing every ON
+    /// condition as soon as the tables it refers to have been joined. (Outer
+    /// joins are left as they are, as their order matters.)
+    crate fn reordered_by_cost(mut self) -> Self {
+        let inner = self.joins.iter().all(|join| match join.kind {
+            JoinKind::Inner | JoinKind::Cross => true,
+            _ => false,
+        });
+        if self.joins.is_empty() || !inner || self.reordering.is_some() {
+            return self;
+        }
+        let mut tables = vec![self.first];
+        tables.extend(self.joins.iter().map(|join| join.table));
+        let statistics = match tables
+            .iter()
+            .map(|table| table.statistics.as_ref())
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(statistics) => statistics,
+            None => return self,
+        };
+        // where each table's columns start
+        let mut starts = Vec::new();
+        let mut width = 0;
+        for table in &tables {
+            starts.push(width);
+            width += table.schema.layout.len();
+        }
+        let table_of = |offset: usize| {
+            starts.iter().rposition(|&start| start <= offset).unwrap()
+        };
+        let column_statistics = |offset: usize| {
+            let table = table_of(offset);
+            (statistics[table], offset - starts[table])
+        };
+        let conditions = self
+            .joins
+            .iter()
+            .flat_map(|join| join.on.conjuncts())
+            .collect::<Vec<_>>();
+        let referenced = conditions
+            .iter()
+            .map(|condition| {
+                condition
+                    .column_offsets()
+                    .into_iter()
+                    .map(table_of)
+                    .collect::<BTreeSet<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let row_count = |table: usize| tables[table].rows.len() as f64;
+        let first = (0..tables.len())
+            .min_by_key(|&table| tables[table].rows.len())
+            .unwrap();
+        let mut order = vec![first];
+        // the conditions tested by each join
+        let mut steps = Vec::new();
+        let mut tested = vec![false; conditions.len()];
+        let mut estimate = row_count(first);
+        while order.len() < tables.len() {
+            let mut best: Option<(f64, usize, Vec<usize>)> = None;
+            for table in 0..tables.len() {
+                if order.contains(&table) {
+                    continue;
+                }
+                let testable = (0..conditions.len())
+                    .filter(|&condition| {
+                        !tested[condition]
+                            && referenced[condition].iter().all(|referenced| {
+                                *referenced == table
+                                    || order.contains(referenced)
+                            })
+                    })
+                    .collect::<Vec<_>>();
+                let joined = estimate
+                    * row_count(table)
+                    * testable
+                        .iter()
+                        .map(|&condition| {
+                            let condition = conditions[condition];
+                            selectivity(condition, &column_statistics)
+                        })
+                        .product::<f64>();
+                if best.as_ref().map_or(true, |(best, _, _)| joined < *best) {
+                    best = Some((joined, table, testable));
+                }
+            }
+            let (joined, table, testable) = best.unwrap();
+            for &condition in &testable {
+                tested[condition] = true;
+            }
+            order.push(table);
+            steps.push(testable);
+            estimate = joined;
+        }
+        if order.iter().enumerate().all(|(i, &table)| i == table) {
+            return self;
+        }
+
+        let mut reordering = vec![0; width];
+        let mut reordered_width = 0;
+        for &table in &order {
+            let table_width = tables[table].schema.layout.len();
+            for column in 0..table_width {
+                reordering[starts[table] + column] = reordered_width + column;
+            }
+            reordered_width += table_width;
+        }
+        let steps = steps
+            .into_iter()
+            .map(|testable| {
+                WhereSubcommand::all(
+                    testable
+                        .into_iter()
+                        .map(|condition| {
+                            conditions[condition].remap(&reordering)
+                        })
+                        .collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut joins = Vec::new();
+        let mut left_width = tables[first].schema.layout.len();
+        let mut left_order = 0;
+        for (&table, on) in order[1..].iter().zip(steps) {
+            let kind = if on.is_unconditional() {
+                JoinKind::Cross
+            } else {
+                JoinKind::Inner
+            };
+            let join = JoinSubcommand::new(
+                kind,
+                tables[table],
+                on,
+                left_width,
+                left_order,
+            );
+            left_order = join.order(left_width, left_order);
+            left_width += tables[table].schema.layout.len();
+            joins.push(join);
+        }
+        self.first = tables[first];
+        self.joins = joins;
+        self.order = reordering
+            .iter()
+            .position(|&offset| offset == left_order)
+            .unwrap();
+        self.reordering = Some(reordering);
+        self
+    }
+
+    crate fn projecting(
+        mut self,
+        column_names: Vec<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut column_offsets = Vec::new();
+        for column_name in column_names {
+            let column = ColumnExpression::Column(column_name);
+            column_offsets.push(self.resolve(&column)?.0);
+        }
+        self.column_offsets = Some(column_offsets);
+        Ok(self)
+    }
+
+    crate fn filtered_by(
+        mut self,
+        condition: Condition,
+    ) -> Result<Self, Box<dyn Error>> {
+        self.filter = WhereSubcommand::compile(condition, &mut |column| {
+            self.resolve(column)
+        })?;
+        Ok(self)
+    }
+
+    crate fn sorted_by(
+        mut self,
+        sort_keys: Vec<SortKey>,
+    ) -> Result<Self, Box<dyn Error>> {
+        self.sort = Some(SortSubcommand::compile(sort_keys, &mut |column| {
+            self.resolve(column)
+        })?);
+        Ok(self)
+    }
+
+    crate fn limited_to(mut self, limit: usize, offset: usize) -> Self {
+        self.limit = Some(limit);
+        self.offset = offset;
+        self
+    }
+
+    crate fn explain(&self) -> PlanNode {
+        let column_names = self
+            .columns
+            .iter()
+            .map(|column| format!("{}.{}", column.qualifier, column.name))
+            .collect::<Vec<_>>();
+        // the columns in the order the joins see them
+        let mut joined_columns = self.columns.iter().collect::<Vec<_>>();
+        let mut joined_column_names = column_names.clone();
+        if let Some(reordering) = &self.reordering {
+            for (column, &offset) in reordering.iter().enumerate() {
+                joined_columns[offset] = &self.columns[column];
+                joined_column_names[offset] = column_names[column].clone();
+            }
+        }
+        let scan = |qualifier: &str| {
+            PlanNode::new("Table Scan", qualifier.to_owned(), Vec::new())
+        };
+        let mut node = scan(&joined_columns[0].qualifier);
+        let mut width = self.first.schema.layout.len();
+        for join in &self.joins {
+            let qualifier = &joined_columns[width].qualifier;
+            // (e.g., "Hash Left Join")
+            let operation = format!(
+                "{} {}",
+                match join.strategy {
+                    JoinStrategy::NestedLoop => "Nested Loop",
+                    JoinStrategy::Hash { .. } => "Hash",
+                    JoinStrategy::Merge { .. } => "Merge",
+                },
+                match join.kind {
+                    JoinKind::Inner => "Inner Join",
+                    JoinKind::Left => "Left Join",
+                    JoinKind::Right => "Right Join",
+                    JoinKind::Cross => "Cross Join",
+                }
+            );
+            let detail = if join.on.is_unconditional() {
+                qualifier.clone()
+            } else {
+                let on = join.on.display(&joined_column_names);
+                format!("{} ON {}", qualifier, on)
+            };
+            node = PlanNode::new(
+                &operation,
+                detail,
+                vec![node, scan(qualifier)],
+            );
+            width += join.table.schema.layout.len();
+        }
+        if !self.filter.is_unconditional() {
+            let filter = self.filter.display(&column_names);
+            node = PlanNode::new("Filter", filter, vec![node]);
+        }
+        if let Some(sort) = &self.sort {
+            let sort = sort.display(&column_names);
+            node = PlanNode::new("Sort", sort, vec![node]);
+        }
+        if let Some(limit) = self.limit {
+            node = PlanNode::new(
+                "Limit",
+                display_limit(limit, self.offset),
+                vec![node],
+            );
+        }
+        let projected = match &self.column_offsets {
+            Some(column_offsets) => column_offsets
+                .iter()
+                .map(|&offset| column_names[offset].clone())
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => "*".to_owned(),
+        };
+        PlanNode::new("Project", projected, vec![node])
+    }
+
+    /// The command's plan as operators, with an instrument attached to each
+    /// step of it (down the left sides of the joins) if the plan is being
+    /// analyzed.
+    crate fn into_operator(
+        self,
+        instruments: &mut Instruments,
+    ) -> BoxedOperator<'a, Vec<&'a Chamber>> {
+        let scan = Scan::new(self.first, AccessPath::TableScan);
+        let mut operator = instruments.attach(Box::new(Map::new(
+            Box::new(scan),
+            |row: &'a Row| row.0.iter().collect(),
+        )));
+        let mut width = self.first.schema.layout.len();
+        for join in self.joins {
+            let table_width = join.table.schema.layout.len();
+            operator =
+                instruments.attach(Box::new(Join::new(operator, join, width)));
+            width += table_width;
+        }
+        if let Some(reordering) = self.reordering {
+            operator = Box::new(Map::new(operator, move |row| {
+                reordering.iter().map(|&offset| row[offset]).collect()
+            }));
+        }
+        if !self.filter.is_unconditional() {
+            let filter = self.filter;
+            operator = instruments.attach(Box::new(Filter::new(
+                operator,
+                move |row: &Vec<&Chamber>| filter.evaluate(&row[..]),
+            )));
+        }
+        if let Some(sort) = self.sort {
+            operator = instruments.attach(Box::new(Sort::new(
+                operator,
+                move |left: &Vec<&Chamber>, right: &Vec<&Chamber>| {
+                    sort.compare(&left[..], &right[..])
+                },
+            )));
+        }
+        if let Some(limit) = self.limit {
+            operator = instruments.attach(Box::new(Limit::new(
+                operator,
+                limit,
+                self.offset,
+            )));
+        }
+        if let Some(column_offsets) = self.column_offsets {
+            operator = Box::new(Map::new(operator, move |row| {
+                column_offsets.iter().map(|&offset| row[offset]).collect()
+            }));
+        }
+        instruments.attach(operator)
+    }
+
+    /// The names (without their qualifiers) and types of the selected
+    /// columns.
+    crate fn columns(&self) -> Vec<ResultColumn> {
+        let column = |joined_column: &JoinedColumn| ResultColumn {
+            name: joined_column.name.clone(),
+            column_type: joined_column.column_type,
+        };
+        match &self.column_offsets {
+            Some(column_offsets) => column_offsets
+                .iter()
+                .map(|&offset| column(&self.columns[offset]))
+                .collect(),
+            None => self.columns.iter().map(column).collect(),
+        }
+    }
+
+    // (all at once, for tests)
+    #[cfg(test)]
+    crate fn execute(self) -> Vec<Vec<&'a Chamber>> {
+        Rows::new(self.into_operator(&mut Instruments::off()))
+            .collect::<Result<_, _>>()
+            .expect("joins shouldn't fail")
+    }
+
+    /// Executes the command, returning its plan along with the rows, with
+    /// what each step of the plan did.
+    crate fn analyze(self) -> (Vec<Vec<&'a Chamber>>, PlanNode) {
+        let mut plan = self.explain();
+        let mut instruments = Instruments::on();
+        let rows = Rows::new(self.into_operator(&mut instruments))
+            .collect::<Result<_, _>>()
+            .expect("joins shouldn't fail");
+        plan.record(instruments.readings());
+        (rows, plan)
+    }
+}
+
+#[derive(Debug)]
+crate struct AggregateSubcommand {
+    crate function: AggregateFunction,
+    // the type of the aggregate's result
+    crate column_type: ColumnType,
+}
+
+impl AggregateSubcommand {
+    crate fn accumulator(&self) -> Accumulator {
+        Accumulator {
+            function: self.function,
+            count: 0,
+            sum: 0,
+            extreme: None,
+        }
+    }
+}
+
+/// An aggregate's value over the chambers added to it so far.
+crate struct Accumulator {
+    function: AggregateFunction,
+    // how many (non-NULL) chambers have been added, and the sum of them (for
+    // SUM and AVG), or the least or greatest of them (for MIN and MAX)
+    count: isize,
+    sum: isize,
+    extreme: Option<Chamber>,
+}
+
+impl Accumulator {
+    /// NULLs are ignored.
+    crate fn add(&mut self, chamber: &Chamber) -> Result<(), Box<dyn Error>> {
+        if chamber.is_null() {
+            return Ok(());
+        }
+        self.count += 1;
+        match self.function {
+            AggregateFunction::Count => {}
+            AggregateFunction::Sum | AggregateFunction::Average => {
+                let i = match *chamber {
+                    Chamber::Integer(i) => i,
+                    _ => panic!(
+                        "SUM and AVG arguments should have been type-checked"
+                    ),
+                };
+                let function = self.function;
+                self.sum = self.sum.checked_add(i).ok_or_else(|| {
+                    format!("integer overflow in {}", function.display())
+                })?;
+            }
+            AggregateFunction::Min | AggregateFunction::Max => {
+                let wanted = match self.function {
+                    AggregateFunction::Min => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                let replace = match &self.extreme {
+                    Some(extreme) => {
+                        chamber.partial_cmp(extreme) == Some(wanted)
+                    }
+                    None => true,
+                };
+                if replace {
+                    self.extreme = Some(chamber.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Aggregates other than `COUNT` over no (non-NULL) values are NULL.
+    crate fn finish(self) -> Chamber {
+        match self.function {
+            AggregateFunction::Count => Chamber::Integer(self.count),
+            _ if self.count == 0 => Chamber::Null,
+            AggregateFunction::Sum => Chamber::Integer(self.sum),
+            // (integer division: we don't have a fractional type)
+            AggregateFunction::Average => {
+                Chamber::Integer(self.sum / self.count)
+            }
+            AggregateFunction::Min | AggregateFunction::Max => {
+                self.extreme.unwrap_or(Chamber::Null)
+            }
+        }
+    }
+}
+
+/// Folds the rows matched by a table scan into one result row per group.
+crate struct AggregateCommand<'a> {
+    // The scan projects the grouping columns, followed by the argument column
+    // of each aggregate (in order), so that aggregate `i` folds column
+    // `group_width + i` of the scanned rows
+    crate scan: SelectCommand<'a>,
+    crate group_width: usize,
+    crate aggregates: Vec<AggregateSubcommand>,
+    // Each group is summarized as a row of its grouping columns followed by
+    // its aggregate values; HAVING filters these rows, and the result
+    // projects from them
+    crate having: WhereSubcommand,
+    crate output_offsets: Vec<usize>,
+    crate limit: Option<usize>,
+    crate offset: usize,
+    // the names of the columns of the group rows (for EXPLAIN)
+    crate group_column_names: Vec<String>,
+}
+
+impl<'a> AggregateCommand<'a> {
+    crate fn new(
+        table: &'a Table,
+        column_expressions: Vec<ColumnExpression>,
+        where_clause: WhereSubcommand,
+        group_by: Vec<String>,
+        having: Option<Condition>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let schema = &table.schema;
+        let group_offsets = column_names_to_offsets(schema, &group_by)?;
+        let group_width = group_offsets.len();
+        let mut group_column_names = group_by.clone();
+        let mut column_names = group_by;
+        let mut aggregate_expressions = Vec::new();
+        let mut aggregates = Vec::new();
+
+        // Finds the offset of a column expression in the group rows, adding
+        // an aggregate if it's one we haven't seen yet
+        let mut resolve = |column: &ColumnExpression| -> Resolution {
+            let (function, column_name) = match column {
+                ColumnExpression::Column(column_name) => {
+                    let (offset, column_type) =
+                        resolve_table_column(schema, column)?;
+                    let position =
+                        group_offsets.iter().position(|&o| o == offset);
+                    return match position {
+                        Some(i) => Ok((i, column_type)),
+                        None => Err(From::from(format!(
+                            "column {} must appear in the GROUP BY clause or \
+                             be used in an aggregate function",
+                            column_name
+                        ))),
+                    };
+                }
+                ColumnExpression::Aggregate {
+                    function,
+                    column_name,
+                } => (*function, column_name),
+            };
+            if let Some(i) = aggregate_expressions
+                .iter()
+                .position(|expression| expression == column)
+            {
+                let aggregate: &AggregateSubcommand = &aggregates[i];
+                return Ok((group_width + i, aggregate.column_type));
+            }
+            let column_name = match column_name {
+                Some(column_name) => column_name.clone(),
+                // `COUNT(*)` doesn't care which column it counts
+                None if function == AggregateFunction::Count => {
+                    "pk".to_owned()
+                }
+                None => {
+                    return Err(From::from(format!(
+                        "{} needs a column argument",
+                        function.display()
+                    )))
+                }
+            };
+            let argument_offset =
+                column_names_to_offsets(schema, &[column_name])?[0];
+            let argument = &schema.layout[argument_offset];
+            let column_type = match function {
+                AggregateFunction::Count => ColumnType::Integer,
+                AggregateFunction::Sum | AggregateFunction::Average => {
+                    if argument.column_type != ColumnType::Integer {
+                        return Err(From::from(format!(
+                            "can't take {} of {:?} column {}",
+                            function.display(),
+                            argument.column_type,
+                            argument.name
+                        )));
+                    }
+                    ColumnType::Integer
+                }
+                AggregateFunction::Min | AggregateFunction::Max => {
+                    argument.column_type
+                }
+            };
+            column_names.push(argument.name.clone());
+            aggregate_expressions.push(column.clone());
+            aggregates.push(AggregateSubcommand {
+                function,
+                column_type,
+            });
+            Ok((group_width + aggregates.len() - 1, column_type))
+        };
+
+        let mut output_offsets = Vec::new();
+        for column_expression in &column_expressions {
+            output_offsets.push(resolve(column_expression)?.0);
+        }
+        let having = match having {
+            Some(condition) => {
+                WhereSubcommand::compile(condition, &mut resolve)?
+            }
+            None => WhereSubcommand::new_unconditional(),
+        };
+        group_column_names.extend(
+            aggregate_expressions
+                .iter()
+                .map(|expression| expression.display()),
+        );
+
+        Ok(Self {
+            scan: SelectCommand::new(table, column_names, where_clause)?,
+            group_width,
+            aggregates,
+            having,
+            output_offsets,
+            limit: None,
+            offset: 0,
+            group_column_names,
+        })
+    }
+
+    crate fn limited_to(mut self, limit: usize, offset: usize) -> Self {
+        self.limit = Some(limit);
+        self.offset = offset;
+        self
+    }
+
+    crate fn explain(&self, table_name: &str) -> PlanNode {
+        let (groups, aggregates) =
+            self.group_column_names.split_at(self.group_width);
+        let detail = match (groups.is_empty(), aggregates.is_empty()) {
+            (true, _) => aggregates.join(", "),
+            (false, true) => format!("GROUP BY {}", groups.join(", ")),
+            (false, false) => format!(
+                "{} GROUP BY {}",
+                aggregates.join(", "),
+                groups.join(", ")
+            ),
+        };
+        let mut node = PlanNode::new(
+            "Aggregate",
+            detail,
+            vec![self.scan.explain(table_name)],
+        );
+        if !self.having.is_unconditional() {
+            let having = self.having.display(&self.group_column_names);
+            node = PlanNode::new("Filter", having, vec![node]);
+        }
+        if let Some(limit) = self.limit {
+            node = PlanNode::new(
+                "Limit",
+                display_limit(limit, self.offset),
+                vec![node],
+            );
+        }
+        let projected = self
+            .output_offsets
+            .iter()
+            .map(|&offset| self.group_column_names[offset].clone())
+            .collect::<Vec<_>>();
+        PlanNode::new("Project", projected.join(", "), vec![node])
+    }
+
+    /// The command's plan as operators, with an instrument attached to each
+    /// step of it if the plan is being analyzed.
+    crate fn into_operator(
+        self,
+        instruments: &mut Instruments,
+    ) -> BoxedOperator<'a, Vec<Chamber>> {
+        let scan = self.scan.into_operator(instruments);
+        let mut operator = instruments.attach(Box::new(Aggregate::new(
+            scan,
+            self.group_width,
+            self.aggregates,
+        )));
+        if !self.having.is_unconditional() {
+            let having = self.having;
+            operator = instruments.attach(Box::new(Filter::new(
+                operator,
+                move |group_row: &Vec<Chamber>| {
+                    having.evaluate(&group_row[..])
+                },
+            )));
+        }
+        if let Some(limit) = self.limit {
+            operator = instruments.attach(Box::new(Limit::new(
+                operator,
+                limit,
+                self.offset,
+            )));
+        }
+        let output_offsets = self.output_offsets;
+        instruments.attach(Box::new(Map::new(
+            operator,
+            move |group_row: Vec<Chamber>| {
+                output_offsets
+                    .iter()
+                    .map(|&offset| group_row[offset].clone())
+                    .collect()
+            },
+        )))
+    }
+
+    /// The names and types of the selected grouping columns and aggregates.
+    crate fn columns(&self) -> Vec<ResultColumn> {
+        self.output_offsets
+            .iter()
+            .map(|&offset| {
+                let column_type = if offset < self.group_width {
+                    let table_offset = self.scan.column_offsets[offset];
+                    self.scan.table.schema.layout[table_offset].column_type
+                } else {
+                    self.aggregates[offset - self.group_width].column_type
+                };
+                ResultColumn {
+                    name: self.group_column_names[offset].clone(),
+                    column_type,
+                }
+            })
+            .collect()
+    }
+
+    /// A row per group, as they're pulled.
+    crate fn rows(self) -> Rows<'a, Vec<Chamber>> {
+        Rows::new(self.into_operator(&mut Instruments::off()))
+    }
+
+    // (all at once, for tests)
+    #[cfg(test)]
+    crate fn execute(self) -> Result<Vec<Vec<Chamber>>, Box<dyn Error>> {
+        self.rows().collect()
+    }
+
+    /// Executes the command, returning its plan along with the rows, with
+    /// what each step of the plan did.
+    crate fn analyze(
+        self,
+        table_name: &str,
+    ) -> Result<(Vec<Vec<Chamber>>, PlanNode), Box<dyn Error>> {
+        let mut plan = self.explain(table_name);
+        let mut instruments = Instruments::on();
+        let rows = Rows::new(self.into_operator(&mut instruments))
+            .collect::<Result<_, _>>()?;
+        plan.record(instruments.readings());
+        Ok((rows, plan))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::IndexKind;
+    use crate::table::*;
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn example_table() -> Table {
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        schema
+            .add_column("year".to_owned(), ColumnType::Integer)
+            .unwrap();
+        let mut table = Table::new(schema);
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("Men Trapped In Men's Bodies".to_owned()),
+                    Chamber::Integer(2013),
+                ]))
+                .unwrap(),
+        );
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("Galileo's Middle Finger".to_owned()),
+                    Chamber::Integer(2015),
+                ]))
+                .unwrap(),
+        );
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("Thing Explainer".to_owned()),
+                    Chamber::Integer(2015),
+                ]))
+                .unwrap(),
+        );
+        table
+    }
+
+    #[test]
+    fn concerning_converting_column_names_to_offsets() {
+        let table = example_table();
+        assert_eq!(
+            column_names_to_offsets(
+                &table.schema,
+                &["title".to_owned(), "year".to_owned()]
+            ).unwrap(),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn concerning_converting_column_names_to_offsets_out_of_order() {
+        let table = example_table();
+        assert_eq!(
+            column_names_to_offsets(
+                &table.schema,
+                &["year".to_owned(), "title".to_owned(), "year".to_owned()]
+            ).unwrap(),
+            vec![2, 1, 2]
+        );
+    }
+
+    #[test]
+    fn concerning_select_by_primary_key() {
+        let table = example_table();
+        let where_clause = WhereSubcommand::new_column_equality(
+            &table.schema,
+            "pk".to_owned(),
+            Chamber::Key(2),
+        ).unwrap();
+        let select_command = SelectCommand::new(
+            &table,
+            vec!["title".to_owned()],
+            where_clause,
+        ).unwrap();
+        assert_eq!(
+            select_command.access_path,
+            AccessPath::PrimaryKeyLookup { pk: 2 }
+        );
+        let result_rows = select_command.execute();
+        assert_eq!(result_rows.len(), 1);
+        assert_eq!(
+            &Chamber::String("Galileo's Middle Finger".to_owned()),
+            result_rows[0][0],
+        );
+    }
+
+    #[test]
+    fn concerning_primary_key_ranges() {
+        let table = example_table();
+        let select = |where_clause| {
+            SelectCommand::new(&table, vec!["pk".to_owned()], where_clause)
+                .unwrap()
+        };
+        let comparison = |operator, pk| {
+            WhereSubcommand::new_comparison(
+                &table.schema,
+                "pk".to_owned(),
+                operator,
+                Chamber::Key(pk),
+            ).unwrap()
+        };
+
+        let command = select(comparison(ComparisonOperator::Greater, 1));
+        assert_eq!(
+            command.access_path,
+            AccessPath::PrimaryKeyRange {
+                lower: Bound::Excluded(1),
+                upper: Bound::Unbounded,
+            }
+        );
+        assert_eq!(
+            command.execute(),
+            vec![vec![&Chamber::Key(2)], vec![&Chamber::Key(3)]]
+        );
+        let command = select(WhereSubcommand::And(
+            Box::new(comparison(ComparisonOperator::GreaterOrEqual, 2)),
+            Box::new(comparison(ComparisonOperator::LessOrEqual, 2)),
+        ));
+        assert_eq!(command.execute(), vec![vec![&Chamber::Key(2)]]);
+        // bounds that cross select nothing (and don't panic)
+        let command = select(WhereSubcommand::And(
+            Box::new(comparison(ComparisonOperator::Greater, 2)),
+            Box::new(comparison(ComparisonOperator::Less, 2)),
+        ));
+        assert!(command.execute().is_empty());
+        let command = select(comparison(ComparisonOperator::Equal, 7));
+        assert!(command.execute().is_empty());
+        let command = select(comparison(ComparisonOperator::NotEqual, 2));
+        assert_eq!(command.access_path, AccessPath::TableScan);
+    }
+
+    #[test]
+    fn concerning_select_by_integer() {
+        let table = example_table();
+        let where_clause = WhereSubcommand::new_column_equality(
+            &table.schema,
+            "year".to_owned(),
+            Chamber::Integer(2015),
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["title".to_owned()],
+            where_clause,
+        ).unwrap();
+        let result_rows = select_command.execute();
+        assert_eq!(result_rows.len(), 2);
+        assert_eq!(
+            vec![
+                vec![&Chamber::String(
+                    "Galileo's Middle Finger".to_owned(),
+                )],
+                vec![&Chamber::String("Thing Explainer".to_owned())],
+            ],
+            result_rows
+        );
+    }
+
+    #[test]
+    fn concerning_select_by_compound_condition() {
+        let table = example_table();
+        // year = 2013 OR NOT (year = 2015 AND title = 'Thing Explainer')
+        let condition = Condition::Or(
+            Box::new(Condition::Comparison {
+                column: ColumnExpression::Column("year".to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::Integer(2013),
+            }),
+            Box::new(Condition::Not(Box::new(Condition::And(
+                Box::new(Condition::Comparison {
+                    column: ColumnExpression::Column("year".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::Integer(2015),
+                }),
+                Box::new(Condition::Comparison {
+                    column: ColumnExpression::Column("title".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::String("Thing Explainer".to_owned()),
+                }),
+            )))),
+        );
+        let where_clause =
+            WhereSubcommand::new(&table.schema, condition).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["title".to_owned()],
+            where_clause,
+        ).unwrap();
+        assert_eq!(
+            vec![
+                vec![&Chamber::String(
+                    "Men Trapped In Men's Bodies".to_owned(),
+                )],
+                vec![&Chamber::String(
+                    "Galileo's Middle Finger".to_owned(),
+                )],
+            ],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_conditions_on_unknown_columns() {
+        let table = example_table();
+        let condition = Condition::And(
+            Box::new(Condition::Comparison {
+                column: ColumnExpression::Column("year".to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::Integer(2015),
+            }),
+            Box::new(Condition::Comparison {
+                column: ColumnExpression::Column("author".to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::String("Randall Munroe".to_owned()),
+            }),
+        );
+        assert!(WhereSubcommand::new(&table.schema, condition).is_err());
+    }
+
+    #[test]
+    fn concerning_select_by_range() {
+        let table = example_table();
+        let where_clause = WhereSubcommand::new_comparison(
+            &table.schema,
+            "year".to_owned(),
+            ComparisonOperator::GreaterOrEqual,
+            Chamber::Integer(2014),
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            where_clause,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![&Chamber::Key(2)], vec![&Chamber::Key(3)]],
+            select_command.execute()
+        );
+
+        let where_clause = WhereSubcommand::new_comparison(
+            &table.schema,
+            "pk".to_owned(),
+            ComparisonOperator::Less,
+            Chamber::Integer(2),
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["year".to_owned()],
+            where_clause,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![&Chamber::Integer(2013)]],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_comparisons_with_mismatched_types() {
+        let table = example_table();
+        let error = WhereSubcommand::new_comparison(
+            &table.schema,
+            "year".to_owned(),
+            ComparisonOperator::Greater,
+            Chamber::String("2014".to_owned()),
+        ).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "can't compare Integer column year to String value 2014"
+        );
+    }
+
+    #[test]
+    fn concerning_three_valued_logic() {
+        let mut table = example_table();
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("Untitled".to_owned()),
+                    Chamber::Null,
+                ]))
+                .unwrap(),
+        );
+        let year = || ColumnExpression::Column("year".to_owned());
+        let year_is = |operator, year| Condition::Comparison {
+            column: ColumnExpression::Column("year".to_owned()),
+            operator,
+            value: year,
+        };
+        let select_pks = |condition| {
+            SelectCommand::new_table_scan(
+                &table,
+                vec!["pk".to_owned()],
+                WhereSubcommand::new(&table.schema, condition).unwrap(),
+            ).unwrap()
+                .execute()
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let not = |condition| Condition::Not(Box::new(condition));
+
+        // NOT UNKNOWN is still UNKNOWN, so the NULL year is in neither
+        assert_eq!(
+            select_pks(year_is(
+                ComparisonOperator::Equal,
+                Chamber::Integer(2015)
+            )),
+            vec![Chamber::Key(2), Chamber::Key(3)]
+        );
+        assert_eq!(
+            select_pks(not(year_is(
+                ComparisonOperator::Equal,
+                Chamber::Integer(2015)
+            ))),
+            vec![Chamber::Key(1)]
+        );
+        // nothing equals NULL, not even NULL
+        assert!(
+            select_pks(year_is(ComparisonOperator::Equal, Chamber::Null))
+                .is_empty()
+        );
+        assert!(
+            select_pks(not(year_is(
+                ComparisonOperator::Equal,
+                Chamber::Null
+            ))).is_empty()
+        );
+        assert_eq!(
+            select_pks(Condition::IsNull(year())),
+            vec![Chamber::Key(4)]
+        );
+        assert_eq!(
+            select_pks(not(Condition::IsNull(year()))),
+            vec![Chamber::Key(1), Chamber::Key(2), Chamber::Key(3)]
+        );
+        // TRUE OR UNKNOWN is TRUE; FALSE AND UNKNOWN is FALSE
+        assert_eq!(
+            select_pks(Condition::Or(
+                Box::new(year_is(
+                    ComparisonOperator::Less,
+                    Chamber::Integer(2014),
+                )),
+                Box::new(Condition::Comparison {
+                    column: ColumnExpression::Column("title".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::String("Untitled".to_owned()),
+                }),
+            )),
+            vec![Chamber::Key(1), Chamber::Key(4)]
+        );
+        assert_eq!(
+            select_pks(not(Condition::And(
+                Box::new(year_is(
+                    ComparisonOperator::Less,
+                    Chamber::Integer(2014),
+                )),
+                Box::new(Condition::Comparison {
+                    column: ColumnExpression::Column("title".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::String("Thing Explainer".to_owned()),
+                }),
+            ))),
+            vec![
+                Chamber::Key(1),
+                Chamber::Key(2),
+                Chamber::Key(3),
+                Chamber::Key(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn concerning_aggregates_over_nulls() {
+        let mut table = example_table();
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("Untitled".to_owned()),
+                    Chamber::Null,
+                ]))
+                .unwrap(),
+        );
+        let aggregate = |function, column_name: Option<&str>| {
+            ColumnExpression::Aggregate {
+                function,
+                column_name: column_name.map(|name| name.to_owned()),
+            }
+        };
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![
+                aggregate(AggregateFunction::Count, None),
+                aggregate(AggregateFunction::Count, Some("year")),
+                aggregate(AggregateFunction::Min, Some("year")),
+                aggregate(AggregateFunction::Average, Some("year")),
+            ],
+            WhereSubcommand::new_unconditional(),
+            vec![],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![
+                Chamber::Integer(4),
+                Chamber::Integer(3),
+                Chamber::Integer(2013),
+                Chamber::Integer(2014),
+            ]],
+            aggregate_command.execute().unwrap()
+        );
+
+        // NULLs group together
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![
+                ColumnExpression::Column("year".to_owned()),
+                aggregate(AggregateFunction::Max, Some("year")),
+            ],
+            WhereSubcommand::new_unconditional(),
+            vec!["year".to_owned()],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![
+                vec![Chamber::Integer(2013), Chamber::Integer(2013)],
+                vec![Chamber::Integer(2015), Chamber::Integer(2015)],
+                vec![Chamber::Null, Chamber::Null],
+            ],
+            aggregate_command.execute().unwrap()
+        );
+    }
+
+    #[test]
+    fn concerning_sorting_by_unselected_columns() {
+        let table = example_table();
+        let sort = SortSubcommand::new(
+            &table.schema,
+            vec![
+                SortKey {
+                    column_name: "year".to_owned(),
+                    order: SortOrder::Descending,
+                },
+                SortKey {
+                    column_name: "title".to_owned(),
+                    order: SortOrder::Descending,
+                },
+            ],
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).unwrap()
+            .sorted_by(sort);
+        assert_eq!(
+            vec![
+                vec![&Chamber::Key(3)],
+                vec![&Chamber::Key(2)],
+                vec![&Chamber::Key(1)],
+            ],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_sort_stability() {
+        let table = example_table();
+        let sort = SortSubcommand::new(
+            &table.schema,
+            vec![SortKey {
+                column_name: "year".to_owned(),
+                order: SortOrder::Descending,
+            }],
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).unwrap()
+            .sorted_by(sort);
+        assert_eq!(
+            vec![
+                vec![&Chamber::Key(2)],
+                vec![&Chamber::Key(3)],
+                vec![&Chamber::Key(1)],
+            ],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_limits_and_offsets() {
+        let table = example_table();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).unwrap()
+            .limited_to(1, 1);
+        assert_eq!(vec![vec![&Chamber::Key(2)]], select_command.execute());
+
+        let sort = SortSubcommand::new(
+            &table.schema,
+            vec![SortKey {
+                column_name: "title".to_owned(),
+                order: SortOrder::Ascending,
+            }],
+        ).unwrap();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).unwrap()
+            .sorted_by(sort)
+            .limited_to(5, 1);
+        assert_eq!(
+            vec![vec![&Chamber::Key(1)], vec![&Chamber::Key(3)]],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_early_termination_of_limited_scans() {
+        let table = example_table();
+        let rows_examined = Rc::new(Cell::new(0));
+        let mut select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["pk".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).unwrap()
+            .limited_to(1, 0);
+        let counter = rows_examined.clone();
+        select_command.filter = Box::new(move |_row| {
+            counter.set(counter.get() + 1);
+            true
+        });
+        assert_eq!(vec![vec![&Chamber::Key(1)]], select_command.execute());
+        assert_eq!(rows_examined.get(), 1);
+    }
+
+    #[test]
+    fn concerning_index_scans() {
+        let mut table = example_table();
+        table
+            .create_index("years".to_owned(), vec![2], false, IndexKind::BTree)
+            .unwrap();
+        table
+            .create_index(
+                "pairs".to_owned(),
+                vec![2, 1],
+                false,
+                IndexKind::BTree,
+            )
+            .unwrap();
+        let comparison = |table: &Table, column: &str, operator, value| {
+            WhereSubcommand::new_comparison(
+                &table.schema,
+                column.to_owned(),
+                operator,
+                value,
+            ).unwrap()
+        };
+        fn select(
+            table: &Table,
+            where_clause: WhereSubcommand,
+        ) -> SelectCommand<'_> {
+            SelectCommand::new(table, vec!["pk".to_owned()], where_clause)
+                .unwrap()
+        }
+
+        let command = select(
+            &table,
+            WhereSubcommand::And(
+                Box::new(comparison(
+                    &table,
+                    "title",
+                    ComparisonOperator::Equal,
+                    Chamber::String("Thing Explainer".to_owned()),
+                )),
+                Box::new(comparison(
+                    &table,
+                    "year",
+                    ComparisonOperator::Equal,
+                    Chamber::Integer(2015),
+                )),
+            ),
+        );
+        assert_eq!(
+            command.access_path,
+            AccessPath::IndexScan {
+                index_offset: 1,
+                prefix: vec![
+                    Chamber::Integer(2015),
+                    Chamber::String("Thing Explainer".to_owned()),
+                ],
+                lower: Bound::Unbounded,
+                upper: Bound::Unbounded,
+            }
+        );
+        assert_eq!(command.execute(), vec![vec![&Chamber::Key(3)]]);
+
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "year",
+                ComparisonOperator::Greater,
+                Chamber::Integer(2013),
+            ),
+        );
+        assert_eq!(
+            command.access_path,
+            AccessPath::IndexScan {
+                index_offset: 0,
+                prefix: vec![],
+                lower: Bound::Excluded(Chamber::Integer(2013)),
+                upper: Bound::Unbounded,
+            }
+        );
+        assert_eq!(
+            command.execute(),
+            vec![vec![&Chamber::Key(2)], vec![&Chamber::Key(3)]]
+        );
+        // (rows come in the order of the index, which is streamed)
+        table.place(
+            table
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("What If?".to_owned()),
+                    Chamber::Integer(2014),
+                ]))
+                .unwrap(),
+        );
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "year",
+                ComparisonOperator::Greater,
+                Chamber::Integer(2013),
+            ),
+        ).limited_to(2, 0);
+        assert_eq!(
+            command.execute(),
+            vec![vec![&Chamber::Key(4)], vec![&Chamber::Key(2)]]
+        );
+
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "year",
+                ComparisonOperator::LessOrEqual,
+                Chamber::Integer(2013),
+            ),
+        );
+        assert_eq!(command.execute(), vec![vec![&Chamber::Key(1)]]);
+
+        // no index starts with the title …
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "title",
+                ComparisonOperator::Equal,
+                Chamber::String("Thing Explainer".to_owned()),
+            ),
+        );
+        assert_eq!(command.access_path, AccessPath::TableScan);
+        assert_eq!(command.execute(), vec![vec![&Chamber::Key(3)]]);
+
+        // … but a hash index of it serves lookups, and not ranges
+        table
+            .create_index("titles".to_owned(), vec![1], false, IndexKind::Hash)
+            .unwrap();
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "title",
+                ComparisonOperator::Equal,
+                Chamber::String("Thing Explainer".to_owned()),
+            ),
+        );
+        assert_eq!(
+            command.access_path,
+            AccessPath::HashLookup {
+                index_offset: 2,
+                key: vec![Chamber::String("Thing Explainer".to_owned())],
+            }
+        );
+        assert_eq!(command.execute(), vec![vec![&Chamber::Key(3)]]);
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "title",
+                ComparisonOperator::Less,
+                Chamber::String("Thing Explainer".to_owned()),
+            ),
+        );
+        assert_eq!(command.access_path, AccessPath::TableScan);
+        drop(command);
+
+        // (and wins over a B-tree index of the same column)
+        table
+            .create_index("hashes".to_owned(), vec![2], false, IndexKind::Hash)
+            .unwrap();
+        let command = select(
+            &table,
+            comparison(
+                &table,
+                "year",
+                ComparisonOperator::Equal,
+                Chamber::Integer(2015),
+            ),
+        );
+        assert_eq!(
+            command.access_path,
+            AccessPath::HashLookup {
+                index_offset: 3,
+                key: vec![Chamber::Integer(2015)],
+            }
+        );
+        assert_eq!(
+            command.execute(),
+            vec![vec![&Chamber::Key(2)], vec![&Chamber::Key(3)]]
+        );
+    }
+
+    #[test]
+    fn concerning_explaining_conditions() {
+        let column_names = ["pk".to_owned(), "title".to_owned()];
+        let title = |operator, value: &str| WhereSubcommand::Comparison {
+            column_offset: 1,
+            operator,
+            value: Chamber::String(value.to_owned()),
+        };
+        let condition = WhereSubcommand::And(
+            Box::new(WhereSubcommand::Or(
+                Box::new(title(ComparisonOperator::Less, "M")),
+                Box::new(WhereSubcommand::IsNull { column_offset: 1 }),
+            )),
+            Box::new(WhereSubcommand::Not(Box::new(WhereSubcommand::And(
+                Box::new(title(ComparisonOperator::NotEqual, "Ulysses")),
+                Box::new(WhereSubcommand::Not(Box::new(
+                    WhereSubcommand::IsNull { column_offset: 0 },
+                ))),
+            )))),
+        );
+        assert_eq!(
+            condition.display(&column_names),
+            "(title < 'M' OR title IS NULL) AND \
+             NOT (title <> 'Ulysses' AND pk IS NOT NULL)"
+        );
+    }
+
+    #[test]
+    fn concerning_explaining_access_paths() {
+        let mut table = example_table();
+        table
+            .create_index("titles".to_owned(), vec![1], false, IndexKind::Hash)
+            .unwrap();
+        let explain = |access_path: AccessPath| {
+            let node = access_path.explain(&table, "books");
+            format!("{} {}", node.operation, node.detail)
+        };
+        assert_eq!(explain(AccessPath::TableScan), "Table Scan books");
+        assert_eq!(
+            explain(AccessPath::PrimaryKeyLookup { pk: 2 }),
+            "Primary Key Lookup books (pk = 2)"
+        );
+        assert_eq!(
+            explain(AccessPath::PrimaryKeyRange {
+                lower: Bound::Included(2),
+                upper: Bound::Excluded(5),
+            }),
+            "Primary Key Range books (pk >= 2 AND pk < 5)"
+        );
+        assert_eq!(
+            explain(AccessPath::HashLookup {
+                index_offset: 0,
+                key: vec![Chamber::String("Thing Explainer".to_owned())],
+            }),
+            "Hash Lookup books USING titles (title = 'Thing Explainer')"
+        );
+    }
+
+    #[test]
+    fn concerning_cost_based_access_paths() {
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        schema
+            .add_column("year".to_owned(), ColumnType::Integer)
+            .unwrap();
+        let mut table = Table::new(schema);
+        // (mostly from 2000, with one each from 2001 and 2002)
+        for i in 0..20 {
+            table.place(
+                table
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String(format!("Volume {}", i)),
+                        Chamber::Integer(2000 + 0.max(i - 17)),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        table
+            .create_index("years".to_owned(), vec![2], false, IndexKind::BTree)
+            .unwrap();
+        table
+            .create_index("titles".to_owned(), vec![1], false, IndexKind::Hash)
+            .unwrap();
+        let plan = |table: &Table, comparisons: &[(&str, _, Chamber)]| {
+            let where_clause = WhereSubcommand::all(
+                comparisons
+                    .iter()
+                    .map(|(column, operator, value)| {
+                        WhereSubcommand::new_comparison(
+                            &table.schema,
+                            column.to_string(),
+                            *operator,
+                            value.clone(),
+                        ).unwrap()
+                    })
+                    .collect(),
+            );
+            AccessPath::plan(table, &where_clause)
+        };
+        let greater = ComparisonOperator::Greater;
+        let equal = ComparisonOperator::Equal;
+        let in_year = |year| [("year", equal, Chamber::Integer(year))];
+        let years = |year| AccessPath::IndexScan {
+            index_offset: 0,
+            prefix: vec![Chamber::Integer(year)],
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        };
+
+        // By the rules, any index beats a table scan ...
+        assert_eq!(plan(&table, &in_year(2000)), years(2000));
+        table.analyze();
+        // ... but it's cheaper to scan the table than to look up most of it
+        // through an index
+        assert_eq!(plan(&table, &in_year(2000)), AccessPath::TableScan);
+        assert_eq!(plan(&table, &in_year(2001)), years(2001));
+        assert_eq!(
+            plan(
+                &table,
+                &[("year", greater, Chamber::Integer(2000))]
+            ),
+            AccessPath::IndexScan {
+                index_offset: 0,
+                prefix: Vec::new(),
+                lower: Bound::Excluded(Chamber::Integer(2000)),
+                upper: Bound::Unbounded,
+            }
+        );
+        let volume = Chamber::String("Volume 3".to_owned());
+        assert_eq!(
+            plan(
+                &table,
+                &[
+                    ("year", equal, Chamber::Integer(2000)),
+                    ("title", equal, volume.clone()),
+                ]
+            ),
+            AccessPath::HashLookup {
+                index_offset: 1,
+                key: vec![volume],
+            }
+        );
+        assert_eq!(
+            plan(&table, &[("pk", ComparisonOperator::Less, Chamber::Key(5))]),
+            AccessPath::PrimaryKeyRange {
+                lower: Bound::Unbounded,
+                upper: Bound::Excluded(5),
+            }
+        );
+        assert_eq!(
+            plan(&table, &[("pk", equal, Chamber::Key(5))]),
+            AccessPath::PrimaryKeyLookup { pk: 5 }
+        );
+    }
+
+    #[test]
+    fn concerning_projection_order() {
+        let table = example_table();
+        let select_command = SelectCommand::new_table_scan(
+            &table,
+            vec!["year".to_owned(), "pk".to_owned(), "year".to_owned()],
+            WhereSubcommand::new_column_equality(
+                &table.schema,
+                "year".to_owned(),
+                Chamber::Integer(2013),
+            ).unwrap(),
+        ).unwrap();
+        assert_eq!(
+            vec![vec![
+                &Chamber::Integer(2013),
+                &Chamber::Key(1),
+                &Chamber::Integer(2013),
+            ]],
+            select_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_projecting_unknown_columns() {
+        let table = example_table();
+        let error = SelectCommand::new_table_scan(
+            &table,
+            vec!["title".to_owned(), "author".to_owned()],
+            WhereSubcommand::new_unconditional(),
+        ).err()
+            .unwrap();
+        assert_eq!(error.to_string(), "no column named author");
+    }
+
+    #[test]
+    fn concerning_aggregates() {
+        let table = example_table();
+        let aggregate = |function, column_name: Option<&str>| {
+            ColumnExpression::Aggregate {
+                function,
+                column_name: column_name.map(|name| name.to_owned()),
+            }
+        };
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![
+                aggregate(AggregateFunction::Count, None),
+                aggregate(AggregateFunction::Sum, Some("year")),
+                aggregate(AggregateFunction::Min, Some("title")),
+                aggregate(AggregateFunction::Max, Some("year")),
+                aggregate(AggregateFunction::Average, Some("year")),
+            ],
+            WhereSubcommand::new_unconditional(),
+            vec![],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![
+                Chamber::Integer(3),
+                Chamber::Integer(6043),
+                Chamber::String("Galileo's Middle Finger".to_owned()),
+                Chamber::Integer(2015),
+                Chamber::Integer(2014),
+            ]],
+            aggregate_command.execute().unwrap()
+        );
+
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![
+                aggregate(AggregateFunction::Count, Some("title")),
+                aggregate(AggregateFunction::Sum, Some("year")),
+            ],
+            WhereSubcommand::new_column_equality(
+                &table.schema,
+                "year".to_owned(),
+                Chamber::Integer(1999),
+            ).unwrap(),
+            vec![],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![Chamber::Integer(0), Chamber::Null]],
+            aggregate_command.execute().unwrap()
+        );
+    }
+
+    #[test]
+    fn concerning_ill_typed_aggregates() {
+        let table = example_table();
+        let sum_of_titles = AggregateCommand::new(
+            &table,
+            vec![ColumnExpression::Aggregate {
+                function: AggregateFunction::Sum,
+                column_name: Some("title".to_owned()),
+            }],
+            WhereSubcommand::new_unconditional(),
+            vec![],
+            None,
+        );
+        assert!(sum_of_titles.is_err());
+        let unaggregated = AggregateCommand::new(
+            &table,
+            vec![
+                ColumnExpression::Column("year".to_owned()),
+                ColumnExpression::Aggregate {
+                    function: AggregateFunction::Count,
+                    column_name: None,
+                },
+            ],
+            WhereSubcommand::new_unconditional(),
+            vec![],
+            None,
+        );
+        assert!(unaggregated.is_err());
+    }
+
+    #[test]
+    fn concerning_grouping() {
+        let table = example_table();
+        let count = || ColumnExpression::Aggregate {
+            function: AggregateFunction::Count,
+            column_name: None,
+        };
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![
+                ColumnExpression::Column("year".to_owned()),
+                count(),
+                ColumnExpression::Aggregate {
+                    function: AggregateFunction::Max,
+                    column_name: Some("title".to_owned()),
+                },
+            ],
+            WhereSubcommand::new_unconditional(),
+            vec!["year".to_owned()],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![
+                vec![
+                    Chamber::Integer(2013),
+                    Chamber::Integer(1),
+                    Chamber::String("Men Trapped In Men's Bodies".to_owned()),
+                ],
+                vec![
+                    Chamber::Integer(2015),
+                    Chamber::Integer(2),
+                    Chamber::String("Thing Explainer".to_owned()),
+                ],
+            ],
+            aggregate_command.execute().unwrap()
+        );
+
+        // HAVING can refer to aggregates that aren't selected
+        let aggregate_command = AggregateCommand::new(
+            &table,
+            vec![ColumnExpression::Column("year".to_owned())],
+            WhereSubcommand::new_unconditional(),
+            vec!["year".to_owned()],
+            Some(Condition::Comparison {
+                column: count(),
+                operator: ComparisonOperator::Greater,
+                value: Chamber::Integer(1),
+            }),
+        ).unwrap();
+        assert_eq!(
+            vec![vec![Chamber::Integer(2015)]],
+            aggregate_command.execute().unwrap()
+        );
+    }
+
+    #[test]
+    fn concerning_grouping_with_no_rows() {
+        let table = example_table();
+        let nothing = || {
+            WhereSubcommand::new_column_equality(
+                &table.schema,
+                "year".to_owned(),
+                Chamber::Integer(1999),
+            ).unwrap()
+        };
+        let count = ColumnExpression::Aggregate {
+            function: AggregateFunction::Count,
+            column_name: None,
+        };
+        let ungrouped = AggregateCommand::new(
+            &table,
+            vec![count.clone()],
+            nothing(),
+            vec![],
+            None,
+        ).unwrap();
+        assert_eq!(
+            vec![vec![Chamber::Integer(0)]],
+            ungrouped.execute().unwrap()
+        );
+        let grouped = AggregateCommand::new(
+            &table,
+            vec![count],
+            nothing(),
+            vec!["year".to_owned()],
+            None,
+        ).unwrap();
+        assert!(grouped.execute().unwrap().is_empty());
+    }
+
+    #[test]
+    fn concerning_ungrouped_columns() {
+        let table = example_table();
+        let select_title = AggregateCommand::new(
+            &table,
+            vec![ColumnExpression::Column("title".to_owned())],
+            WhereSubcommand::new_unconditional(),
+            vec!["year".to_owned()],
+            None,
+        );
+        assert!(select_title.is_err());
+        let having_title = AggregateCommand::new(
+            &table,
+            vec![ColumnExpression::Column("year".to_owned())],
+            WhereSubcommand::new_unconditional(),
+            vec!["year".to_owned()],
+            Some(Condition::Comparison {
+                column: ColumnExpression::Column("title".to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::String("Thing Explainer".to_owned()),
+            }),
+        );
+        assert!(having_title.is_err());
+    }
+
+    fn example_authors_and_books() -> (Table, Table) {
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("name".to_owned(), ColumnType::String)
+            .unwrap();
+        let mut authors = Table::new(schema);
+        for name in &["Randall Munroe", "Alice Dreger", "Thomas Nagel"] {
+            authors.place(
+                authors
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String(name.to_string()),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        schema
+            .add_column("author".to_owned(), ColumnType::Integer)
+            .unwrap();
+        let mut books = Table::new(schema);
+        for &(title, author) in &[
+            ("Thing Explainer", 1),
+            ("Galileo's Middle Finger", 2),
+            ("What If?", 1),
+            ("Flatland", 4),
+        ] {
+            books.place(
+                books
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String(title.to_owned()),
+                        Chamber::Integer(author),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        (authors, books)
+    }
+
+    fn on_author(left: &str, right: &str) -> Option<Condition> {
+        Some(Condition::ColumnComparison {
+            left: ColumnExpression::Column(left.to_owned()),
+            operator: ComparisonOperator::Equal,
+            right: ColumnExpression::Column(right.to_owned()),
+        })
+    }
+
+    #[test]
+    fn concerning_inner_joins() {
+        let (authors, books) = example_authors_and_books();
+        let join_command = JoinCommand::new(&books, "b".to_owned())
+            .join(
+                JoinKind::Inner,
+                &authors,
+                "authors".to_owned(),
+                on_author("b.author", "authors.pk"),
+            ).unwrap()
+            .projecting(vec!["title".to_owned(), "name".to_owned()])
+            .unwrap()
+            .filtered_by(Condition::Comparison {
+                column: ColumnExpression::Column("authors.pk".to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::Integer(1),
+            }).unwrap();
+        let randall = Chamber::String("Randall Munroe".to_owned());
+        assert_eq!(
+            vec![
+                vec![&Chamber::String("Thing Explainer".to_owned()), &randall],
+                vec![&Chamber::String("What If?".to_owned()), &randall],
+            ],
+            join_command.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_outer_joins() {
+        let (authors, books) = example_authors_and_books();
+        let left_join = JoinCommand::new(&books, "books".to_owned())
+            .join(
+                JoinKind::Left,
+                &authors,
+                "authors".to_owned(),
+                on_author("author", "authors.pk"),
+            ).unwrap()
+            .projecting(vec!["title".to_owned(), "name".to_owned()])
+            .unwrap()
+            .sorted_by(vec![SortKey {
+                column_name: "name".to_owned(),
+                order: SortOrder::Ascending,
+            }]).unwrap();
+        let string = |s: &str| Chamber::String(s.to_owned());
+        assert_eq!(
+            vec![
+                vec![
+                    &string("Galileo's Middle Finger"),
+                    &string("Alice Dreger"),
+                ],
+                vec![&string("Thing Explainer"), &string("Randall Munroe")],
+                vec![&string("What If?"), &string("Randall Munroe")],
+                vec![&string("Flatland"), &Chamber::Null],
+            ],
+            left_join.execute()
+        );
+
+        let right_join = JoinCommand::new(&books, "books".to_owned())
+            .join(
+                JoinKind::Right,
+                &authors,
+                "authors".to_owned(),
+                on_author("author", "authors.pk"),
+            ).unwrap()
+            .projecting(vec!["name".to_owned(), "title".to_owned()])
+            .unwrap();
+        assert_eq!(
+            vec![
+                vec![&string("Randall Munroe"), &string("Thing Explainer")],
+                vec![&string("Randall Munroe"), &string("What If?")],
+                vec![
+                    &string("Alice Dreger"),
+                    &string("Galileo's Middle Finger"),
+                ],
+                vec![&string("Thomas Nagel"), &Chamber::Null],
+            ],
+            right_join.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_cross_joins() {
+        let (authors, books) = example_authors_and_books();
+        let cross_join = JoinCommand::new(&authors, "a".to_owned())
+            .join(JoinKind::Cross, &books, "b".to_owned(), None)
+            .unwrap()
+            .projecting(vec!["a.pk".to_owned(), "b.pk".to_owned()])
+            .unwrap()
+            .limited_to(5, 2);
+        assert_eq!(
+            vec![
+                vec![&Chamber::Key(1), &Chamber::Key(3)],
+                vec![&Chamber::Key(1), &Chamber::Key(4)],
+                vec![&Chamber::Key(2), &Chamber::Key(1)],
+                vec![&Chamber::Key(2), &Chamber::Key(2)],
+                vec![&Chamber::Key(2), &Chamber::Key(3)],
+            ],
+            cross_join.execute()
+        );
+    }
+
+    #[test]
+    fn concerning_join_order() {
+        let (mut authors, mut books) = example_authors_and_books();
+        fn join<'a>(
+            books: &'a Table,
+            authors: &'a Table,
+            kind: JoinKind,
+        ) -> JoinCommand<'a> {
+            let on = on_author("author", "a.pk");
+            JoinCommand::new(books, "b".to_owned())
+                .join(kind, authors, "a".to_owned(), on)
+                .unwrap()
+                .projecting(vec!["title".to_owned(), "name".to_owned()])
+                .unwrap()
+                .reordered_by_cost()
+        }
+        let command = join(&books, &authors, JoinKind::Inner);
+        assert!(command.reordering.is_none());
+        drop(command);
+
+        authors.analyze();
+        books.analyze();
+        // (the three authors are fewer than the four books, so the join
+        // starts from them; the rows still come out with the books first)
+        let command = join(&books, &authors, JoinKind::Inner);
+        assert_eq!(command.reordering, Some(vec![2, 3, 4, 0, 1]));
+        assert_eq!(command.order, 3);
+        let plan = command.explain();
+        let join_node = &plan.children[0];
+        assert_eq!(
+            (&join_node.operation[..], &join_node.detail[..]),
+            ("Hash Inner Join", "b ON b.author = a.pk")
+        );
+        assert_eq!(join_node.children[0].detail, "a");
+        let string = |s: &str| Chamber::String(s.to_owned());
+        let randall = string("Randall Munroe");
+        let alice = string("Alice Dreger");
+        assert_eq!(
+            command.execute(),
+            vec![
+                vec![&string("Thing Explainer"), &randall],
+                vec![&string("What If?"), &randall],
+                vec![&string("Galileo's Middle Finger"), &alice],
+            ]
+        );
+
+        // Outer joins keep their order
+        let command = join(&books, &authors, JoinKind::Left);
+        assert!(command.reordering.is_none());
+    }
+
+    #[test]
+    fn concerning_estimating_selectivity() {
+        let (mut authors, mut books) = example_authors_and_books();
+        authors.analyze();
+        books.analyze();
+        let statistics = |offset: usize| {
+            if offset < 2 {
+                (authors.statistics.as_ref().unwrap(), offset)
+            } else {
+                (books.statistics.as_ref().unwrap(), offset - 2)
+            }
+        };
+        let estimate = |condition| selectivity(&condition, &statistics);
+        let by_author = WhereSubcommand::ColumnComparison {
+            left_offset: 0,
+            operator: ComparisonOperator::Equal,
+            right_offset: 4,
+        };
+        // (four books, but only three distinct authors on either side)
+        assert_eq!(estimate(by_author.clone()), 1.0 / 3.0);
+        let randall = WhereSubcommand::Comparison {
+            column_offset: 4,
+            operator: ComparisonOperator::Equal,
+            value: Chamber::Integer(1),
+        };
+        // (two of the four books are by author 1)
+        assert_eq!(estimate(randall.clone()), 0.5);
+        assert_eq!(
+            estimate(WhereSubcommand::And(
+                Box::new(by_author.clone()),
+                Box::new(randall.clone())
+            )),
+            1.0 / 6.0
+        );
+        assert_eq!(
+            estimate(WhereSubcommand::Not(Box::new(randall))),
+            0.5
+        );
+        assert_eq!(
+            estimate(WhereSubcommand::Comparison {
+                column_offset: 4,
+                operator: ComparisonOperator::Greater,
+                value: Chamber::Integer(1),
+            }),
+            0.5
+        );
+        assert_eq!(
+            estimate(WhereSubcommand::IsNull { column_offset: 1 }),
+            0.0
+        );
+    }
+
+    #[test]
+    fn concerning_join_strategies() {
+        let (authors, books) = example_authors_and_books();
+        let greater = Some(Condition::ColumnComparison {
+            left: ColumnExpression::Column("author".to_owned()),
+            operator: ComparisonOperator::Greater,
+            right: ColumnExpression::Column("a.pk".to_owned()),
+        });
+        let on_author_and_title = Some(Condition::And(
+            Box::new(on_author("a.pk", "author").unwrap()),
+            Box::new(Condition::Comparison {
+                column: ColumnExpression::Column("title".to_owned()),
+                operator: ComparisonOperator::NotEqual,
+                value: Chamber::String("What If?".to_owned()),
+            }),
+        ));
+        let by_author = JoinStrategy::Hash {
+            left_offset: 2,
+            right_offset: 0,
+        };
+        let by_pk = JoinStrategy::Merge {
+            left_offset: 0,
+            right_offset: 0,
+        };
+        let cases = vec![
+            (on_author("author", "a.pk"), by_author),
+            (on_author_and_title, by_author),
+            (on_author("a.pk", "books.pk"), by_pk),
+            (greater, JoinStrategy::NestedLoop),
+        ];
+        for (on, strategy) in cases {
+            for &kind in &[JoinKind::Inner, JoinKind::Left, JoinKind::Right] {
+                let join_command = || {
+                    JoinCommand::new(&books, "books".to_owned())
+                        .join(kind, &authors, "a".to_owned(), on.clone())
+                        .unwrap()
+                };
+                let planned = join_command();
+                assert_eq!(planned.joins[0].strategy, strategy);
+                let mut nested_loop = join_command();
+                nested_loop.joins[0].strategy = JoinStrategy::NestedLoop;
+                assert_eq!(planned.execute(), nested_loop.execute());
+            }
+        }
+
+        // After a right join, rows come in the order of the right table
+        let join_command = JoinCommand::new(&books, "books".to_owned())
+            .join(
+                JoinKind::Right,
+                &authors,
+                "a".to_owned(),
+                on_author("author", "a.pk"),
+            ).unwrap()
+            .join(
+                JoinKind::Inner,
+                &authors,
+                "b".to_owned(),
+                on_author("b.pk", "a.pk"),
+            ).unwrap();
+        assert_eq!(
+            join_command.joins[1].strategy,
+            JoinStrategy::Merge {
+                left_offset: 3,
+                right_offset: 0,
+            }
+        );
+        assert_eq!(join_command.execute().len(), 4);
+    }
+
+    #[test]
+    fn concerning_ill_formed_joins() {
+        let (authors, books) = example_authors_and_books();
+        let join = |kind, qualifier: &str, on| {
+            JoinCommand::new(&books, "books".to_owned())
+                .join(kind, &authors, qualifier.to_owned(), on)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            join(JoinKind::Inner, "authors", None),
+            "INNER JOIN needs an ON clause"
+        );
+        assert_eq!(
+            join(JoinKind::Cross, "authors", on_author("author", "pk")),
+            "CROSS JOIN can't have an ON clause"
+        );
+        assert_eq!(
+            join(JoinKind::Left, "authors", on_author("author", "pk")),
+            "column reference pk is ambiguous"
+        );
+        assert_eq!(
+            join(JoinKind::Left, "books", on_author("author", "books.pk")),
+            "table name books is specified more than once"
+        );
+        assert_eq!(
+            join(JoinKind::Left, "a", on_author("title", "a.pk")),
+            "can't compare String column title to Key column a.pk"
         );
     }
 
@@ -1,28 +1,107 @@
+use std::cmp::Ordering;
+use std::error::Error;
+
 use nom::{alphanumeric1, digit1, multispace0, multispace1};
 
-use crate::table::Chamber;
+use crate::table::{Chamber, ColumnType};
 
 #[allow(unreachable_pub)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum Statement {
     Select(SelectStatement),
     Insert(InsertStatement),
+    CreateTable(CreateTableStatement),
+    CreateIndex(CreateIndexStatement),
+    DropTable(DropTableStatement),
+    Truncate(TruncateStatement),
+    Delete(DeleteStatement),
+    Update(UpdateStatement),
+    Explain(ExplainStatement),
+    Analyze(AnalyzeStatement),
 }
 
 #[allow(unreachable_pub)]
 #[derive(Debug, PartialEq, Eq)]
 crate enum ColumnClause {
     Star,
-    Names(Vec<String>),
+    Expressions(Vec<ColumnExpression>),
 }
 
 #[allow(unreachable_pub)]
-#[derive(Debug, PartialEq, Eq)]
-pub struct WhereClause {
-    crate column_name: String,
-    // XXX TODO: this actually needs to be a `Chamber`
-    // and how will we detect Integer vs. Key?!
-    crate value: Chamber,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Average,
+}
+
+#[allow(unreachable_pub)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnExpression {
+    Column(String),
+    Aggregate {
+        function: AggregateFunction,
+        // `None` for `COUNT(*)`
+        column_name: Option<String>,
+    },
+}
+
+impl AggregateFunction {
+    crate fn display(self) -> &'static str {
+        match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+            AggregateFunction::Average => "AVG",
+        }
+    }
+}
+
+impl ColumnExpression {
+    crate fn is_aggregate(&self) -> bool {
+        match self {
+            ColumnExpression::Column(_) => false,
+            ColumnExpression::Aggregate { .. } => true,
+        }
+    }
+
+    crate fn display(&self) -> String {
+        match self {
+            ColumnExpression::Column(column_name) => column_name.clone(),
+            ColumnExpression::Aggregate {
+                function,
+                column_name,
+            } => format!(
+                "{}({})",
+                function.display(),
+                column_name.as_ref().map(|name| name.as_str()).unwrap_or("*")
+            ),
+        }
+    }
+}
+
+#[allow(unreachable_pub)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Comparison {
+        column: ColumnExpression,
+        operator: ComparisonOperator,
+        value: Chamber,
+    },
+    // e.g., `books.author = authors.pk`, as in join conditions
+    ColumnComparison {
+        left: ColumnExpression,
+        operator: ComparisonOperator,
+        right: ColumnExpression,
+    },
+    // `IS NOT NULL` is the negation of this
+    IsNull(ColumnExpression),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
 }
 
 #[allow(unreachable_pub)]
@@ -30,9 +109,78 @@ pub struct WhereClause {
 pub struct SelectStatement {
     crate column_names: ColumnClause,
     crate table_name: String,
-    crate where_clause: Option<WhereClause>,
+    crate table_alias: Option<String>,
+    crate joins: Vec<JoinClause>,
+    crate where_clause: Option<Condition>,
+    crate group_by: Vec<String>,
+    crate having: Option<Condition>,
+    crate order_by: Vec<SortKey>,
+    crate limit: Option<Limit>,
+}
+
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Cross,
+}
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct JoinClause {
+    crate kind: JoinKind,
+    crate table_name: String,
+    crate table_alias: Option<String>,
+    crate on: Option<Condition>,
+}
+
+impl JoinKind {
+    crate fn display(self) -> &'static str {
+        match self {
+            JoinKind::Inner => "INNER JOIN",
+            JoinKind::Left => "LEFT JOIN",
+            JoinKind::Right => "RIGHT JOIN",
+            JoinKind::Cross => "CROSS JOIN",
+        }
+    }
+}
+
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
 }
 
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct SortKey {
+    crate column_name: String,
+    crate order: SortOrder,
+}
+
+// Words that can't be used as table aliases (lest `FROM books WHERE ...`
+// make "WHERE" an alias)
+const KEYWORDS: &[&str] = &[
+    "AS", "CROSS", "FROM", "GROUP", "HAVING", "INNER", "JOIN", "LEFT", "LIMIT",
+    "ON", "ORDER", "OUTER", "RIGHT", "WHERE",
+];
+
+// A column name, optionally qualified with a table name or alias (as
+// "table.column")
+named!(column_reference <&str, String>,
+    do_parse!(
+        first: alphanumeric1 >>
+        second: opt!(preceded!(char!('.'), alphanumeric1)) >>
+        (match second {
+            Some(column_name) => format!("{}.{}", first, column_name),
+            None => first.to_owned(),
+        })
+    )
+);
+
 named!(string_literal <&str, Chamber>,
     do_parse!(
         value: delimited!(
@@ -46,26 +194,173 @@ named!(string_literal <&str, Chamber>,
 
 named!(integer_literal <&str, Chamber>,
     do_parse!(
-        value: digit1 >>
-        (Chamber::Integer(value.parse().unwrap()))
+        // (one too big to be an integer isn't one)
+        value: map_res!(
+            recognize!(pair!(opt!(char!('-')), digit1)),
+            str::parse::<isize>
+        ) >>
+        (Chamber::Integer(value))
     )
 );
 
+named!(null_literal <&str, Chamber>,
+    // (a whole word, lest we mistake a column named, e.g., "NULLS")
+    value!(Chamber::Null, verify!(alphanumeric1, |word: &str| word == "NULL"))
+);
+
 named!(literal <&str, Chamber>,
-    alt!(integer_literal | string_literal)
+    alt!(integer_literal | string_literal | null_literal)
+);
+
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComparisonOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl ComparisonOperator {
+    /// Returns `None` (SQL's UNKNOWN) for incomparable chambers: NULL
+    /// compared to anything (even NULL), or chambers of different types.
+    crate fn apply(self, left: &Chamber, right: &Chamber) -> Option<bool> {
+        let ordering = left.compare(right)?;
+        Some(match self {
+            ComparisonOperator::Equal => ordering == Ordering::Equal,
+            ComparisonOperator::NotEqual => ordering != Ordering::Equal,
+            ComparisonOperator::Less => ordering == Ordering::Less,
+            ComparisonOperator::LessOrEqual => ordering != Ordering::Greater,
+            ComparisonOperator::Greater => ordering == Ordering::Greater,
+            ComparisonOperator::GreaterOrEqual => ordering != Ordering::Less,
+        })
+    }
+
+    crate fn display(self) -> &'static str {
+        match self {
+            ComparisonOperator::Equal => "=",
+            ComparisonOperator::NotEqual => "<>",
+            ComparisonOperator::Less => "<",
+            ComparisonOperator::LessOrEqual => "<=",
+            ComparisonOperator::Greater => ">",
+            ComparisonOperator::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+named!(parse_comparison_operator<&str, ComparisonOperator>,
+    alt!(
+        // two-character operators have to be tried first
+        value!(ComparisonOperator::LessOrEqual, tag!("<=")) |
+        value!(ComparisonOperator::GreaterOrEqual, tag!(">=")) |
+        value!(ComparisonOperator::NotEqual, tag!("<>")) |
+        value!(ComparisonOperator::NotEqual, tag!("!=")) |
+        value!(ComparisonOperator::Equal, tag!("=")) |
+        value!(ComparisonOperator::Less, tag!("<")) |
+        value!(ComparisonOperator::Greater, tag!(">"))
+    )
 );
 
-named!(parse_where_clause<&str, WhereClause>,
+named!(parse_comparison<&str, Condition>,
     do_parse!(
-        tag!("WHERE") >>
-        multispace1 >>
-        column_name: alphanumeric1 >>
+        column: parse_column_expression >>
         multispace0 >>
-        tag!("=") >>
+        operator: parse_comparison_operator >>
         multispace0 >>
-        value: literal >>
-        (WhereClause { column_name: column_name.to_owned(),
-                       value })
+        condition: alt!(
+            map!(literal, |value| Condition::Comparison {
+                column: column.clone(),
+                operator,
+                value
+            }) |
+            map!(parse_column_expression, |right| Condition::ColumnComparison {
+                left: column.clone(),
+                operator,
+                right
+            })
+        ) >>
+        (condition)
+    )
+);
+
+named!(parse_null_test<&str, Condition>,
+    do_parse!(
+        column: parse_column_expression >>
+        delimited!(multispace1, tag!("IS"), multispace1) >>
+        negated: opt!(terminated!(tag!("NOT"), multispace1)) >>
+        null_literal >>
+        (if negated.is_some() {
+            Condition::Not(Box::new(Condition::IsNull(column)))
+        } else {
+            Condition::IsNull(column)
+        })
+    )
+);
+
+named!(parse_parenthesized_condition<&str, Condition>,
+    delimited!(
+        terminated!(char!('('), multispace0),
+        parse_condition,
+        preceded!(multispace0, char!(')'))
+    )
+);
+
+// Precedence, from tightest to loosest binding: parentheses, NOT, AND, OR
+
+named!(parse_negation<&str, Condition>,
+    alt!(
+        do_parse!(
+            tag!("NOT") >>
+            // the space can only be omitted before a parenthesis (lest we
+            // mistake a column named, e.g., "NOTE" for a negation)
+            alt!(multispace1 | peek!(tag!("("))) >>
+            negated: parse_negation >>
+            (Condition::Not(Box::new(negated)))
+        ) |
+        parse_parenthesized_condition |
+        parse_null_test |
+        parse_comparison
+    )
+);
+
+named!(parse_conjunction<&str, Condition>,
+    do_parse!(
+        first: parse_negation >>
+        // (complete, as what follows the whitespace after a condition can be
+        // the end of the input rather than another conjunct)
+        rest: many0!(
+            complete!(preceded!(
+                delimited!(multispace1, tag!("AND"), multispace1),
+                parse_negation
+            ))
+        ) >>
+        (rest.into_iter().fold(first, |conjunction, conjunct| {
+            Condition::And(Box::new(conjunction), Box::new(conjunct))
+        }))
+    )
+);
+
+named!(parse_condition<&str, Condition>,
+    do_parse!(
+        first: parse_conjunction >>
+        rest: many0!(
+            complete!(preceded!(
+                delimited!(multispace1, tag!("OR"), multispace1),
+                parse_conjunction
+            ))
+        ) >>
+        (rest.into_iter().fold(first, |disjunction, disjunct| {
+            Condition::Or(Box::new(disjunction), Box::new(disjunct))
+        }))
+    )
+);
+
+named!(parse_where_clause<&str, Condition>,
+    preceded!(
+        terminated!(tag!("WHERE"), multispace1),
+        parse_condition
     )
 );
 
@@ -76,17 +371,197 @@ named!(parse_star<&str, ColumnClause>,
     )
 );
 
-named!(parse_select_column_names<&str, ColumnClause>,
+named!(parse_aggregate_function<&str, AggregateFunction>,
+    alt!(
+        value!(AggregateFunction::Count, tag!("COUNT")) |
+        value!(AggregateFunction::Sum, tag!("SUM")) |
+        value!(AggregateFunction::Min, tag!("MIN")) |
+        value!(AggregateFunction::Max, tag!("MAX")) |
+        value!(AggregateFunction::Average, tag!("AVG"))
+    )
+);
+
+named!(parse_aggregate<&str, ColumnExpression>,
+    alt!(
+        do_parse!(
+            tag!("COUNT") >>
+            multispace0 >>
+            delimited!(
+                terminated!(char!('('), multispace0),
+                char!('*'),
+                preceded!(multispace0, char!(')'))
+            ) >>
+            (ColumnExpression::Aggregate {
+                function: AggregateFunction::Count,
+                column_name: None
+            })
+        ) |
+        do_parse!(
+            function: parse_aggregate_function >>
+            multispace0 >>
+            column_name: delimited!(
+                terminated!(char!('('), multispace0),
+                column_reference,
+                preceded!(multispace0, char!(')'))
+            ) >>
+            (ColumnExpression::Aggregate {
+                function,
+                column_name: Some(column_name)
+            })
+        )
+    )
+);
+
+named!(parse_column_expression<&str, ColumnExpression>,
+    alt!(
+        // (a column could be named, e.g., "COUNT", so the parenthesized
+        // aggregate syntax has to be tried first)
+        parse_aggregate |
+        map!(column_reference, ColumnExpression::Column)
+    )
+);
+
+named!(parse_select_column_expressions<&str, ColumnClause>,
     do_parse!(
-        names: separated_list!(commaspace, alphanumeric1) >>
-        (ColumnClause::Names(names.iter().map(|name| {
-            name.clone().to_owned() // really?
-        }).collect()))
+        expressions: separated_nonempty_list!(
+            commaspace,
+            parse_column_expression
+        ) >>
+        (ColumnClause::Expressions(expressions))
     )
 );
 
 named!(parse_select_column_clause<&str, ColumnClause>,
-    alt!(parse_star | parse_select_column_names)
+    alt!(parse_star | parse_select_column_expressions)
+);
+
+named!(parse_group_by_clause<&str, Vec<String>>,
+    do_parse!(
+        tag!("GROUP") >>
+        multispace1 >>
+        tag!("BY") >>
+        multispace1 >>
+        column_names: separated_nonempty_list!(commaspace, column_reference) >>
+        (column_names)
+    )
+);
+
+named!(parse_having_clause<&str, Condition>,
+    preceded!(
+        terminated!(tag!("HAVING"), multispace1),
+        parse_condition
+    )
+);
+
+named!(parse_sort_order<&str, SortOrder>,
+    alt!(
+        value!(SortOrder::Ascending, tag!("ASC")) |
+        value!(SortOrder::Descending, tag!("DESC"))
+    )
+);
+
+named!(parse_sort_key<&str, SortKey>,
+    do_parse!(
+        column_name: column_reference >>
+        order: opt!(preceded!(multispace1, parse_sort_order)) >>
+        (SortKey { column_name,
+                   order: order.unwrap_or(SortOrder::Ascending) })
+    )
+);
+
+named!(parse_order_by_clause<&str, Vec<SortKey>>,
+    do_parse!(
+        tag!("ORDER") >>
+        multispace1 >>
+        tag!("BY") >>
+        multispace1 >>
+        sort_keys: separated_nonempty_list!(commaspace, parse_sort_key) >>
+        (sort_keys)
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Limit {
+    crate count: usize,
+    crate offset: usize,
+}
+
+named!(parse_limit_clause<&str, Limit>,
+    do_parse!(
+        tag!("LIMIT") >>
+        multispace1 >>
+        count: map_res!(digit1, str::parse::<usize>) >>
+        offset: opt!(
+            preceded!(
+                delimited!(multispace1, tag!("OFFSET"), multispace1),
+                map_res!(digit1, str::parse::<usize>)
+            )
+        ) >>
+        (Limit { count, offset: offset.unwrap_or(0) })
+    )
+);
+
+named!(parse_table_alias<&str, String>,
+    do_parse!(
+        opt!(terminated!(tag!("AS"), multispace1)) >>
+        alias: verify!(alphanumeric1,
+                       |name: &str| !KEYWORDS.contains(&name)) >>
+        (alias.to_owned())
+    )
+);
+
+named!(parse_table_reference<&str, (String, Option<String>)>,
+    do_parse!(
+        table_name: alphanumeric1 >>
+        table_alias: opt!(preceded!(multispace1, parse_table_alias)) >>
+        ((table_name.to_owned(), table_alias))
+    )
+);
+
+named!(parse_outer<&str, ()>,
+    do_parse!(
+        opt!(preceded!(multispace1, tag!("OUTER"))) >>
+        ()
+    )
+);
+
+named!(parse_join_kind<&str, JoinKind>,
+    do_parse!(
+        kind: opt!(
+            terminated!(
+                alt!(
+                    value!(JoinKind::Inner, tag!("INNER")) |
+                    value!(JoinKind::Left,
+                           terminated!(tag!("LEFT"), parse_outer)) |
+                    value!(JoinKind::Right,
+                           terminated!(tag!("RIGHT"), parse_outer)) |
+                    value!(JoinKind::Cross, tag!("CROSS"))
+                ),
+                multispace1
+            )
+        ) >>
+        tag!("JOIN") >>
+        (kind.unwrap_or(JoinKind::Inner))
+    )
+);
+
+named!(parse_join_clause<&str, JoinClause>,
+    do_parse!(
+        kind: parse_join_kind >>
+        multispace1 >>
+        table: parse_table_reference >>
+        on: opt!(
+            preceded!(
+                delimited!(multispace1, tag!("ON"), multispace1),
+                parse_condition
+            )
+        ) >>
+        (JoinClause { kind,
+                      table_name: table.0,
+                      table_alias: table.1,
+                      on })
+    )
 );
 
 named!(parse_select_statement<&str, Statement>,
@@ -97,15 +572,25 @@ named!(parse_select_statement<&str, Statement>,
        multispace1 >>
        tag!("FROM") >>
        multispace1 >>
-       table_name: alphanumeric1 >>
-       multispace1 >>
-       where_clause: opt!(parse_where_clause) >>
+       table: parse_table_reference >>
+       joins: many0!(preceded!(multispace1, parse_join_clause)) >>
+       where_clause: opt!(preceded!(multispace1, parse_where_clause)) >>
+       group_by: opt!(preceded!(multispace1, parse_group_by_clause)) >>
+       having: opt!(preceded!(multispace1, parse_having_clause)) >>
+       order_by: opt!(preceded!(multispace1, parse_order_by_clause)) >>
+       limit: opt!(preceded!(multispace1, parse_limit_clause)) >>
        multispace0 >>
        tag!(";") >>
        (Statement::Select(
            SelectStatement { column_names,
-                             table_name: table_name.to_string(),
-                             where_clause }
+                             table_name: table.0,
+                             table_alias: table.1,
+                             joins,
+                             where_clause,
+                             group_by: group_by.unwrap_or_default(),
+                             having,
+                             order_by: order_by.unwrap_or_default(),
+                             limit }
            )
        )
    )
@@ -115,6 +600,8 @@ named!(parse_select_statement<&str, Statement>,
 #[derive(Debug, PartialEq, Eq)]
 pub struct InsertStatement {
     crate table_name: String,
+    // `None` for all the columns (after the primary key), in order
+    crate column_names: Option<Vec<String>>,
     crate values: Vec<Chamber>,
 }
 
@@ -141,6 +628,16 @@ named!(parse_insert_statement<&str, Statement>,
         tag!("INTO") >>
         multispace1 >>
         table_name: alphanumeric1 >>
+        column_names: opt!(
+            preceded!(
+                multispace0,
+                delimited!(
+                    terminated!(char!('('), multispace0),
+                    separated_nonempty_list!(commaspace, alphanumeric1),
+                    preceded!(multispace0, char!(')'))
+                )
+            )
+        ) >>
         multispace1 >>
         tag!("VALUES") >>
         multispace1 >>
@@ -149,129 +646,1110 @@ named!(parse_insert_statement<&str, Statement>,
         tag!(";") >>
         (Statement::Insert(InsertStatement {
             table_name: table_name.to_string(),
+            column_names: column_names.map(|names| {
+                names.into_iter().map(|name| name.to_owned()).collect()
+            }),
             values
         }))
     )
 );
 
-// nom doesn't know about `pub(crate)`/`crate` (Issue #807, PR #792)
-named!(pub parse_statement<&str, Statement>,
-    alt!(parse_select_statement | parse_insert_statement)
-);
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ColumnConstraint {
+    NotNull,
+    Unique,
+    Default(Chamber),
+    Check(Condition),
+    References {
+        table_name: String,
+        column_name: String,
+        on_delete: ReferentialAction,
+    },
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::table::Chamber;
+/// What happens to the rows referencing a row that's deleted.
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReferentialAction {
+    Restrict,
+    Cascade,
+    SetNull,
+}
 
-    #[test]
-    fn concerning_parsing_a_where_clause_for_an_integer_column() {
-        assert_eq!(
-            parse_where_clause("WHERE year = 2018 "),
-            Ok((
-                " ",
-                WhereClause {
-                    column_name: "year".to_owned(),
-                    value: Chamber::Integer(2018)
-                }
-            ))
-        );
-    }
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ColumnDefinition {
+    crate name: String,
+    crate column_type: ColumnType,
+    crate constraints: Vec<ColumnConstraint>,
+}
 
-    #[test]
-    fn concerning_parsing_a_select_star_statement() {
-        assert_eq!(
-            parse_select_statement("SELECT * FROM books WHERE year = 2018;"),
-            Ok((
-                "",
-                Statement::Select(SelectStatement {
-                    column_names: ColumnClause::Star,
-                    table_name: "books".to_owned(),
-                    where_clause: Some(WhereClause {
-                        column_name: "year".to_owned(),
-                        value: Chamber::Integer(2018)
-                    }),
-                })
-            ))
-        );
-    }
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct CreateTableStatement {
+    crate table_name: String,
+    crate columns: Vec<ColumnDefinition>,
+}
 
-    #[test]
-    fn concerning_parsing_a_whereless_select_statement() {
-        assert_eq!(
-            parse_select_statement("SELECT * FROM books ;"),
-            Ok((
-                "",
-                Statement::Select(SelectStatement {
-                    column_names: ColumnClause::Star,
-                    table_name: "books".to_owned(),
-                    where_clause: None
-                })
-            ))
-        );
-    }
+named!(parse_column_type<&str, ColumnType>,
+    alt!(
+        value!(ColumnType::Integer, tag!("INTEGER")) |
+        value!(ColumnType::String, tag!("STRING"))
+    )
+);
 
-    #[test]
-    fn concerning_parsing_a_select_column_names_statement() {
-        assert_eq!(
-            parse_select_statement(
-                "SELECT title, author FROM books WHERE year = 2018;"
-            ),
-            Ok((
-                "",
-                Statement::Select(SelectStatement {
-                    column_names: ColumnClause::Names(vec![
-                        "title".to_owned(),
-                        "author".to_owned(),
-                    ]),
-                    table_name: "books".to_owned(),
-                    where_clause: Some(WhereClause {
-                        column_name: "year".to_owned(),
-                        value: Chamber::Integer(2018),
-                    }),
-                })
-            ))
-        );
-    }
+named!(parse_referential_action<&str, ReferentialAction>,
+    alt!(
+        value!(ReferentialAction::Restrict, tag!("RESTRICT")) |
+        value!(ReferentialAction::Cascade, tag!("CASCADE")) |
+        value!(ReferentialAction::SetNull,
+               tuple!(tag!("SET"), multispace1, tag!("NULL")))
+    )
+);
 
-    #[test]
-    fn concerning_the_parsing_of_literals() {
-        assert_eq!(
-            string_literal("'hello SQL world'"),
-            Ok((
-                "",
-                Chamber::String("hello SQL world".to_owned())
-            ))
-        );
-        assert_eq!(
-            literal("'hello SQL world'"),
-            Ok((
-                "",
-                Chamber::String("hello SQL world".to_owned())
-            ))
-        );
-        assert_eq!(
-            integer_literal("9001 "),
-            Ok((" ", Chamber::Integer(9001)))
+named!(parse_references<&str, ColumnConstraint>,
+    do_parse!(
+        tag!("REFERENCES") >>
+        multispace1 >>
+        table_name: alphanumeric1 >>
+        column_name: opt!(
+            preceded!(
+                multispace0,
+                delimited!(
+                    terminated!(char!('('), multispace0),
+                    alphanumeric1,
+                    preceded!(multispace0, char!(')'))
+                )
+            )
+        ) >>
+        on_delete: opt!(
+            preceded!(
+                tuple!(multispace1, tag!("ON"), multispace1, tag!("DELETE"),
+                       multispace1),
+                parse_referential_action
+            )
+        ) >>
+        (ColumnConstraint::References {
+            table_name: table_name.to_owned(),
+            // (the referenced table's primary key by default)
+            column_name: column_name.unwrap_or("pk").to_owned(),
+            on_delete: on_delete.unwrap_or(ReferentialAction::Restrict),
+        })
+    )
+);
+
+named!(parse_column_constraint<&str, ColumnConstraint>,
+    alt!(
+        value!(ColumnConstraint::NotNull,
+               tuple!(tag!("NOT"), multispace1, tag!("NULL"))) |
+        value!(ColumnConstraint::Unique, tag!("UNIQUE")) |
+        map!(preceded!(terminated!(tag!("DEFAULT"), multispace1), literal),
+             ColumnConstraint::Default) |
+        map!(preceded!(terminated!(tag!("CHECK"), multispace0),
+                       parse_parenthesized_condition),
+             ColumnConstraint::Check) |
+        parse_references
+    )
+);
+
+named!(parse_column_definition<&str, ColumnDefinition>,
+    do_parse!(
+        name: alphanumeric1 >>
+        multispace1 >>
+        column_type: parse_column_type >>
+        constraints: many0!(preceded!(multispace1, parse_column_constraint)) >>
+        (ColumnDefinition { name: name.to_owned(), column_type, constraints })
+    )
+);
+
+named!(parse_create_table_statement<&str, Statement>,
+    do_parse!(
+        tag!("CREATE") >>
+        multispace1 >>
+        tag!("TABLE") >>
+        multispace1 >>
+        table_name: alphanumeric1 >>
+        multispace0 >>
+        columns: delimited!(
+            terminated!(char!('('), multispace0),
+            separated_nonempty_list!(commaspace, parse_column_definition),
+            preceded!(multispace0, char!(')'))
+        ) >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::CreateTable(CreateTableStatement {
+            table_name: table_name.to_string(),
+            columns
+        }))
+    )
+);
+
+/// How an index keeps its entries: in order (the default), or hashed, which
+/// only serves lookups of whole keys.
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexKind {
+    BTree,
+    Hash,
+}
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct CreateIndexStatement {
+    crate index_name: String,
+    crate table_name: String,
+    crate column_names: Vec<String>,
+    crate unique: bool,
+    crate kind: IndexKind,
+}
+
+named!(parse_index_kind<&str, IndexKind>,
+    preceded!(
+        terminated!(tag!("USING"), multispace1),
+        alt!(
+            value!(IndexKind::BTree, tag!("BTREE")) |
+            value!(IndexKind::Hash, tag!("HASH"))
+        )
+    )
+);
+
+named!(parse_create_index_statement<&str, Statement>,
+    do_parse!(
+        tag!("CREATE") >>
+        multispace1 >>
+        unique: opt!(terminated!(tag!("UNIQUE"), multispace1)) >>
+        tag!("INDEX") >>
+        multispace1 >>
+        index_name: alphanumeric1 >>
+        multispace1 >>
+        tag!("ON") >>
+        multispace1 >>
+        table_name: alphanumeric1 >>
+        multispace0 >>
+        kind: opt!(terminated!(parse_index_kind, multispace0)) >>
+        column_names: delimited!(
+            terminated!(char!('('), multispace0),
+            separated_nonempty_list!(commaspace, alphanumeric1),
+            preceded!(multispace0, char!(')'))
+        ) >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::CreateIndex(CreateIndexStatement {
+            index_name: index_name.to_string(),
+            table_name: table_name.to_string(),
+            column_names: column_names.iter().map(|n| n.to_string()).collect(),
+            unique: unique.is_some(),
+            kind: kind.unwrap_or(IndexKind::BTree)
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct DropTableStatement {
+    crate table_name: String,
+    crate if_exists: bool,
+}
+
+named!(parse_if_exists<&str, ()>,
+    do_parse!(
+        tag!("IF") >>
+        multispace1 >>
+        tag!("EXISTS") >>
+        multispace1 >>
+        ()
+    )
+);
+
+named!(parse_drop_table_statement<&str, Statement>,
+    do_parse!(
+        tag!("DROP") >>
+        multispace1 >>
+        tag!("TABLE") >>
+        multispace1 >>
+        if_exists: opt!(parse_if_exists) >>
+        table_name: alphanumeric1 >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::DropTable(DropTableStatement {
+            table_name: table_name.to_string(),
+            if_exists: if_exists.is_some()
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct TruncateStatement {
+    crate table_name: String,
+}
+
+named!(parse_truncate_statement<&str, Statement>,
+    do_parse!(
+        tag!("TRUNCATE") >>
+        multispace1 >>
+        opt!(terminated!(tag!("TABLE"), multispace1)) >>
+        table_name: alphanumeric1 >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::Truncate(TruncateStatement {
+            table_name: table_name.to_string()
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeleteStatement {
+    crate table_name: String,
+    crate where_clause: Option<Condition>,
+}
+
+named!(parse_delete_statement<&str, Statement>,
+    do_parse!(
+        tag!("DELETE") >>
+        multispace1 >>
+        tag!("FROM") >>
+        multispace1 >>
+        table_name: alphanumeric1 >>
+        where_clause: opt!(preceded!(multispace1, parse_where_clause)) >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::Delete(DeleteStatement {
+            table_name: table_name.to_string(),
+            where_clause
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArithmeticOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithmeticOperator {
+    /// Returns NULL if either operand is NULL. Overflow and division by zero
+    /// are errors, as is anything but integers (or keys).
+    crate fn apply(
+        self,
+        left: &Chamber,
+        right: &Chamber,
+    ) -> Result<Chamber, Box<dyn Error>> {
+        let integer = |chamber: &Chamber| match chamber {
+            Chamber::Integer(i) => Ok(*i),
+            Chamber::Key(k) => Ok(*k as isize),
+            _ => Err(format!(
+                "can't apply {} to non-integer value {}",
+                self.display(),
+                chamber.display()
+            )),
+        };
+        let (left, right) = match (left, right) {
+            (Chamber::Null, _) | (_, Chamber::Null) => {
+                return Ok(Chamber::Null)
+            }
+            (left, right) => (integer(left)?, integer(right)?),
+        };
+        let result = match self {
+            ArithmeticOperator::Add => left.checked_add(right),
+            ArithmeticOperator::Subtract => left.checked_sub(right),
+            ArithmeticOperator::Multiply => left.checked_mul(right),
+            ArithmeticOperator::Divide => {
+                if right == 0 {
+                    return Err(From::from("division by zero"));
+                }
+                left.checked_div(right)
+            }
+        };
+        result.map(Chamber::Integer).ok_or_else(|| {
+            From::from(format!(
+                "integer overflow in {} {} {}",
+                left,
+                self.display(),
+                right
+            ))
+        })
+    }
+
+    crate fn display(self) -> &'static str {
+        match self {
+            ArithmeticOperator::Add => "+",
+            ArithmeticOperator::Subtract => "-",
+            ArithmeticOperator::Multiply => "*",
+            ArithmeticOperator::Divide => "/",
+        }
+    }
+}
+
+/// A value to be computed from each row that an UPDATE changes (and
+/// evaluated against the row as it was before the update).
+#[allow(unreachable_pub)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValueExpression {
+    Literal(Chamber),
+    Column(String),
+    Arithmetic(
+        Box<ValueExpression>,
+        ArithmeticOperator,
+        Box<ValueExpression>,
+    ),
+}
+
+impl ValueExpression {
+    crate fn display(&self) -> String {
+        match self {
+            ValueExpression::Literal(Chamber::String(string)) => {
+                format!("'{}'", string)
+            }
+            ValueExpression::Literal(value) => value.display(),
+            ValueExpression::Column(column_name) => column_name.clone(),
+            ValueExpression::Arithmetic(left, operator, right) => format!(
+                "({} {} {})",
+                left.display(),
+                operator.display(),
+                right.display()
+            ),
+        }
+    }
+}
+
+// Folds `a - b + c` into `(a - b) + c`
+fn associate_left(
+    first: ValueExpression,
+    rest: Vec<(ArithmeticOperator, ValueExpression)>,
+) -> ValueExpression {
+    rest.into_iter().fold(first, |left, (operator, right)| {
+        ValueExpression::Arithmetic(Box::new(left), operator, Box::new(right))
+    })
+}
+
+named!(parse_value_factor<&str, ValueExpression>,
+    alt!(
+        map!(literal, ValueExpression::Literal) |
+        map!(column_reference, ValueExpression::Column) |
+        delimited!(
+            terminated!(char!('('), multispace0),
+            parse_value_expression,
+            preceded!(multispace0, char!(')'))
+        )
+    )
+);
+
+named!(parse_value_term<&str, ValueExpression>,
+    do_parse!(
+        first: parse_value_factor >>
+        rest: many0!(pair!(
+            delimited!(
+                multispace0,
+                alt!(
+                    value!(ArithmeticOperator::Multiply, char!('*')) |
+                    value!(ArithmeticOperator::Divide, char!('/'))
+                ),
+                multispace0
+            ),
+            parse_value_factor
+        )) >>
+        (associate_left(first, rest))
+    )
+);
+
+named!(parse_value_expression<&str, ValueExpression>,
+    do_parse!(
+        first: parse_value_term >>
+        rest: many0!(pair!(
+            delimited!(
+                multispace0,
+                alt!(
+                    value!(ArithmeticOperator::Add, char!('+')) |
+                    value!(ArithmeticOperator::Subtract, char!('-'))
+                ),
+                multispace0
+            ),
+            parse_value_term
+        )) >>
+        (associate_left(first, rest))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Assignment {
+    crate column_name: String,
+    crate value: ValueExpression,
+}
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct UpdateStatement {
+    crate table_name: String,
+    crate assignments: Vec<Assignment>,
+    crate where_clause: Option<Condition>,
+}
+
+named!(parse_assignment<&str, Assignment>,
+    do_parse!(
+        column_name: alphanumeric1 >>
+        multispace0 >>
+        tag!("=") >>
+        multispace0 >>
+        value: parse_value_expression >>
+        (Assignment { column_name: column_name.to_owned(), value })
+    )
+);
+
+named!(parse_update_statement<&str, Statement>,
+    do_parse!(
+        tag!("UPDATE") >>
+        multispace1 >>
+        table_name: alphanumeric1 >>
+        multispace1 >>
+        tag!("SET") >>
+        multispace1 >>
+        assignments: separated_nonempty_list!(commaspace, parse_assignment) >>
+        where_clause: opt!(preceded!(multispace1, parse_where_clause)) >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::Update(UpdateStatement {
+            table_name: table_name.to_string(),
+            assignments,
+            where_clause
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExplainStatement {
+    // whether to execute the statement, reporting what each step of the plan
+    // actually did
+    crate analyze: bool,
+    crate statement: SelectStatement,
+}
+
+fn select_statement(statement: Statement) -> Option<SelectStatement> {
+    match statement {
+        Statement::Select(statement) => Some(statement),
+        _ => None,
+    }
+}
+
+named!(parse_explain_statement<&str, Statement>,
+    do_parse!(
+        tag!("EXPLAIN") >>
+        multispace1 >>
+        analyze: opt!(terminated!(tag!("ANALYZE"), multispace1)) >>
+        statement: map_opt!(parse_select_statement, select_statement) >>
+        (Statement::Explain(ExplainStatement {
+            analyze: analyze.is_some(),
+            statement
+        }))
+    )
+);
+
+#[allow(unreachable_pub)]
+#[derive(Debug, PartialEq, Eq)]
+pub struct AnalyzeStatement {
+    // the table to collect statistics for (or every table, if none is named)
+    crate table_name: Option<String>,
+}
+
+named!(parse_analyze_statement<&str, Statement>,
+    do_parse!(
+        tag!("ANALYZE") >>
+        table_name: opt!(preceded!(multispace1, alphanumeric1)) >>
+        multispace0 >>
+        tag!(";") >>
+        (Statement::Analyze(AnalyzeStatement {
+            table_name: table_name.map(|name| name.to_string())
+        }))
+    )
+);
+
+// nom doesn't know about `pub(crate)`/`crate` (Issue #807, PR #792)
+named!(pub parse_statement<&str, Statement>,
+    alt!(parse_select_statement |
+         parse_insert_statement |
+         parse_create_table_statement |
+         parse_create_index_statement |
+         parse_drop_table_statement |
+         parse_truncate_statement |
+         parse_delete_statement |
+         parse_update_statement |
+         parse_explain_statement |
+         parse_analyze_statement)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::table::{Chamber, ColumnType};
+
+    #[test]
+    fn concerning_parsing_a_where_clause_for_an_integer_column() {
+        assert_eq!(
+            parse_where_clause("WHERE year = 2018 "),
+            Ok((
+                " ",
+                Condition::Comparison {
+                    column: ColumnExpression::Column("year".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::Integer(2018)
+                }
+            ))
+        );
+        assert_eq!(
+            parse_where_clause("WHERE year > -44;"),
+            Ok((
+                ";",
+                Condition::Comparison {
+                    column: ColumnExpression::Column("year".to_owned()),
+                    operator: ComparisonOperator::Greater,
+                    value: Chamber::Integer(-44)
+                }
+            ))
+        );
+        // (an integer too big to be one is an error, not a panic)
+        assert!(integer_literal("-99999999999999999999999;").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_conjunctions_and_disjunctions() {
+        let comparison = |column_name: &str, value| {
+            Box::new(Condition::Comparison {
+                column: ColumnExpression::Column(column_name.to_owned()),
+                operator: ComparisonOperator::Equal,
+                value: Chamber::Integer(value),
+            })
+        };
+        // AND binds more tightly than OR
+        assert_eq!(
+            parse_condition("a = 1 OR b = 2 AND c = 3;"),
+            Ok((
+                ";",
+                Condition::Or(
+                    comparison("a", 1),
+                    Box::new(Condition::And(
+                        comparison("b", 2),
+                        comparison("c", 3)
+                    ))
+                )
+            ))
+        );
+        // ... unless parenthesized
+        assert_eq!(
+            parse_condition("(a = 1 OR b = 2) AND c = 3;"),
+            Ok((
+                ";",
+                Condition::And(
+                    Box::new(Condition::Or(
+                        comparison("a", 1),
+                        comparison("b", 2)
+                    )),
+                    comparison("c", 3)
+                )
+            ))
+        );
+        // chains associate to the left
+        assert_eq!(
+            parse_condition("a = 1 AND b = 2 AND c = 3;"),
+            Ok((
+                ";",
+                Condition::And(
+                    Box::new(Condition::And(
+                        comparison("a", 1),
+                        comparison("b", 2)
+                    )),
+                    comparison("c", 3)
+                )
+            ))
+        );
+        // (the input can end after the whitespace following a condition)
+        assert_eq!(
+            parse_condition("a = 1 OR b = 2 "),
+            Ok((" ", Condition::Or(comparison("a", 1), comparison("b", 2))))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_comparison_operators() {
+        for &(source, operator) in &[
+            ("year = 1990;", ComparisonOperator::Equal),
+            ("year <> 1990;", ComparisonOperator::NotEqual),
+            ("year != 1990;", ComparisonOperator::NotEqual),
+            ("year < 1990;", ComparisonOperator::Less),
+            ("year <= 1990;", ComparisonOperator::LessOrEqual),
+            ("year > 1990;", ComparisonOperator::Greater),
+            ("year >= 1990;", ComparisonOperator::GreaterOrEqual),
+            ("year>=1990;", ComparisonOperator::GreaterOrEqual),
+        ] {
+            assert_eq!(
+                parse_condition(source),
+                Ok((
+                    ";",
+                    Condition::Comparison {
+                        column: ColumnExpression::Column("year".to_owned()),
+                        operator,
+                        value: Chamber::Integer(1990),
+                    }
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn concerning_applying_comparison_operators() {
+        let (one, two) = (Chamber::Integer(1), Chamber::Integer(2));
+        assert_eq!(ComparisonOperator::Less.apply(&one, &two), Some(true));
+        assert_eq!(
+            ComparisonOperator::LessOrEqual.apply(&one, &one),
+            Some(true)
+        );
+        assert_eq!(
+            ComparisonOperator::Greater.apply(&one, &two),
+            Some(false)
+        );
+        assert_eq!(
+            ComparisonOperator::NotEqual.apply(&one, &two),
+            Some(true)
+        );
+        let word = Chamber::String("1".to_owned());
+        assert_eq!(ComparisonOperator::Equal.apply(&one, &word), None);
+        assert_eq!(ComparisonOperator::NotEqual.apply(&one, &word), None);
+        let null = Chamber::Null;
+        assert_eq!(ComparisonOperator::Equal.apply(&null, &null), None);
+        assert_eq!(ComparisonOperator::NotEqual.apply(&one, &null), None);
+    }
+
+    #[test]
+    fn concerning_parsing_negations() {
+        assert_eq!(
+            parse_condition("NOT a = 1 AND NOT(b = 2);"),
+            Ok((
+                ";",
+                Condition::And(
+                    Box::new(Condition::Not(Box::new(
+                        Condition::Comparison {
+                            column: ColumnExpression::Column("a".to_owned()),
+                            operator: ComparisonOperator::Equal,
+                            value: Chamber::Integer(1),
+                        }
+                    ))),
+                    Box::new(Condition::Not(Box::new(
+                        Condition::Comparison {
+                            column: ColumnExpression::Column("b".to_owned()),
+                            operator: ComparisonOperator::Equal,
+                            value: Chamber::Integer(2),
+                        }
+                    )))
+                )
+            ))
+        );
+        assert_eq!(
+            parse_condition("NOTE = 'fine';"),
+            Ok((
+                ";",
+                Condition::Comparison {
+                    column: ColumnExpression::Column("NOTE".to_owned()),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::String("fine".to_owned()),
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_a_select_star_statement() {
+        assert_eq!(
+            parse_select_statement("SELECT * FROM books WHERE year = 2018;"),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Star,
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("year".to_owned()),
+                        operator: ComparisonOperator::Equal,
+                        value: Chamber::Integer(2018)
+                    }),
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: None,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_a_whereless_select_statement() {
+        assert_eq!(
+            parse_select_statement("SELECT * FROM books ;"),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Star,
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: None,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_a_select_column_names_statement() {
+        assert_eq!(
+            parse_select_statement(
+                "SELECT title, author FROM books WHERE year = 2018;"
+            ),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Expressions(vec![
+                        ColumnExpression::Column("title".to_owned()),
+                        ColumnExpression::Column("author".to_owned()),
+                    ]),
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("year".to_owned()),
+                        operator: ComparisonOperator::Equal,
+                        value: Chamber::Integer(2018),
+                    }),
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: None,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_the_parsing_of_literals() {
+        assert_eq!(
+            string_literal("'hello SQL world'"),
+            Ok((
+                "",
+                Chamber::String("hello SQL world".to_owned())
+            ))
+        );
+        assert_eq!(
+            literal("'hello SQL world'"),
+            Ok((
+                "",
+                Chamber::String("hello SQL world".to_owned())
+            ))
+        );
+        assert_eq!(
+            integer_literal("9001 "),
+            Ok((" ", Chamber::Integer(9001)))
+        );
+        assert_eq!(
+            literal("9001 "),
+            Ok((" ", Chamber::Integer(9001)))
+        );
+        assert_eq!(literal("NULL)"), Ok((")", Chamber::Null)));
+        assert!(literal("NULLS)").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_null_tests() {
+        let year = || ColumnExpression::Column("year".to_owned());
+        assert_eq!(
+            parse_condition("year IS NULL OR year IS NOT NULL;"),
+            Ok((
+                ";",
+                Condition::Or(
+                    Box::new(Condition::IsNull(year())),
+                    Box::new(Condition::Not(Box::new(Condition::IsNull(
+                        year()
+                    )))),
+                )
+            ))
+        );
+        assert_eq!(
+            parse_condition("year = NULL;"),
+            Ok((
+                ";",
+                Condition::Comparison {
+                    column: year(),
+                    operator: ComparisonOperator::Equal,
+                    value: Chamber::Null,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_the_parsing_of_value_lists() {
+        assert_eq!(
+            parse_values("(1, 'Structure and Interpretation')"),
+            Ok((
+                "",
+                vec![
+                    Chamber::Integer(1),
+                    Chamber::String("Structure and Interpretation".to_owned()),
+                ]
+            ))
+        )
+    }
+
+    #[test]
+    fn concerning_parsing_aggregates() {
+        assert_eq!(
+            parse_select_column_clause(
+                "COUNT(*), MAX( year ), AVG(year) FROM"
+            ),
+            Ok((
+                " FROM",
+                ColumnClause::Expressions(vec![
+                    ColumnExpression::Aggregate {
+                        function: AggregateFunction::Count,
+                        column_name: None,
+                    },
+                    ColumnExpression::Aggregate {
+                        function: AggregateFunction::Max,
+                        column_name: Some("year".to_owned()),
+                    },
+                    ColumnExpression::Aggregate {
+                        function: AggregateFunction::Average,
+                        column_name: Some("year".to_owned()),
+                    },
+                ])
+            ))
+        );
+        assert_eq!(
+            parse_select_column_clause("COUNT, MAXIMUM FROM"),
+            Ok((
+                " FROM",
+                ColumnClause::Expressions(vec![
+                    ColumnExpression::Column("COUNT".to_owned()),
+                    ColumnExpression::Column("MAXIMUM".to_owned()),
+                ])
+            ))
         );
+        assert!(parse_statement("SELECT SUM(*) FROM books;").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_group_by_and_having_clauses() {
         assert_eq!(
-            literal("9001 "),
-            Ok((" ", Chamber::Integer(9001)))
-        )
+            parse_statement(
+                "SELECT year, COUNT(*) FROM books GROUP BY year \
+                 HAVING COUNT(*) > 1;"
+            ),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Expressions(vec![
+                        ColumnExpression::Column("year".to_owned()),
+                        ColumnExpression::Aggregate {
+                            function: AggregateFunction::Count,
+                            column_name: None,
+                        },
+                    ]),
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: None,
+                    group_by: vec!["year".to_owned()],
+                    having: Some(Condition::Comparison {
+                        column: ColumnExpression::Aggregate {
+                            function: AggregateFunction::Count,
+                            column_name: None,
+                        },
+                        operator: ComparisonOperator::Greater,
+                        value: Chamber::Integer(1),
+                    }),
+                    order_by: vec![],
+                    limit: None,
+                })
+            ))
+        );
     }
 
     #[test]
-    fn concerning_the_parsing_of_value_lists() {
+    fn concerning_parsing_a_select_statement_with_an_order_by_clause() {
         assert_eq!(
-            parse_values("(1, 'Structure and Interpretation')"),
+            parse_statement(
+                "SELECT title FROM books WHERE year > 2000 \
+                 ORDER BY year DESC, title;"
+            ),
             Ok((
                 "",
-                vec![
-                    Chamber::Integer(1),
-                    Chamber::String("Structure and Interpretation".to_owned()),
-                ]
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Expressions(vec![
+                        ColumnExpression::Column("title".to_owned()),
+                    ]),
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("year".to_owned()),
+                        operator: ComparisonOperator::Greater,
+                        value: Chamber::Integer(2000),
+                    }),
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![
+                        SortKey {
+                            column_name: "year".to_owned(),
+                            order: SortOrder::Descending,
+                        },
+                        SortKey {
+                            column_name: "title".to_owned(),
+                            order: SortOrder::Ascending,
+                        },
+                    ],
+                    limit: None,
+                })
             ))
-        )
+        );
+        assert_eq!(
+            parse_statement("SELECT * FROM books ORDER BY pk ASC;"),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Star,
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![SortKey {
+                        column_name: "pk".to_owned(),
+                        order: SortOrder::Ascending,
+                    }],
+                    limit: None,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_a_select_statement_with_a_limit_clause() {
+        assert_eq!(
+            parse_statement("SELECT * FROM books LIMIT 10 OFFSET 20;"),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Star,
+                    table_name: "books".to_owned(),
+                    table_alias: None,
+                    joins: vec![],
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: Some(Limit {
+                        count: 10,
+                        offset: 20,
+                    }),
+                })
+            ))
+        );
+        assert_eq!(
+            parse_limit_clause("LIMIT 5;"),
+            Ok((
+                ";",
+                Limit {
+                    count: 5,
+                    offset: 0,
+                }
+            ))
+        );
+        // (a count or offset too big to be one is an error, not a panic)
+        for source in &[
+            "SELECT * FROM books LIMIT 99999999999999999999999;",
+            "SELECT * FROM books LIMIT 5 OFFSET 99999999999999999999999;",
+        ] {
+            assert!(parse_statement(source).is_err());
+        }
+    }
+
+    #[test]
+    fn concerning_parsing_joins() {
+        assert_eq!(
+            parse_statement(
+                "SELECT b.title, authors.name FROM books AS b \
+                 LEFT OUTER JOIN authors ON b.author = authors.pk \
+                 CROSS JOIN shelves s WHERE s.pk = 1;"
+            ),
+            Ok((
+                "",
+                Statement::Select(SelectStatement {
+                    column_names: ColumnClause::Expressions(vec![
+                        ColumnExpression::Column("b.title".to_owned()),
+                        ColumnExpression::Column("authors.name".to_owned()),
+                    ]),
+                    table_name: "books".to_owned(),
+                    table_alias: Some("b".to_owned()),
+                    joins: vec![
+                        JoinClause {
+                            kind: JoinKind::Left,
+                            table_name: "authors".to_owned(),
+                            table_alias: None,
+                            on: Some(Condition::ColumnComparison {
+                                left: ColumnExpression::Column(
+                                    "b.author".to_owned(),
+                                ),
+                                operator: ComparisonOperator::Equal,
+                                right: ColumnExpression::Column(
+                                    "authors.pk".to_owned(),
+                                ),
+                            }),
+                        },
+                        JoinClause {
+                            kind: JoinKind::Cross,
+                            table_name: "shelves".to_owned(),
+                            table_alias: Some("s".to_owned()),
+                            on: None,
+                        },
+                    ],
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("s.pk".to_owned()),
+                        operator: ComparisonOperator::Equal,
+                        value: Chamber::Integer(1),
+                    }),
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                    limit: None,
+                })
+            ))
+        );
+        assert_eq!(
+            parse_join_kind("JOIN"),
+            Ok(("", JoinKind::Inner))
+        );
+        assert_eq!(
+            parse_join_kind("INNER JOIN"),
+            Ok(("", JoinKind::Inner))
+        );
+        assert_eq!(
+            parse_join_kind("RIGHT JOIN"),
+            Ok(("", JoinKind::Right))
+        );
+        // keywords aren't taken for aliases
+        assert_eq!(
+            parse_table_reference("books WHERE pk = 1;"),
+            Ok((" WHERE pk = 1;", ("books".to_owned(), None)))
+        );
     }
 
     #[test]
@@ -282,6 +1760,7 @@ mod tests {
                 "",
                 Statement::Insert(InsertStatement {
                     table_name: "prices".to_owned(),
+                    column_names: None,
                     values: vec![
                         Chamber::Integer(120),
                         Chamber::Integer(8401),
@@ -302,6 +1781,7 @@ mod tests {
                 "",
                 Statement::Insert(InsertStatement {
                     table_name: "books".to_owned(),
+                    column_names: None,
                     values: vec![
                         Chamber::String(
                             "Mathematical Analysis: A Concise Introduction"
@@ -314,4 +1794,365 @@ mod tests {
         );
     }
 
+    #[test]
+    fn concerning_parsing_a_create_table_statement() {
+        assert_eq!(
+            parse_statement(
+                "CREATE TABLE books (title STRING, year INTEGER);"
+            ),
+            Ok((
+                "",
+                Statement::CreateTable(CreateTableStatement {
+                    table_name: "books".to_owned(),
+                    columns: vec![
+                        ColumnDefinition {
+                            name: "title".to_owned(),
+                            column_type: ColumnType::String,
+                            constraints: vec![],
+                        },
+                        ColumnDefinition {
+                            name: "year".to_owned(),
+                            column_type: ColumnType::Integer,
+                            constraints: vec![],
+                        },
+                    ],
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_column_constraints() {
+        assert_eq!(
+            parse_statement(
+                "CREATE TABLE books (title STRING NOT NULL UNIQUE, \
+                 year INTEGER DEFAULT 2018 CHECK (year > 1400));"
+            ),
+            Ok((
+                "",
+                Statement::CreateTable(CreateTableStatement {
+                    table_name: "books".to_owned(),
+                    columns: vec![
+                        ColumnDefinition {
+                            name: "title".to_owned(),
+                            column_type: ColumnType::String,
+                            constraints: vec![
+                                ColumnConstraint::NotNull,
+                                ColumnConstraint::Unique,
+                            ],
+                        },
+                        ColumnDefinition {
+                            name: "year".to_owned(),
+                            column_type: ColumnType::Integer,
+                            constraints: vec![
+                                ColumnConstraint::Default(Chamber::Integer(
+                                    2018,
+                                )),
+                                ColumnConstraint::Check(Condition::Comparison {
+                                    column: ColumnExpression::Column(
+                                        "year".to_owned(),
+                                    ),
+                                    operator: ComparisonOperator::Greater,
+                                    value: Chamber::Integer(1400),
+                                }),
+                            ],
+                        },
+                    ],
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_foreign_keys() {
+        let references = |column_name: &str, on_delete| {
+            ColumnConstraint::References {
+                table_name: "authors".to_owned(),
+                column_name: column_name.to_owned(),
+                on_delete,
+            }
+        };
+        assert_eq!(
+            parse_column_definition(
+                "author INTEGER REFERENCES authors(pk) NOT NULL,"
+            ),
+            Ok((
+                ",",
+                ColumnDefinition {
+                    name: "author".to_owned(),
+                    column_type: ColumnType::Integer,
+                    constraints: vec![
+                        references("pk", ReferentialAction::Restrict),
+                        ColumnConstraint::NotNull,
+                    ],
+                }
+            ))
+        );
+        assert_eq!(
+            parse_column_constraint(
+                "REFERENCES authors ( pk ) ON DELETE CASCADE)"
+            ),
+            Ok((")", references("pk", ReferentialAction::Cascade)))
+        );
+        assert_eq!(
+            parse_column_constraint(
+                "REFERENCES authors ON DELETE SET NULL)"
+            ),
+            Ok((")", references("pk", ReferentialAction::SetNull)))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_an_insert_statement_with_column_names() {
+        assert_eq!(
+            parse_statement(
+                "INSERT INTO books (year, title) VALUES (1, 'A');"
+            ),
+            Ok((
+                "",
+                Statement::Insert(InsertStatement {
+                    table_name: "books".to_owned(),
+                    column_names: Some(vec![
+                        "year".to_owned(),
+                        "title".to_owned(),
+                    ]),
+                    values: vec![
+                        Chamber::Integer(1),
+                        Chamber::String("A".to_owned()),
+                    ],
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_create_index_statements() {
+        assert_eq!(
+            parse_statement("CREATE INDEX booksbyyear ON books (year);"),
+            Ok((
+                "",
+                Statement::CreateIndex(CreateIndexStatement {
+                    index_name: "booksbyyear".to_owned(),
+                    table_name: "books".to_owned(),
+                    column_names: vec!["year".to_owned()],
+                    unique: false,
+                    kind: IndexKind::BTree,
+                })
+            ))
+        );
+        assert_eq!(
+            parse_statement(
+                "CREATE UNIQUE INDEX booksbytitle ON books (title, year);"
+            ),
+            Ok((
+                "",
+                Statement::CreateIndex(CreateIndexStatement {
+                    index_name: "booksbytitle".to_owned(),
+                    table_name: "books".to_owned(),
+                    column_names: vec![
+                        "title".to_owned(),
+                        "year".to_owned()
+                    ],
+                    unique: true,
+                    kind: IndexKind::BTree,
+                })
+            ))
+        );
+        assert_eq!(
+            parse_statement(
+                "CREATE INDEX booksbytitle ON books USING HASH (title);"
+            ),
+            Ok((
+                "",
+                Statement::CreateIndex(CreateIndexStatement {
+                    index_name: "booksbytitle".to_owned(),
+                    table_name: "books".to_owned(),
+                    column_names: vec!["title".to_owned()],
+                    unique: false,
+                    kind: IndexKind::Hash,
+                })
+            ))
+        );
+        assert!(parse_statement("CREATE INDEX ON books (year);").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_explain_statements() {
+        let select = |sql| match parse_statement(sql) {
+            Ok(("", Statement::Select(statement))) => statement,
+            other => panic!("unexpected parse {:?}", other),
+        };
+        assert_eq!(
+            parse_statement("EXPLAIN SELECT title FROM books;"),
+            Ok((
+                "",
+                Statement::Explain(ExplainStatement {
+                    analyze: false,
+                    statement: select("SELECT title FROM books;"),
+                })
+            ))
+        );
+        assert_eq!(
+            parse_statement(
+                "EXPLAIN ANALYZE SELECT * FROM books WHERE year > 2000;"
+            ),
+            Ok((
+                "",
+                Statement::Explain(ExplainStatement {
+                    analyze: true,
+                    statement: select(
+                        "SELECT * FROM books WHERE year > 2000;"
+                    ),
+                })
+            ))
+        );
+        assert!(parse_statement("EXPLAIN DELETE FROM books;").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_analyze_statements() {
+        assert_eq!(
+            parse_statement("ANALYZE;"),
+            Ok((
+                "",
+                Statement::Analyze(AnalyzeStatement { table_name: None })
+            ))
+        );
+        assert_eq!(
+            parse_statement("ANALYZE books ;"),
+            Ok((
+                "",
+                Statement::Analyze(AnalyzeStatement {
+                    table_name: Some("books".to_owned())
+                })
+            ))
+        );
+        assert!(parse_statement("ANALYZEbooks;").is_err());
+    }
+
+    #[test]
+    fn concerning_parsing_drop_table_statements() {
+        assert_eq!(
+            parse_statement("DROP TABLE books;"),
+            Ok((
+                "",
+                Statement::DropTable(DropTableStatement {
+                    table_name: "books".to_owned(),
+                    if_exists: false,
+                })
+            ))
+        );
+        assert_eq!(
+            parse_statement("DROP TABLE IF EXISTS books;"),
+            Ok((
+                "",
+                Statement::DropTable(DropTableStatement {
+                    table_name: "books".to_owned(),
+                    if_exists: true,
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_truncate_statements() {
+        let expected = Statement::Truncate(TruncateStatement {
+            table_name: "books".to_owned(),
+        });
+        assert_eq!(parse_statement("TRUNCATE books;"), Ok(("", expected)));
+        let expected = Statement::Truncate(TruncateStatement {
+            table_name: "books".to_owned(),
+        });
+        assert_eq!(
+            parse_statement("TRUNCATE TABLE books;"),
+            Ok(("", expected))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_delete_statements() {
+        assert_eq!(
+            parse_statement("DELETE FROM books;"),
+            Ok((
+                "",
+                Statement::Delete(DeleteStatement {
+                    table_name: "books".to_owned(),
+                    where_clause: None,
+                })
+            ))
+        );
+        assert_eq!(
+            parse_statement(
+                "DELETE FROM books WHERE title = 'Infinite Jest';"
+            ),
+            Ok((
+                "",
+                Statement::Delete(DeleteStatement {
+                    table_name: "books".to_owned(),
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("title".to_owned()),
+                        operator: ComparisonOperator::Equal,
+                        value: Chamber::String("Infinite Jest".to_owned()),
+                    }),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn concerning_parsing_an_update_statement() {
+        assert_eq!(
+            parse_statement(
+                "UPDATE books SET title = 'The Sequences', year = 2015 \
+                 WHERE pk = 1;"
+            ),
+            Ok((
+                "",
+                Statement::Update(UpdateStatement {
+                    table_name: "books".to_owned(),
+                    assignments: vec![
+                        Assignment {
+                            column_name: "title".to_owned(),
+                            value: ValueExpression::Literal(Chamber::String(
+                                "The Sequences".to_owned()
+                            )),
+                        },
+                        Assignment {
+                            column_name: "year".to_owned(),
+                            value: ValueExpression::Literal(
+                                Chamber::Integer(2015)
+                            ),
+                        },
+                    ],
+                    where_clause: Some(Condition::Comparison {
+                        column: ColumnExpression::Column("pk".to_owned()),
+                        operator: ComparisonOperator::Equal,
+                        value: Chamber::Integer(1),
+                    }),
+                })
+            ))
+        );
+        let value = |sql| match parse_statement(sql) {
+            Ok((_, Statement::Update(mut statement))) => {
+                statement.assignments.remove(0).value.display()
+            }
+            other => panic!("unexpected parse {:?}", other),
+        };
+        assert_eq!(
+            value("UPDATE books SET copies = copies + 1;"),
+            "(copies + 1)"
+        );
+        assert_eq!(
+            value(
+                "UPDATE books SET copies = copies - 2*year + 1 WHERE pk = 1;"
+            ),
+            "((copies - (2 * year)) + 1)"
+        );
+        assert_eq!(
+            value("UPDATE books SET copies = (copies - 2) / sold;"),
+            "((copies - 2) / sold)"
+        );
+        assert_eq!(value("UPDATE books SET copies = sold;"), "sold");
+    }
+
 }
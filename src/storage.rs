@@ -0,0 +1,860 @@
+//! The file format a database is stored in. A file is a sequence of
+//! fixed-size pages: the first is a header (saying which version of the
+//! format the file is in, and how much of the write-ahead log has been
+//! folded into it), and the others are linked into chains, each
+//! holding a stream of bytes—the catalog (starting at page 1) describing
+//! every table and where its rows are, and a chain of data pages for each
+//! table's rows. (A row that doesn't fit in what's left of a page carries on
+//! in the next one.)
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+use crate::query_planner::WhereSubcommand;
+use crate::sql::{ComparisonOperator, IndexKind, ReferentialAction};
+use crate::table::{
+    Chamber, Column, ColumnStatistics, ColumnType, Constraints, ForeignKey,
+    Row, Table, TableSchema, TableStatistics,
+};
+
+crate const PAGE_SIZE: usize = 4096;
+/// Bumped whenever the format changes (files in any other version are
+/// refused, rather than misread).
+crate const FORMAT_VERSION: u32 = 2;
+
+const MAGIC: &[u8; 8] = b"EPILOGUE";
+// The page kind (one byte), the next page in the chain (four bytes, or zero
+// at the end of the chain), and how much of the page is used (two bytes)
+const PAGE_HEADER_SIZE: usize = 7;
+const PAGE_CAPACITY: usize = PAGE_SIZE - PAGE_HEADER_SIZE;
+const CATALOG_PAGE: u32 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PageKind {
+    Catalog = 1,
+    Data = 2,
+}
+
+crate fn corrupt(what: &str) -> Box<dyn Error> {
+    From::from(format!("database file is corrupt ({})", what))
+}
+
+/// Serializes values into bytes: integers are little-endian, and strings and
+/// sequences are preceded by their lengths.
+#[derive(Default)]
+crate struct Encoder {
+    crate bytes: Vec<u8>,
+}
+
+impl Encoder {
+    crate fn new() -> Self {
+        Self::default()
+    }
+
+    crate fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    fn little_endian(&mut self, n: u64, width: usize) {
+        for i in 0..width {
+            self.bytes.push((n >> (8 * i)) as u8);
+        }
+    }
+
+    crate fn u16(&mut self, n: u16) {
+        self.little_endian(u64::from(n), 2);
+    }
+
+    crate fn u32(&mut self, n: u32) {
+        self.little_endian(u64::from(n), 4);
+    }
+
+    crate fn u64(&mut self, n: u64) {
+        self.little_endian(n, 8);
+    }
+
+    crate fn usize(&mut self, n: usize) {
+        self.u64(n as u64);
+    }
+
+    crate fn bool(&mut self, b: bool) {
+        self.u8(b as u8);
+    }
+
+    crate fn string(&mut self, string: &str) {
+        self.usize(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    crate fn option<T>(
+        &mut self,
+        value: Option<&T>,
+        encode: fn(&mut Self, &T),
+    ) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    crate fn chamber(&mut self, chamber: &Chamber) {
+        match *chamber {
+            Chamber::Null => self.u8(0),
+            Chamber::Key(k) => {
+                self.u8(1);
+                self.usize(k);
+            }
+            Chamber::Integer(i) => {
+                self.u8(2);
+                self.u64(i as i64 as u64);
+            }
+            Chamber::String(ref s) => {
+                self.u8(3);
+                self.string(s);
+            }
+        }
+    }
+
+    crate fn row(&mut self, row: &Row) {
+        self.usize(row.0.len());
+        for chamber in &row.0 {
+            self.chamber(chamber);
+        }
+    }
+
+    crate fn column_type(&mut self, column_type: ColumnType) {
+        self.u8(match column_type {
+            ColumnType::Key => 0,
+            ColumnType::Integer => 1,
+            ColumnType::String => 2,
+        });
+    }
+
+    crate fn condition(&mut self, condition: &WhereSubcommand) {
+        match condition {
+            WhereSubcommand::Unconditional => self.u8(0),
+            WhereSubcommand::Comparison {
+                column_offset,
+                operator,
+                value,
+            } => {
+                self.u8(1);
+                self.usize(*column_offset);
+                self.comparison_operator(*operator);
+                self.chamber(value);
+            }
+            WhereSubcommand::ColumnComparison {
+                left_offset,
+                operator,
+                right_offset,
+            } => {
+                self.u8(2);
+                self.usize(*left_offset);
+                self.comparison_operator(*operator);
+                self.usize(*right_offset);
+            }
+            WhereSubcommand::And(left, right) => {
+                self.u8(3);
+                self.condition(left);
+                self.condition(right);
+            }
+            WhereSubcommand::Or(left, right) => {
+                self.u8(4);
+                self.condition(left);
+                self.condition(right);
+            }
+            WhereSubcommand::Not(negated) => {
+                self.u8(5);
+                self.condition(negated);
+            }
+            WhereSubcommand::IsNull { column_offset } => {
+                self.u8(6);
+                self.usize(*column_offset);
+            }
+        }
+    }
+
+    crate fn index_kind(&mut self, kind: IndexKind) {
+        self.u8(match kind {
+            IndexKind::BTree => 0,
+            IndexKind::Hash => 1,
+        });
+    }
+
+    fn comparison_operator(&mut self, operator: ComparisonOperator) {
+        self.u8(match operator {
+            ComparisonOperator::Equal => 0,
+            ComparisonOperator::NotEqual => 1,
+            ComparisonOperator::Less => 2,
+            ComparisonOperator::LessOrEqual => 3,
+            ComparisonOperator::Greater => 4,
+            ComparisonOperator::GreaterOrEqual => 5,
+        });
+    }
+
+    crate fn column(&mut self, column: &Column) {
+        self.string(&column.name);
+        self.column_type(column.column_type);
+        let constraints = &column.constraints;
+        self.bool(constraints.not_null);
+        self.bool(constraints.unique);
+        self.option(constraints.default.as_ref(), Self::chamber);
+        self.option(constraints.check.as_ref(), Self::condition);
+        self.option(constraints.references.as_ref(), |encoder, reference| {
+            encoder.string(&reference.table_name);
+            encoder.u8(match reference.on_delete {
+                ReferentialAction::Restrict => 0,
+                ReferentialAction::Cascade => 1,
+                ReferentialAction::SetNull => 2,
+            });
+        });
+    }
+
+    fn statistics(&mut self, statistics: &TableStatistics) {
+        self.usize(statistics.row_count);
+        self.usize(statistics.columns.len());
+        for column in &statistics.columns {
+            self.usize(column.distinct_count);
+            self.usize(column.null_count);
+            self.option(column.min.as_ref(), Self::chamber);
+            self.option(column.max.as_ref(), Self::chamber);
+            self.usize(column.histogram.len());
+            for chamber in &column.histogram {
+                self.chamber(chamber);
+            }
+        }
+    }
+
+    /// A table's catalog entry: everything about it but its rows (and the
+    /// entries of its indices, which are rebuilt from the rows).
+    fn table(&mut self, name: &str, table: &Table, first_page: u32) {
+        self.string(name);
+        self.usize(table.next_pk);
+        self.usize(table.rows.len());
+        self.u32(first_page);
+        self.usize(table.schema.layout.len());
+        for column in &table.schema.layout {
+            self.column(column);
+        }
+        self.usize(table.indices.len());
+        for index in &table.indices {
+            self.string(&index.name);
+            self.usize(index.column_offsets.len());
+            for &offset in &index.column_offsets {
+                self.usize(offset);
+            }
+            self.bool(index.unique);
+            self.index_kind(index.entries.kind());
+        }
+        self.option(table.statistics.as_ref(), Self::statistics);
+    }
+}
+
+/// Deserializes what an `Encoder` serialized.
+crate struct Decoder<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Decoder<'b> {
+    crate fn new(bytes: &'b [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    crate fn is_done(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'b [u8], Box<dyn Error>> {
+        if self.bytes.len() - self.position < count {
+            return Err(corrupt("unexpected end of data"));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+
+    crate fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn little_endian(&mut self, width: usize) -> Result<u64, Box<dyn Error>> {
+        let bytes = self.take(width)?;
+        Ok(bytes
+            .iter()
+            .enumerate()
+            .fold(0, |n, (i, &byte)| n | u64::from(byte) << (8 * i)))
+    }
+
+    crate fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(self.little_endian(2)? as u16)
+    }
+
+    crate fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(self.little_endian(4)? as u32)
+    }
+
+    crate fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        self.little_endian(8)
+    }
+
+    crate fn usize(&mut self) -> Result<usize, Box<dyn Error>> {
+        let n = self.u64()?;
+        if n > usize::max_value() as u64 {
+            return Err(corrupt("number out of range"));
+        }
+        Ok(n as usize)
+    }
+
+    /// A count of things to follow, each of which takes at least a byte (so
+    /// a corrupt count fails before anything is allocated for it).
+    crate fn count(&mut self) -> Result<usize, Box<dyn Error>> {
+        let count = self.usize()?;
+        if count > self.bytes.len() - self.position {
+            return Err(corrupt("count out of range"));
+        }
+        Ok(count)
+    }
+
+    crate fn bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(corrupt("bad boolean")),
+        }
+    }
+
+    crate fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let length = self.count()?;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| corrupt("bad string"))
+    }
+
+    crate fn option<T>(
+        &mut self,
+        decode: fn(&mut Self) -> Result<T, Box<dyn Error>>,
+    ) -> Result<Option<T>, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(decode(self)?)),
+            _ => Err(corrupt("bad option")),
+        }
+    }
+
+    crate fn chamber(&mut self) -> Result<Chamber, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(Chamber::Null),
+            1 => Ok(Chamber::Key(self.usize()?)),
+            2 => Ok(Chamber::Integer(self.u64()? as i64 as isize)),
+            3 => Ok(Chamber::String(self.string()?)),
+            _ => Err(corrupt("bad chamber")),
+        }
+    }
+
+    crate fn row(&mut self) -> Result<Row, Box<dyn Error>> {
+        let width = self.count()?;
+        let mut chambers = Vec::with_capacity(width);
+        for _ in 0..width {
+            chambers.push(self.chamber()?);
+        }
+        Ok(Row(chambers))
+    }
+
+    crate fn column_type(&mut self) -> Result<ColumnType, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(ColumnType::Key),
+            1 => Ok(ColumnType::Integer),
+            2 => Ok(ColumnType::String),
+            _ => Err(corrupt("bad column type")),
+        }
+    }
+
+    crate fn condition(&mut self) -> Result<WhereSubcommand, Box<dyn Error>> {
+        Ok(match self.u8()? {
+            0 => WhereSubcommand::Unconditional,
+            1 => WhereSubcommand::Comparison {
+                column_offset: self.usize()?,
+                operator: self.comparison_operator()?,
+                value: self.chamber()?,
+            },
+            2 => WhereSubcommand::ColumnComparison {
+                left_offset: self.usize()?,
+                operator: self.comparison_operator()?,
+                right_offset: self.usize()?,
+            },
+            3 => WhereSubcommand::And(
+                Box::new(self.condition()?),
+                Box::new(self.condition()?),
+            ),
+            4 => WhereSubcommand::Or(
+                Box::new(self.condition()?),
+                Box::new(self.condition()?),
+            ),
+            5 => WhereSubcommand::Not(Box::new(self.condition()?)),
+            6 => WhereSubcommand::IsNull {
+                column_offset: self.usize()?,
+            },
+            _ => return Err(corrupt("bad condition")),
+        })
+    }
+
+    crate fn index_kind(&mut self) -> Result<IndexKind, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(IndexKind::BTree),
+            1 => Ok(IndexKind::Hash),
+            _ => Err(corrupt("bad index kind")),
+        }
+    }
+
+    fn comparison_operator(
+        &mut self,
+    ) -> Result<ComparisonOperator, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(ComparisonOperator::Equal),
+            1 => Ok(ComparisonOperator::NotEqual),
+            2 => Ok(ComparisonOperator::Less),
+            3 => Ok(ComparisonOperator::LessOrEqual),
+            4 => Ok(ComparisonOperator::Greater),
+            5 => Ok(ComparisonOperator::GreaterOrEqual),
+            _ => Err(corrupt("bad comparison operator")),
+        }
+    }
+
+    crate fn column(&mut self) -> Result<Column, Box<dyn Error>> {
+        let name = self.string()?;
+        let column_type = self.column_type()?;
+        let constraints = Constraints {
+            not_null: self.bool()?,
+            unique: self.bool()?,
+            default: self.option(Self::chamber)?,
+            check: self.option(Self::condition)?,
+            references: self.option(|decoder| {
+                Ok(ForeignKey {
+                    table_name: decoder.string()?,
+                    on_delete: match decoder.u8()? {
+                        0 => ReferentialAction::Restrict,
+                        1 => ReferentialAction::Cascade,
+                        2 => ReferentialAction::SetNull,
+                        _ => return Err(corrupt("bad referential action")),
+                    },
+                })
+            })?,
+        };
+        Ok(Column {
+            name,
+            column_type,
+            constraints,
+        })
+    }
+
+    fn statistics(&mut self) -> Result<TableStatistics, Box<dyn Error>> {
+        let row_count = self.usize()?;
+        let mut columns = Vec::new();
+        for _ in 0..self.count()? {
+            let distinct_count = self.usize()?;
+            let null_count = self.usize()?;
+            let min = self.option(Self::chamber)?;
+            let max = self.option(Self::chamber)?;
+            let mut histogram = Vec::new();
+            for _ in 0..self.count()? {
+                histogram.push(self.chamber()?);
+            }
+            columns.push(ColumnStatistics {
+                distinct_count,
+                null_count,
+                min,
+                max,
+                histogram,
+            });
+        }
+        Ok(TableStatistics { row_count, columns })
+    }
+}
+
+// Pages being laid out for a file
+struct Pages {
+    pages: Vec<Vec<u8>>,
+}
+
+impl Pages {
+    fn allocate(&mut self) -> u32 {
+        self.pages.push(vec![0; PAGE_SIZE]);
+        (self.pages.len() - 1) as u32
+    }
+
+    /// Writes the bytes to a chain of pages starting with the given (already
+    /// allocated) page.
+    fn write_chain(&mut self, first: u32, kind: PageKind, bytes: &[u8]) {
+        let mut chunks = bytes.chunks(PAGE_CAPACITY).peekable();
+        let mut page_number = first;
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let next = match chunks.peek() {
+                Some(_) => self.allocate(),
+                None => 0,
+            };
+            let mut header = Encoder::new();
+            header.u8(kind as u8);
+            header.u32(next);
+            header.u16(chunk.len() as u16);
+            let page = &mut self.pages[page_number as usize];
+            page[..PAGE_HEADER_SIZE].copy_from_slice(&header.bytes);
+            page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + chunk.len()]
+                .copy_from_slice(chunk);
+            if next == 0 {
+                break;
+            }
+            page_number = next;
+        }
+    }
+}
+
+/// The bytes of a chain of pages of the file (checking that it doesn't run
+/// off the end of the file, or around in circles).
+fn read_chain(
+    file: &[u8],
+    first: u32,
+    kind: PageKind,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let page_count = file.len() / PAGE_SIZE;
+    let mut bytes = Vec::new();
+    let mut page_number = first as usize;
+    for _ in 0..page_count {
+        if page_number == 0 || page_number >= page_count {
+            return Err(corrupt("broken page chain"));
+        }
+        let page = &file[page_number * PAGE_SIZE..][..PAGE_SIZE];
+        if page[0] != kind as u8 {
+            return Err(corrupt(&format!(
+                "page {} should be a {:?} page",
+                page_number, kind
+            )));
+        }
+        let mut decoder = Decoder::new(&page[1..PAGE_HEADER_SIZE]);
+        let next = decoder.u32()?;
+        let length = decoder.u16()? as usize;
+        if length > PAGE_CAPACITY {
+            return Err(corrupt("page overflows"));
+        }
+        bytes.extend_from_slice(
+            &page[PAGE_HEADER_SIZE..PAGE_HEADER_SIZE + length],
+        );
+        if next == 0 {
+            return Ok(bytes);
+        }
+        page_number = next as usize;
+    }
+    Err(corrupt("page chain loops"))
+}
+
+/// A database's tables, as the contents of a file, along with the sequence
+/// number of the last change in the log that they include.
+crate fn write_tables(
+    tables: &HashMap<String, Table>,
+    checkpoint: u64,
+) -> Vec<u8> {
+    let mut pages = Pages { pages: Vec::new() };
+    let header = pages.allocate();
+    let catalog = pages.allocate();
+    // (tables are written in order of their names, so that the same
+    // database always makes the same file)
+    let mut names = tables.keys().collect::<Vec<_>>();
+    names.sort();
+    let mut encoder = Encoder::new();
+    encoder.usize(names.len());
+    for name in names {
+        let table = &tables[name];
+        let mut rows = Encoder::new();
+        for row in table.rows.values() {
+            rows.row(row);
+        }
+        let first_page = pages.allocate();
+        pages.write_chain(first_page, PageKind::Data, &rows.bytes);
+        encoder.table(name, table, first_page);
+    }
+    pages.write_chain(catalog, PageKind::Catalog, &encoder.bytes);
+
+    let mut header_fields = Encoder::new();
+    header_fields.bytes.extend_from_slice(MAGIC);
+    header_fields.u32(FORMAT_VERSION);
+    header_fields.u32(PAGE_SIZE as u32);
+    header_fields.u32(pages.pages.len() as u32);
+    header_fields.u32(CATALOG_PAGE);
+    header_fields.u64(checkpoint);
+    let header_page = &mut pages.pages[header as usize];
+    header_page[..header_fields.bytes.len()]
+        .copy_from_slice(&header_fields.bytes);
+    pages.pages.concat()
+}
+
+/// The tables of a database, from the contents of a file, along with the
+/// sequence number of the last change in the log that they include.
+crate fn read_tables(
+    file: &[u8],
+) -> Result<(HashMap<String, Table>, u64), Box<dyn Error>> {
+    if file.len() < PAGE_SIZE || &file[..MAGIC.len()] != MAGIC {
+        return Err(From::from("not a database file"));
+    }
+    let mut header = Decoder::new(&file[MAGIC.len()..PAGE_SIZE]);
+    let version = header.u32()?;
+    if version != FORMAT_VERSION {
+        return Err(From::from(format!(
+            "database file is in format version {}, but only version {} is \
+             supported",
+            version, FORMAT_VERSION
+        )));
+    }
+    if header.u32()? as usize != PAGE_SIZE {
+        return Err(corrupt("wrong page size"));
+    }
+    let page_count = header.u32()? as usize;
+    if file.len() != page_count * PAGE_SIZE {
+        return Err(corrupt("wrong length"));
+    }
+    let catalog_page = header.u32()?;
+    let checkpoint = header.u64()?;
+
+    let catalog = read_chain(file, catalog_page, PageKind::Catalog)?;
+    let mut decoder = Decoder::new(&catalog);
+    let mut tables = HashMap::new();
+    for _ in 0..decoder.count()? {
+        let name = decoder.string()?;
+        let next_pk = decoder.usize()?;
+        let row_count = decoder.usize()?;
+        let first_page = decoder.u32()?;
+        let mut layout = Vec::new();
+        for _ in 0..decoder.count()? {
+            layout.push(decoder.column()?);
+        }
+        let width = layout.len();
+        for column in &layout {
+            if let Some(check) = &column.constraints.check {
+                if check.column_offsets().iter().any(|&o| o >= width) {
+                    return Err(corrupt("CHECK column out of range"));
+                }
+            }
+        }
+        let mut table = Table::new(TableSchema { layout });
+        table.next_pk = next_pk;
+
+        let data = read_chain(file, first_page, PageKind::Data)?;
+        let mut rows = Decoder::new(&data);
+        for _ in 0..row_count {
+            let row = rows.row()?;
+            if row.0.len() != table.schema.layout.len() {
+                return Err(corrupt("row doesn't fit its table"));
+            }
+            table.schema.validate_row(&row)?;
+            let pk = row.pk();
+            if pk >= next_pk || table.rows.contains_key(&pk) {
+                return Err(corrupt("bad primary key"));
+            }
+            table.validate_uniqueness(std::iter::once(&row))?;
+            table.place(row);
+        }
+        if !rows.is_done() {
+            return Err(corrupt("extra data after rows"));
+        }
+
+        for _ in 0..decoder.count()? {
+            let name = decoder.string()?;
+            let mut column_offsets = Vec::new();
+            for _ in 0..decoder.count()? {
+                let offset = decoder.usize()?;
+                if offset >= table.schema.layout.len() {
+                    return Err(corrupt("index column out of range"));
+                }
+                column_offsets.push(offset);
+            }
+            let unique = decoder.bool()?;
+            let kind = decoder.index_kind()?;
+            table.create_index(name, column_offsets, unique, kind)?;
+        }
+        table.statistics = decoder.option(Decoder::statistics)?;
+        tables.insert(name, table);
+    }
+    if !decoder.is_done() {
+        return Err(corrupt("extra data after catalog"));
+    }
+    let references = tables.values().flat_map(|table| &table.schema.layout);
+    for column in references {
+        if let Some(foreign_key) = &column.constraints.references {
+            if !tables.contains_key(&foreign_key.table_name) {
+                return Err(corrupt("reference to a missing table"));
+            }
+        }
+    }
+    Ok((tables, checkpoint))
+}
+
+/// Replaces the file at `path` with the bytes, by way of a temporary file
+/// beside it, so that the file is either all old or all new (even if we
+/// crash part of the way through). The rename is synced too (by syncing
+/// the directory), so the new file is there for good once this returns.
+crate fn write_file(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".new");
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)?;
+    let directory = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_tables(title_length: usize) -> HashMap<String, Table> {
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        schema
+            .add_column("year".to_owned(), ColumnType::Integer)
+            .unwrap();
+        let mut table = Table::new(schema);
+        for year in -2..20 {
+            table.place(
+                table
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String("é".repeat(title_length)),
+                        if year == 0 {
+                            Chamber::Null
+                        } else {
+                            Chamber::Integer(year)
+                        },
+                    ]))
+                    .unwrap(),
+            );
+        }
+        table.remove(3);
+        table
+            .create_index("years".to_owned(), vec![2], false, IndexKind::Hash)
+            .unwrap();
+        table.analyze();
+        let mut tables = HashMap::new();
+        tables.insert("books".to_owned(), table);
+        tables.insert("shelves".to_owned(), Table::new(TableSchema::new()));
+        tables
+    }
+
+    #[test]
+    fn concerning_encoding() {
+        let condition = WhereSubcommand::Or(
+            Box::new(WhereSubcommand::Not(Box::new(
+                WhereSubcommand::IsNull { column_offset: 2 },
+            ))),
+            Box::new(WhereSubcommand::And(
+                Box::new(WhereSubcommand::Comparison {
+                    column_offset: 1,
+                    operator: ComparisonOperator::GreaterOrEqual,
+                    value: Chamber::Integer(-1),
+                }),
+                Box::new(WhereSubcommand::ColumnComparison {
+                    left_offset: 1,
+                    operator: ComparisonOperator::NotEqual,
+                    right_offset: 2,
+                }),
+            )),
+        );
+        let row = Row(vec![
+            Chamber::Key(7),
+            Chamber::Integer(isize::min_value()),
+            Chamber::String("Ἀχιλλεύς".to_owned()),
+            Chamber::Null,
+        ]);
+        let mut encoder = Encoder::new();
+        encoder.condition(&condition);
+        encoder.row(&row);
+        encoder.option(None, Encoder::chamber);
+
+        let mut decoder = Decoder::new(&encoder.bytes);
+        let decoded = decoder.condition().unwrap();
+        let column_names = ["pk".to_owned(), "a".to_owned(), "b".to_owned()];
+        assert_eq!(
+            decoded.display(&column_names),
+            "b IS NOT NULL OR a >= -1 AND a <> b"
+        );
+        assert_eq!(decoder.row().unwrap().0, row.0);
+        assert_eq!(decoder.option(Decoder::chamber).unwrap(), None);
+        assert!(decoder.is_done());
+
+        let mut truncated = Decoder::new(&encoder.bytes[..10]);
+        truncated.condition().unwrap_err();
+    }
+
+    #[test]
+    fn concerning_database_files() {
+        // (long enough titles that the rows take several pages, and some
+        // of them are split between pages)
+        let tables = example_tables(1000);
+        let file = write_tables(&tables, 5);
+        assert_eq!(file.len() % PAGE_SIZE, 0);
+        assert!(file.len() > 6 * PAGE_SIZE);
+        assert_eq!(write_tables(&tables, 5), file);
+
+        let (read, checkpoint) = read_tables(&file).unwrap();
+        assert_eq!(checkpoint, 5);
+        assert_eq!(read.len(), 2);
+        let (books, read_books) = (&tables["books"], &read["books"]);
+        assert_eq!(read_books.rows.len(), 21);
+        let read_rows = read_books.rows.values();
+        for (row, read_row) in books.rows.values().zip(read_rows) {
+            assert_eq!(row.0, read_row.0);
+        }
+        assert_eq!(read_books.next_pk, 23);
+        assert_eq!(read_books.statistics, books.statistics);
+        let index = &read_books.indices[0];
+        assert_eq!(index.name, "years");
+        assert_eq!(index.entries.kind(), IndexKind::Hash);
+        assert_eq!(index.entries.len(), 21);
+        assert!(read["shelves"].rows.is_empty());
+    }
+
+    #[test]
+    fn concerning_bad_database_files() {
+        let file = write_tables(&example_tables(10), 0);
+        let error = |file: &[u8]| read_tables(file).err().unwrap().to_string();
+
+        assert_eq!(error(&file[..100]), "not a database file");
+        let mut future = file.clone();
+        future[MAGIC.len()] = 3;
+        assert_eq!(
+            error(&future),
+            "database file is in format version 3, but only version 2 is \
+             supported"
+        );
+        assert_eq!(
+            error(&file[..file.len() - PAGE_SIZE]),
+            "database file is corrupt (wrong length)"
+        );
+        // the catalog page, pointing at itself
+        let mut looped = file.clone();
+        looped[PAGE_SIZE + 1] = 1;
+        assert_eq!(
+            error(&looped),
+            "database file is corrupt (page chain loops)"
+        );
+        // a data page, claiming more than it holds
+        let mut overflowing = file.clone();
+        overflowing[2 * PAGE_SIZE + 5] = 0xff;
+        overflowing[2 * PAGE_SIZE + 6] = 0xff;
+        assert_eq!(
+            error(&overflowing),
+            "database file is corrupt (page overflows)"
+        );
+    }
+}
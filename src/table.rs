@@ -1,8 +1,12 @@
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 
 use prettytable;
 
+use crate::query_planner::{ValueSubcommand, WhereSubcommand};
+use crate::sql::{IndexKind, ReferentialAction};
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 crate enum ColumnType {
     Key,
@@ -10,37 +14,110 @@ crate enum ColumnType {
     String,
 }
 
-// TODO: `Option`-ize to represent NULL?
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Chamber {
     Key(usize),
     Integer(isize),
     String(String),
+    Null,
 }
 
 impl Chamber {
-    crate fn column_type(&self) -> ColumnType {
+    /// `None` for NULL, which doesn't have a type of its own.
+    crate fn column_type(&self) -> Option<ColumnType> {
         match *self {
-            Chamber::Key(_) => ColumnType::Key,
-            Chamber::Integer(_) => ColumnType::Integer,
-            Chamber::String(_) => ColumnType::String,
+            Chamber::Key(_) => Some(ColumnType::Key),
+            Chamber::Integer(_) => Some(ColumnType::Integer),
+            Chamber::String(_) => Some(ColumnType::String),
+            Chamber::Null => None,
         }
     }
 
+    crate fn is_null(&self) -> bool {
+        *self == Chamber::Null
+    }
+
     #[allow(dead_code)]
     crate fn display(&self) -> String {
         match self {
             Chamber::Key(k) => format!("{}", k),
             Chamber::Integer(i) => format!("{}", i),
             Chamber::String(s) => format!("{}", s),
+            Chamber::Null => "NULL".to_owned(),
         }
     }
+
+    /// Like `partial_cmp`, but also compares keys with integers by value (as
+    /// when joining a foreign key column to a primary key).
+    crate fn compare(&self, other: &Chamber) -> Option<Ordering> {
+        match (self, other) {
+            (Chamber::Key(k), Chamber::Integer(i)) => {
+                if *i < 0 {
+                    Some(Ordering::Greater)
+                } else {
+                    k.partial_cmp(&(*i as usize))
+                }
+            }
+            (Chamber::Integer(_), Chamber::Key(_)) => {
+                other.compare(self).map(|ordering| ordering.reverse())
+            }
+            _ => self.partial_cmp(other),
+        }
+    }
+
+    /// A total order, for sorting and indexing: chambers of the same type
+    /// compare as with `partial_cmp`, and NULLs go after everything else.
+    /// (Chambers of different types, which a column shouldn't hold, are
+    /// ordered by type.)
+    crate fn sort_cmp(&self, other: &Chamber) -> Ordering {
+        let rank = |chamber: &Chamber| match chamber {
+            Chamber::Key(_) => 0,
+            Chamber::Integer(_) => 1,
+            Chamber::String(_) => 2,
+            Chamber::Null => 3,
+        };
+        self.partial_cmp(other)
+            .unwrap_or_else(|| rank(self).cmp(&rank(other)))
+    }
+}
+
+/// Integers are ordered numerically, strings lexically, and keys by value;
+/// chambers of different types don't have an order.
+impl PartialOrd for Chamber {
+    fn partial_cmp(&self, other: &Chamber) -> Option<Ordering> {
+        match (self, other) {
+            (Chamber::Key(a), Chamber::Key(b)) => a.partial_cmp(b),
+            (Chamber::Integer(a), Chamber::Integer(b)) => a.partial_cmp(b),
+            (Chamber::String(a), Chamber::String(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+/// A reference to the primary key of a table (which is enforced by the
+/// `Database`, as it involves other tables).
+#[derive(Debug)]
+crate struct ForeignKey {
+    crate table_name: String,
+    crate on_delete: ReferentialAction,
+}
+
+#[derive(Debug, Default)]
+crate struct Constraints {
+    crate not_null: bool,
+    crate unique: bool,
+    // what an INSERT that doesn't name the column puts in it (if not NULL)
+    crate default: Option<Chamber>,
+    // evaluated against the whole row; only FALSE (not UNKNOWN) violates it
+    crate check: Option<WhereSubcommand>,
+    crate references: Option<ForeignKey>,
 }
 
 #[derive(Debug)]
 crate struct Column {
     crate name: String,
     crate column_type: ColumnType,
+    crate constraints: Constraints,
 }
 
 crate struct TableSchema {
@@ -53,16 +130,78 @@ impl TableSchema {
             layout: vec![Column {
                 name: "pk".to_owned(),
                 column_type: ColumnType::Key,
+                constraints: Constraints::default(),
             }],
         }
     }
 
-    // TODO: verify uniqueness of column names!!
-    crate fn add_column(&mut self, name: String, column_type: ColumnType) {
+    crate fn add_column(
+        &mut self,
+        name: String,
+        column_type: ColumnType,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.layout.iter().any(|column| column.name == name) {
+            return Err(From::from(format!(
+                "duplicate column name {}",
+                name
+            )));
+        }
         self.layout.push(Column {
             name,
             column_type,
+            constraints: Constraints::default(),
         });
+        Ok(())
+    }
+
+    /// Sets the constraints on the column at the given offset. (CHECK
+    /// constraints are compiled against the schema, so the columns have to be
+    /// added first.)
+    crate fn constrain(
+        &mut self,
+        offset: usize,
+        constraints: Constraints,
+    ) -> Result<(), Box<dyn Error>> {
+        let column = &mut self.layout[offset];
+        if column.column_type == ColumnType::Key {
+            return Err(From::from(format!(
+                "can't constrain primary key column {}",
+                column.name
+            )));
+        }
+        if let Some(default) = &constraints.default {
+            match default.column_type() {
+                Some(default_type) if default_type != column.column_type => {
+                    return Err(From::from(format!(
+                        "DEFAULT for {:?} column {} can't be {:?} value {}",
+                        column.column_type,
+                        column.name,
+                        default_type,
+                        default.display()
+                    )))
+                }
+                _ => {}
+            }
+        }
+        column.constraints = constraints;
+        Ok(())
+    }
+
+    /// A row to insert values into: the primary key is a placeholder, and the
+    /// other columns have their defaults.
+    crate fn default_row(&self) -> Row {
+        Row(self
+            .layout
+            .iter()
+            .map(|column| match column.column_type {
+                ColumnType::Key => Chamber::Key(0),
+                _ => column
+                    .constraints
+                    .default
+                    .clone()
+                    .unwrap_or(Chamber::Null),
+            })
+            .collect())
     }
 
     crate fn validate_row(
@@ -72,43 +211,532 @@ impl TableSchema {
         for (i, (ref chamber, ref column_def)) in
             chambers.iter().zip(&self.layout).enumerate()
         {
-            let column_type = chamber.column_type();
             let expected_type = column_def.column_type;
-            if column_type != expected_type {
+            match chamber.column_type() {
+                Some(column_type) if column_type == expected_type => {}
+                Some(column_type) => {
+                    return Err(From::from(format!(
+                        "type mismatch at {}: expected {:?}, got {:?}",
+                        i, expected_type, column_type
+                    )))
+                }
+                // (any column but the primary key can hold NULL)
+                None if expected_type != ColumnType::Key => {}
+                None => {
+                    return Err(From::from(format!(
+                        "type mismatch at {}: expected {:?}, got NULL",
+                        i, expected_type
+                    )))
+                }
+            }
+        }
+        for (chamber, column) in chambers.iter().zip(&self.layout) {
+            if column.constraints.not_null && chamber.is_null() {
                 return Err(From::from(format!(
-                    "type mismatch at {}: expected {:?}, got {:?}",
-                    i, expected_type, column_type
+                    "NULL value in column {} violates NOT NULL constraint",
+                    column.name
                 )));
             }
         }
+        for column in &self.layout {
+            if let Some(check) = &column.constraints.check {
+                if check.truth(&chambers[..]) == Some(false) {
+                    return Err(From::from(format!(
+                        "row violates CHECK constraint on column {}",
+                        column.name
+                    )));
+                }
+            }
+        }
         Ok(())
     }
 }
 
+#[derive(Debug)]
 crate struct Row(crate Vec<Chamber>);
 
+/// The values of a row in the columns of an index, ordered column by column
+/// with `Chamber::sort_cmp` (so that a key sorts just before the keys it's a
+/// prefix of).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+crate struct IndexKey(crate Vec<Chamber>);
+
+impl Ord for IndexKey {
+    fn cmp(&self, other: &IndexKey) -> Ordering {
+        for (left, right) in self.0.iter().zip(&other.0) {
+            let ordering = left.sort_cmp(right);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        self.0.len().cmp(&other.0.len())
+    }
+}
+
+impl PartialOrd for IndexKey {
+    fn partial_cmp(&self, other: &IndexKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The primary keys of the rows having each key in an index.
+crate enum IndexEntries {
+    BTree(BTreeMap<IndexKey, BTreeSet<usize>>),
+    Hash(HashMap<IndexKey, BTreeSet<usize>>),
+}
+
+impl IndexEntries {
+    fn new(kind: IndexKind) -> Self {
+        match kind {
+            IndexKind::BTree => IndexEntries::BTree(BTreeMap::new()),
+            IndexKind::Hash => IndexEntries::Hash(HashMap::new()),
+        }
+    }
+
+    crate fn kind(&self) -> IndexKind {
+        match self {
+            IndexEntries::BTree(_) => IndexKind::BTree,
+            IndexEntries::Hash(_) => IndexKind::Hash,
+        }
+    }
+
+    crate fn get(&self, key: &IndexKey) -> Option<&BTreeSet<usize>> {
+        match self {
+            IndexEntries::BTree(entries) => entries.get(key),
+            IndexEntries::Hash(entries) => entries.get(key),
+        }
+    }
+
+    #[cfg(test)]
+    crate fn len(&self) -> usize {
+        match self {
+            IndexEntries::BTree(entries) => entries.len(),
+            IndexEntries::Hash(entries) => entries.len(),
+        }
+    }
+
+    fn insert(&mut self, key: IndexKey, pk: usize) {
+        let pks = match self {
+            IndexEntries::BTree(entries) => entries.entry(key).or_default(),
+            IndexEntries::Hash(entries) => entries.entry(key).or_default(),
+        };
+        pks.insert(pk);
+    }
+
+    fn remove(&mut self, key: &IndexKey, pk: usize) {
+        let pks = match self {
+            IndexEntries::BTree(entries) => entries.get_mut(key),
+            IndexEntries::Hash(entries) => entries.get_mut(key),
+        };
+        let now_empty = match pks {
+            Some(pks) => {
+                pks.remove(&pk);
+                pks.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            match self {
+                IndexEntries::BTree(entries) => entries.remove(key),
+                IndexEntries::Hash(entries) => entries.remove(key),
+            };
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            IndexEntries::BTree(entries) => entries.clear(),
+            IndexEntries::Hash(entries) => entries.clear(),
+        }
+    }
+}
+
+/// A secondary index: the primary keys of the rows having each combination
+/// of values in the indexed columns, in order of those values (for a B-tree
+/// index) or hashed by them.
+crate struct Index {
+    crate name: String,
+    crate column_offsets: Vec<usize>,
+    crate unique: bool,
+    crate entries: IndexEntries,
+}
+
+impl Index {
+    crate fn key(&self, row: &Row) -> IndexKey {
+        IndexKey(
+            self.column_offsets
+                .iter()
+                .map(|&offset| row.0[offset].clone())
+                .collect(),
+        )
+    }
+
+    fn add(&mut self, row: &Row) {
+        let key = self.key(row);
+        self.entries.insert(key, row.pk());
+    }
+
+    fn remove(&mut self, row: &Row) {
+        let key = self.key(row);
+        self.entries.remove(&key, row.pk());
+    }
+
+    /// Checks that no two of the rows have the same key in a UNIQUE index
+    /// (keys with NULLs aside, as for UNIQUE columns), going through all of
+    /// them: this is for indexing rows that are already in a table.
+    fn validate_uniqueness<'r, I>(&self, rows: I) -> Result<(), Box<dyn Error>>
+    where
+        I: Iterator<Item = &'r Row>,
+    {
+        if !self.unique {
+            return Ok(());
+        }
+        let mut seen = HashSet::new();
+        for row in rows {
+            let key = self.key(row);
+            if !key.0.iter().any(Chamber::is_null) && !seen.insert(key.clone())
+            {
+                return Err(self.violation(&key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a key that the given rows (new rows, or new versions of rows in
+    /// `table_rows`, which the index holds) would have more than one of in a
+    /// UNIQUE index, looking up only the keys that they change.
+    fn duplicate<'r, I>(
+        &self,
+        table_rows: &BTreeMap<usize, Row>,
+        rows: I,
+    ) -> Option<IndexKey>
+    where
+        I: Iterator<Item = &'r Row> + Clone,
+    {
+        if !self.unique {
+            return None;
+        }
+        let old_key =
+            |row: &Row| table_rows.get(&row.pk()).map(|old| self.key(old));
+        // (rows moving to new keys leave their old ones free)
+        let moved = rows
+            .clone()
+            .filter(|row| {
+                old_key(row).map_or(false, |key| key != self.key(row))
+            })
+            .map(Row::pk)
+            .collect::<HashSet<_>>();
+        let mut seen = HashSet::new();
+        for row in rows {
+            let key = self.key(row);
+            if key.0.iter().any(Chamber::is_null)
+                || old_key(row).as_ref() == Some(&key)
+            {
+                continue;
+            }
+            let taken = self.entries.get(&key).map_or(false, |pks| {
+                pks.iter().any(|pk| *pk != row.pk() && !moved.contains(pk))
+            });
+            if taken || !seen.insert(key.clone()) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    fn violation(&self, key: &IndexKey) -> Box<dyn Error> {
+        From::from(format!(
+            "duplicate key ({}) violates UNIQUE index {}",
+            key.0
+                .iter()
+                .map(Chamber::display)
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.name
+        ))
+    }
+}
+
+impl Row {
+    crate fn pk(&self) -> usize {
+        match self.0[0] {
+            Chamber::Key(pk) => pk,
+            _ => panic!("rows should start with their primary key"),
+        }
+    }
+}
+
+// how many buckets a histogram divides the values of a column into
+const HISTOGRAM_BUCKETS: usize = 10;
+
+/// What ANALYZE found in a column.
+#[derive(Debug, PartialEq)]
+crate struct ColumnStatistics {
+    crate distinct_count: usize,
+    crate null_count: usize,
+    crate min: Option<Chamber>,
+    crate max: Option<Chamber>,
+    // the (non-NULL) values at the boundaries of buckets holding about as
+    // many values as each other, from the least to the greatest (or every
+    // value, if there are only a few)
+    crate histogram: Vec<Chamber>,
+}
+
+impl ColumnStatistics {
+    fn collect<'r, I>(chambers: I) -> Self
+    where
+        I: Iterator<Item = &'r Chamber>,
+    {
+        let (nulls, mut values): (Vec<_>, Vec<_>) =
+            chambers.partition(|chamber| chamber.is_null());
+        values.sort_by(|a, b| a.sort_cmp(b));
+        let mut distinct_count = 0;
+        for (i, value) in values.iter().enumerate() {
+            if i == 0 || values[i - 1] != *value {
+                distinct_count += 1;
+            }
+        }
+        let bounds = values.len().min(HISTOGRAM_BUCKETS + 1);
+        let histogram = (0..bounds)
+            .map(|i| {
+                let position = if bounds > 1 {
+                    i * (values.len() - 1) / (bounds - 1)
+                } else {
+                    0
+                };
+                values[position].clone()
+            })
+            .collect();
+        Self {
+            distinct_count,
+            null_count: nulls.len(),
+            min: values.first().map(|&value| value.clone()),
+            max: values.last().map(|&value| value.clone()),
+            histogram,
+        }
+    }
+}
+
+/// Statistics about a table, for estimating how many rows a query will
+/// touch. They're only as fresh as the last ANALYZE.
+#[derive(Debug, PartialEq)]
+crate struct TableStatistics {
+    crate row_count: usize,
+    crate columns: Vec<ColumnStatistics>,
+}
+
 pub struct Table {
     crate schema: TableSchema,
     crate rows: BTreeMap<usize, Row>,
-    // TODO indices
+    // Primary keys are never reused (until a TRUNCATE), so allocation can't
+    // be derived from the number of rows once deletions are possible
+    crate next_pk: usize,
+    crate indices: Vec<Index>,
+    // An implicit unique index on each UNIQUE column, which enforces it
+    // (apart from the named indices, as it's never created or persisted)
+    crate constraint_indices: Vec<Index>,
+    crate statistics: Option<TableStatistics>,
 }
 
 impl Table {
     crate fn new(schema: TableSchema) -> Self {
+        let constraint_indices = schema
+            .layout
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.constraints.unique)
+            .map(|(offset, column)| Index {
+                name: column.name.clone(),
+                column_offsets: vec![offset],
+                unique: true,
+                entries: IndexEntries::new(IndexKind::Hash),
+            })
+            .collect();
         Self {
             schema,
             rows: BTreeMap::new(),
+            next_pk: 1,
+            indices: Vec::new(),
+            constraint_indices,
+            statistics: None,
+        }
+    }
+
+    /// Checks that the given columns (by offset) could be indexed under
+    /// the given name: that the name isn't taken, and that a UNIQUE index
+    /// wouldn't have duplicates.
+    crate fn validate_index(
+        &self,
+        name: &str,
+        column_offsets: &[usize],
+        unique: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.indices.iter().any(|index| index.name == name) {
+            return Err(From::from(format!("index {} already exists", name)));
         }
+        // (checking doesn't need the entries, so they're left empty)
+        let index = Index {
+            name: name.to_owned(),
+            column_offsets: column_offsets.to_vec(),
+            unique,
+            entries: IndexEntries::new(IndexKind::Hash),
+        };
+        index.validate_uniqueness(self.rows.values())
+    }
+
+    /// Indexes the given columns (by offset) under the given name, failing
+    /// if the name is taken or if a UNIQUE index would have duplicates.
+    crate fn create_index(
+        &mut self,
+        name: String,
+        column_offsets: Vec<usize>,
+        unique: bool,
+        kind: IndexKind,
+    ) -> Result<(), Box<dyn Error>> {
+        self.validate_index(&name, &column_offsets, unique)?;
+        let mut index = Index {
+            name,
+            column_offsets,
+            unique,
+            entries: IndexEntries::new(kind),
+        };
+        for row in self.rows.values() {
+            index.add(row);
+        }
+        self.indices.push(index);
+        Ok(())
     }
 
     // TODO: use `failure` crate
-    crate fn insert(&mut self, mut row: Row) -> Result<usize, Box<dyn Error>> {
+    /// Checks a row to be inserted, returning it with the primary key it
+    /// will have.
+    crate fn prepare_insert(
+        &self,
+        mut row: Row,
+    ) -> Result<Row, Box<dyn Error>> {
+        row.0[0] = Chamber::Key(self.next_pk);
         self.schema.validate_row(&row)?;
-        let pk = self.rows.len() + 1;
-        row.0[0] = Chamber::Key(pk);
-        let p = self.rows.insert(pk, row);
-        assert!(p.is_none());
-        Ok(pk)
+        self.validate_uniqueness(std::iter::once(&row))?;
+        Ok(row)
+    }
+
+    /// Checks rows to be placed in the table (new ones, or new versions of
+    /// ones in it) against its UNIQUE columns and indices.
+    crate fn validate_uniqueness<'r, I>(
+        &self,
+        rows: I,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        I: Iterator<Item = &'r Row> + Clone,
+    {
+        for index in &self.constraint_indices {
+            if let Some(key) = index.duplicate(&self.rows, rows.clone()) {
+                return Err(From::from(format!(
+                    "duplicate value {} in column {} violates UNIQUE \
+                     constraint",
+                    key.0[0].display(),
+                    index.name
+                )));
+            }
+        }
+        for index in &self.indices {
+            if let Some(key) = index.duplicate(&self.rows, rows.clone()) {
+                return Err(index.violation(&key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts a row in the table under its primary key, in place of any row
+    /// that was there (without checking it against the table's constraints,
+    /// which is up to the caller).
+    crate fn place(&mut self, row: Row) {
+        let pk = row.pk();
+        self.remove(pk);
+        let indices = self.indices.iter_mut();
+        for index in indices.chain(&mut self.constraint_indices) {
+            index.add(&row);
+        }
+        self.rows.insert(pk, row);
+        self.next_pk = self.next_pk.max(pk + 1);
+    }
+
+    /// Removes the row with the given primary key, if there is one.
+    crate fn remove(&mut self, pk: usize) -> Option<Row> {
+        let row = self.rows.remove(&pk)?;
+        let indices = self.indices.iter_mut();
+        for index in indices.chain(&mut self.constraint_indices) {
+            index.remove(&row);
+        }
+        Some(row)
+    }
+
+    /// The rows satisfying the predicate as they'd be with the chambers at
+    /// the given column offsets overwritten (by values computed from the rows
+    /// as they were), checked against the table's constraints as it would be
+    /// with them in place of the originals.
+    crate fn updated_where<F>(
+        &self,
+        assignments: &[(usize, ValueSubcommand)],
+        predicate: F,
+    ) -> Result<Vec<Row>, Box<dyn Error>>
+    where
+        F: Fn(&Row) -> bool,
+    {
+        for &(offset, _) in assignments {
+            let column = &self.schema.layout[offset];
+            if column.column_type == ColumnType::Key {
+                return Err(From::from(format!(
+                    "can't update primary key column {}",
+                    column.name
+                )));
+            }
+        }
+        let mut updated = Vec::new();
+        for row in self.rows.values() {
+            if !predicate(row) {
+                continue;
+            }
+            let mut chambers = row.0.clone();
+            for (offset, value) in assignments {
+                chambers[*offset] = value.evaluate(row)?;
+            }
+            let new_row = Row(chambers);
+            self.schema.validate_row(&new_row)?;
+            updated.push(new_row);
+        }
+        // (uniqueness is a property of the table as it would be after the
+        // update, not of the rows one at a time)
+        self.validate_uniqueness(updated.iter())?;
+        Ok(updated)
+    }
+
+    /// Removes all rows (and restarts primary-key allocation), returning how
+    /// many there were.
+    crate fn truncate(&mut self) -> usize {
+        let count = self.rows.len();
+        self.rows.clear();
+        let indices = self.indices.iter_mut();
+        for index in indices.chain(&mut self.constraint_indices) {
+            index.entries.clear();
+        }
+        self.next_pk = 1;
+        count
+    }
+
+    /// Collects statistics about every column (replacing any from before).
+    crate fn analyze(&mut self) {
+        let columns = (0..self.schema.layout.len())
+            .map(|offset| {
+                ColumnStatistics::collect(
+                    self.rows.values().map(|row| &row.0[offset]),
+                )
+            })
+            .collect();
+        self.statistics = Some(TableStatistics {
+            row_count: self.rows.len(),
+            columns,
+        });
     }
 
     #[allow(dead_code)]
@@ -147,12 +775,71 @@ mod tests {
 
     fn example_table() -> Table {
         let mut schema = TableSchema::new();
-        schema.add_column("title".to_owned(), ColumnType::String);
-        schema.add_column("year".to_owned(), ColumnType::Integer);
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        schema
+            .add_column("year".to_owned(), ColumnType::Integer)
+            .unwrap();
         let table = Table::new(schema);
         table
     }
 
+    // (an assignment of a constant, as in `UPDATE … SET column = constant`)
+    fn set(offset: usize, value: Chamber) -> (usize, ValueSubcommand) {
+        (offset, ValueSubcommand::Constant(value))
+    }
+
+    #[test]
+    fn concerning_duplicate_column_names() {
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        assert!(
+            schema
+                .add_column("title".to_owned(), ColumnType::Integer)
+                .is_err()
+        );
+        assert!(schema.add_column("pk".to_owned(), ColumnType::Key).is_err());
+    }
+
+    #[test]
+    fn concerning_chamber_ordering() {
+        assert!(Chamber::Integer(-3) < Chamber::Integer(2));
+        assert!(Chamber::Key(10) > Chamber::Key(9));
+        assert!(
+            Chamber::String("Zebra".to_owned())
+                < Chamber::String("aardvark".to_owned())
+        );
+        assert_eq!(
+            Chamber::Integer(1).partial_cmp(&Chamber::String("1".to_owned())),
+            None
+        );
+        assert_eq!(Chamber::Integer(1).partial_cmp(&Chamber::Key(1)), None);
+        assert_eq!(Chamber::Null.partial_cmp(&Chamber::Null), None);
+    }
+
+    #[test]
+    fn concerning_comparing_keys_with_integers() {
+        assert_eq!(
+            Chamber::Key(2).compare(&Chamber::Integer(3)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Chamber::Integer(3).compare(&Chamber::Key(2)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            Chamber::Integer(-1).compare(&Chamber::Key(0)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            Chamber::Key(1).compare(&Chamber::String("1".to_owned())),
+            None
+        );
+    }
+
     #[test]
     fn concerning_insertion() {
         let mut books = example_table();
@@ -161,18 +848,431 @@ mod tests {
             Chamber::String("The Fountainhead".to_owned()),
             Chamber::Integer(1943),
         ]);
-        books.insert(the_fountainhead).unwrap();
+        books.place(books.prepare_insert(the_fountainhead).unwrap());
     }
 
     #[test]
-    fn concerning_type_mismatch_on_insertion() {
+    fn concerning_insertion_of_nulls() {
         let mut books = example_table();
+        let untitled = Row(vec![
+            Chamber::Key(0),
+            Chamber::Null,
+            Chamber::Integer(1943),
+        ]);
+        books.place(books.prepare_insert(untitled).unwrap());
+        assert!(
+            books
+                .schema
+                .validate_row(&Row(vec![
+                    Chamber::Null,
+                    Chamber::Null,
+                    Chamber::Null,
+                ]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn concerning_type_mismatch_on_insertion() {
+        let books = example_table();
         let causality = Row(vec![
             Chamber::Key(0),
             Chamber::Integer(2000),
             Chamber::String("Causality".to_owned()),
         ]);
-        assert!(books.insert(causality).is_err());
+        assert!(books.prepare_insert(causality).is_err());
+    }
+
+    #[test]
+    fn concerning_primary_keys_after_deletion() {
+        let mut books = example_table();
+        for (title, year) in &[
+            ("Gödel, Escher, Bach", 1979),
+            ("Le Ton beau de Marot", 1997),
+            ("I Am a Strange Loop", 2007),
+        ] {
+            books.place(
+                books
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String((*title).to_owned()),
+                        Chamber::Integer(*year),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        assert!(books.remove(3).is_some());
+        let row = books
+            .prepare_insert(Row(vec![
+                Chamber::Key(0),
+                Chamber::String("Metamagical Themas".to_owned()),
+                Chamber::Integer(1985),
+            ]))
+            .unwrap();
+        assert_eq!(row.pk(), 4);
+        books.place(row);
+        assert_eq!(
+            books.rows.keys().cloned().collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+    }
+
+    #[test]
+    fn concerning_updates() {
+        let mut books = example_table();
+        books.place(
+            books
+                .prepare_insert(Row(vec![
+                    Chamber::Key(0),
+                    Chamber::String("The Moon Is a Harsh Mistress".to_owned()),
+                    Chamber::Integer(1965),
+                ]))
+                .unwrap(),
+        );
+        let updated = books
+            .updated_where(&[set(2, Chamber::Integer(1966))], |_| true)
+            .unwrap();
+        assert_eq!(updated.len(), 1);
+        for row in updated {
+            books.place(row);
+        }
+        assert_eq!(books.rows[&1].0[2], Chamber::Integer(1966));
+
+        assert!(
+            books
+                .updated_where(&[set(0, Chamber::Key(7))], |_| true)
+                .is_err()
+        );
+        assert!(
+            books
+                .updated_where(
+                    &[
+                        set(1, Chamber::Integer(1)),
+                        set(2, Chamber::Integer(1)),
+                    ],
+                    |_| true
+                )
+                .is_err()
+        );
+        assert_eq!(
+            books.rows[&1].0[1],
+            Chamber::String("The Moon Is a Harsh Mistress".to_owned())
+        );
+        assert_eq!(books.rows[&1].0[2], Chamber::Integer(1966));
+    }
+
+    #[test]
+    fn concerning_not_null_and_unique_constraints() {
+        // (constraints are set before the table is made, as for CREATE
+        // TABLE, so that it has an index for the UNIQUE column)
+        let mut schema = example_table().schema;
+        schema
+            .constrain(
+                1,
+                Constraints {
+                    unique: true,
+                    ..Constraints::default()
+                },
+            )
+            .unwrap();
+        schema
+            .constrain(
+                2,
+                Constraints {
+                    not_null: true,
+                    ..Constraints::default()
+                },
+            )
+            .unwrap();
+        let mut books = Table::new(schema);
+        let book = |title: Option<&str>, year: Option<isize>| {
+            Row(vec![
+                Chamber::Key(0),
+                title.map_or(Chamber::Null, |t| Chamber::String(t.to_owned())),
+                year.map_or(Chamber::Null, Chamber::Integer),
+            ])
+        };
+        let mut insert = |row| books.place(books.prepare_insert(row).unwrap());
+        insert(book(Some("Anathem"), Some(2008)));
+        insert(book(Some("Seveneves"), Some(2015)));
+        // NULLs aren't duplicates of each other
+        insert(book(None, Some(2019)));
+        insert(book(None, Some(2021)));
+        assert_eq!(
+            books
+                .prepare_insert(book(Some("Anathem"), Some(2009)))
+                .unwrap_err()
+                .to_string(),
+            "duplicate value Anathem in column title violates UNIQUE \
+             constraint"
+        );
+        assert_eq!(
+            books
+                .prepare_insert(book(Some("Reamde"), None))
+                .unwrap_err()
+                .to_string(),
+            "NULL value in column year violates NOT NULL constraint"
+        );
+
+        // Updates are checked against the table as it would be afterward
+        let is_seveneves = |row: &Row| row.0[0] == Chamber::Key(2);
+        assert!(
+            books
+                .updated_where(
+                    &[set(1, Chamber::String("Anathem".to_owned()))],
+                    is_seveneves
+                )
+                .is_err()
+        );
+        assert!(
+            books
+                .updated_where(&[set(2, Chamber::Null)], is_seveneves)
+                .is_err()
+        );
+        assert_eq!(
+            books
+                .updated_where(
+                    &[set(1, Chamber::String("Seveneves".to_owned()))],
+                    |row| row.0[0] == Chamber::Key(1)
+                )
+                .unwrap_err()
+                .to_string(),
+            "duplicate value Seveneves in column title violates UNIQUE \
+             constraint"
+        );
+        let updated = books
+            .updated_where(
+                &[set(1, Chamber::String("Snow Crash".to_owned()))],
+                |row| row.0[2] == Chamber::Integer(2008),
+            )
+            .unwrap();
+        assert_eq!(updated.len(), 1);
+        for row in updated {
+            books.place(row);
+        }
+        assert!(
+            books
+                .updated_where(
+                    &[set(1, Chamber::String("Cryptonomicon".to_owned()))],
+                    |row| row.0[2] != Chamber::Integer(2008)
+                )
+                .is_err()
+        );
+        // (Snow Crash, Seveneves and NULL)
+        assert_eq!(books.constraint_indices[0].entries.len(), 3);
+        books.truncate();
+        assert_eq!(books.constraint_indices[0].entries.len(), 0);
+    }
+
+    #[test]
+    fn concerning_defaults() {
+        let mut schema = example_table().schema;
+        assert!(
+            schema
+                .constrain(
+                    2,
+                    Constraints {
+                        default: Some(Chamber::String("1999".to_owned())),
+                        ..Constraints::default()
+                    },
+                )
+                .is_err()
+        );
+        schema
+            .constrain(
+                2,
+                Constraints {
+                    default: Some(Chamber::Integer(1999)),
+                    ..Constraints::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            schema.default_row().0,
+            vec![Chamber::Key(0), Chamber::Null, Chamber::Integer(1999)]
+        );
+    }
+
+    #[test]
+    fn concerning_index_key_ordering() {
+        let key = |chambers: &[Chamber]| IndexKey(chambers.to_vec());
+        assert!(
+            key(&[Chamber::Integer(1)])
+                < key(&[Chamber::Integer(1), Chamber::Integer(-5)])
+        );
+        assert!(
+            key(&[Chamber::Integer(1), Chamber::Null])
+                < key(&[Chamber::Integer(2), Chamber::Integer(0)])
+        );
+        assert!(key(&[Chamber::Integer(1000)]) < key(&[Chamber::Null]));
+    }
+
+    #[test]
+    fn concerning_indices() {
+        let mut books = example_table();
+        books
+            .create_index("years".to_owned(), vec![2], false, IndexKind::BTree)
+            .unwrap();
+        for (title, year) in &[
+            ("Blindsight", 2006),
+            ("Echopraxia", 2014),
+            ("Starfish", 1999),
+            ("Maelstrom", 2001),
+        ] {
+            books.place(
+                books
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String((*title).to_owned()),
+                        Chamber::Integer(*year),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        books.remove(4);
+        let updated = books
+            .updated_where(&[set(2, Chamber::Integer(2006))], |row| {
+                row.0[2] == Chamber::Integer(2014)
+            })
+            .unwrap();
+        for row in updated {
+            books.place(row);
+        }
+        let updated =
+            books.updated_where(&[set(1, Chamber::Null)], |_| true).unwrap();
+        for row in updated {
+            books.place(row);
+        }
+        let entries = match &books.indices[0].entries {
+            IndexEntries::BTree(entries) => entries
+                .iter()
+                .map(|(key, pks)| {
+                    (key.0.clone(), pks.iter().cloned().collect())
+                })
+                .collect::<Vec<(Vec<Chamber>, Vec<usize>)>>(),
+            IndexEntries::Hash(_) => panic!("should be a B-tree index"),
+        };
+        assert_eq!(
+            entries,
+            vec![
+                (vec![Chamber::Integer(1999)], vec![3]),
+                (vec![Chamber::Integer(2006)], vec![1, 2]),
+            ]
+        );
+
+        assert!(
+            books
+                .create_index(
+                    "years".to_owned(),
+                    vec![1],
+                    false,
+                    IndexKind::BTree
+                )
+                .is_err()
+        );
+        assert!(
+            books
+                .create_index(
+                    "uniqueyears".to_owned(),
+                    vec![2],
+                    true,
+                    IndexKind::BTree
+                )
+                .is_err()
+        );
+        books.truncate();
+        assert_eq!(books.indices[0].entries.len(), 0);
+    }
+
+    #[test]
+    fn concerning_unique_indices() {
+        let mut books = example_table();
+        books
+            .create_index(
+                "titles".to_owned(),
+                vec![1, 2],
+                true,
+                IndexKind::Hash,
+            )
+            .unwrap();
+        let book = |title: &str, year| {
+            Row(vec![
+                Chamber::Key(0),
+                Chamber::String(title.to_owned()),
+                Chamber::Integer(year),
+            ])
+        };
+        books.place(books.prepare_insert(book("Solaris", 1961)).unwrap());
+        books.place(books.prepare_insert(book("Solaris", 1972)).unwrap());
+        let error =
+            books.prepare_insert(book("Solaris", 1961)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "duplicate key (Solaris, 1961) violates UNIQUE index titles"
+        );
+        assert!(
+            books
+                .updated_where(&[set(2, Chamber::Integer(1961))], |_| true)
+                .is_err()
+        );
+        let updated =
+            books.updated_where(&[set(2, Chamber::Null)], |_| true).unwrap();
+        for row in updated {
+            books.place(row);
+        }
+        assert_eq!(books.indices[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn concerning_statistics() {
+        let mut books = example_table();
+        for i in 0..30 {
+            books.place(
+                books
+                    .prepare_insert(Row(vec![
+                        Chamber::Key(0),
+                        Chamber::String(format!("Volume {}", i % 7)),
+                        Chamber::Integer(2000 + i % 3),
+                    ]))
+                    .unwrap(),
+            );
+        }
+        let untitled =
+            || Row(vec![Chamber::Key(0), Chamber::Null, Chamber::Null]);
+        books.place(books.prepare_insert(untitled()).unwrap());
+        assert_eq!(books.statistics, None);
+        books.analyze();
+        let statistics = books.statistics.as_ref().unwrap();
+        assert_eq!(statistics.row_count, 31);
+        let pks = &statistics.columns[0];
+        assert_eq!(pks.distinct_count, 31);
+        assert_eq!(
+            (&pks.min, &pks.max),
+            (&Some(Chamber::Key(1)), &Some(Chamber::Key(31)))
+        );
+        assert_eq!(
+            pks.histogram,
+            (0..11).map(|i| Chamber::Key(1 + 3 * i)).collect::<Vec<_>>()
+        );
+        let titles = &statistics.columns[1];
+        assert_eq!((titles.distinct_count, titles.null_count), (7, 1));
+        let years = &statistics.columns[2];
+        assert_eq!(
+            years.histogram,
+            [2000, 2000, 2000, 2000, 2001, 2001, 2001, 2002, 2002, 2002, 2002]
+                .iter()
+                .map(|&year| Chamber::Integer(year))
+                .collect::<Vec<_>>()
+        );
+
+        books.truncate();
+        books.place(books.prepare_insert(untitled()).unwrap());
+        books.analyze();
+        let years = &books.statistics.as_ref().unwrap().columns[2];
+        assert_eq!(
+            (years.distinct_count, &years.min, years.histogram.len()),
+            (0, &None, 0)
+        );
     }
 
     #[test]
@@ -183,7 +1283,13 @@ mod tests {
             Chamber::String("Permutation City".to_owned()),
             Chamber::Integer(1994),
         ]);
-        books.insert(permutation_city).unwrap();
+        books.place(books.prepare_insert(permutation_city).unwrap());
+        let untitled = Row(vec![
+            Chamber::Key(0),
+            Chamber::String("".to_owned()),
+            Chamber::Null,
+        ]);
+        books.place(books.prepare_insert(untitled).unwrap());
         assert_eq!(
             books.display(),
             "\
@@ -191,6 +1297,7 @@ mod tests {
 | pk | title            | year |
 +----+------------------+------+
 | 1  | Permutation City | 1994 |
+| 2  |                  | NULL |
 +----+------------------+------+
 "
         );
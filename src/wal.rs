@@ -0,0 +1,440 @@
+//! The write-ahead log, where the changes made by each statement are
+//! recorded (and synced to disk) before they're made, so that they survive a
+//! crash before the next checkpoint writes out the database file. Opening a
+//! database replays the changes in its log that its file doesn't include.
+//!
+//! The log is a sequence of records, one for each statement: its length and
+//! checksum, then its sequence number and changes. A record that was cut
+//! short (by a crash while it was being written) fails its checksum, and it
+//! and anything after it are dropped when the log is opened: that statement
+//! never happened.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::sql::IndexKind;
+use crate::storage::{corrupt, Decoder, Encoder};
+use crate::table::{Row, Table, TableSchema};
+
+/// A change to a database's tables, worked out (and checked) in full before
+/// it's recorded, so that replaying it doesn't depend on anything but the
+/// changes before it.
+crate enum Change {
+    CreateTable {
+        table_name: String,
+        schema: TableSchema,
+    },
+    DropTable {
+        table_name: String,
+    },
+    CreateIndex {
+        table_name: String,
+        index_name: String,
+        column_offsets: Vec<usize>,
+        unique: bool,
+        kind: IndexKind,
+    },
+    // (inserting a row, or updating one, under its primary key)
+    Put {
+        table_name: String,
+        row: Row,
+    },
+    Delete {
+        table_name: String,
+        pk: usize,
+    },
+    Truncate {
+        table_name: String,
+    },
+    Analyze {
+        table_name: String,
+    },
+}
+
+impl Change {
+    /// Makes the change to the tables. This only fails for a change that
+    /// wasn't checked against them (as when a log is replayed onto a
+    /// database file that it doesn't go with).
+    crate fn apply(
+        self,
+        tables: &mut HashMap<String, Table>,
+    ) -> Result<(), Box<dyn Error>> {
+        fn table<'t>(
+            tables: &'t mut HashMap<String, Table>,
+            table_name: &str,
+        ) -> Result<&'t mut Table, Box<dyn Error>> {
+            tables.get_mut(table_name).ok_or_else(|| {
+                From::from(format!("no table named {}", table_name))
+            })
+        }
+        match self {
+            Change::CreateTable { table_name, schema } => {
+                if tables.contains_key(&table_name) {
+                    return Err(From::from(format!(
+                        "table {} already exists",
+                        table_name
+                    )));
+                }
+                tables.insert(table_name, Table::new(schema));
+            }
+            Change::DropTable { table_name } => {
+                if tables.remove(&table_name).is_none() {
+                    return Err(From::from(format!(
+                        "no table named {}",
+                        table_name
+                    )));
+                }
+            }
+            Change::CreateIndex {
+                table_name,
+                index_name,
+                column_offsets,
+                unique,
+                kind,
+            } => table(tables, &table_name)?.create_index(
+                index_name,
+                column_offsets,
+                unique,
+                kind,
+            )?,
+            Change::Put { table_name, row } => {
+                table(tables, &table_name)?.place(row)
+            }
+            Change::Delete { table_name, pk } => {
+                table(tables, &table_name)?.remove(pk);
+            }
+            Change::Truncate { table_name } => {
+                table(tables, &table_name)?.truncate();
+            }
+            Change::Analyze { table_name } => {
+                table(tables, &table_name)?.analyze()
+            }
+        }
+        Ok(())
+    }
+
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Change::CreateTable { table_name, schema } => {
+                encoder.u8(0);
+                encoder.string(table_name);
+                encoder.usize(schema.layout.len());
+                for column in &schema.layout {
+                    encoder.column(column);
+                }
+            }
+            Change::DropTable { table_name } => {
+                encoder.u8(1);
+                encoder.string(table_name);
+            }
+            Change::CreateIndex {
+                table_name,
+                index_name,
+                column_offsets,
+                unique,
+                kind,
+            } => {
+                encoder.u8(2);
+                encoder.string(table_name);
+                encoder.string(index_name);
+                encoder.usize(column_offsets.len());
+                for &offset in column_offsets {
+                    encoder.usize(offset);
+                }
+                encoder.bool(*unique);
+                encoder.index_kind(*kind);
+            }
+            Change::Put { table_name, row } => {
+                encoder.u8(3);
+                encoder.string(table_name);
+                encoder.row(row);
+            }
+            Change::Delete { table_name, pk } => {
+                encoder.u8(4);
+                encoder.string(table_name);
+                encoder.usize(*pk);
+            }
+            Change::Truncate { table_name } => {
+                encoder.u8(5);
+                encoder.string(table_name);
+            }
+            Change::Analyze { table_name } => {
+                encoder.u8(6);
+                encoder.string(table_name);
+            }
+        }
+    }
+
+    fn decode(decoder: &mut Decoder<'_>) -> Result<Self, Box<dyn Error>> {
+        Ok(match decoder.u8()? {
+            0 => {
+                let table_name = decoder.string()?;
+                let mut layout = Vec::new();
+                for _ in 0..decoder.count()? {
+                    layout.push(decoder.column()?);
+                }
+                Change::CreateTable {
+                    table_name,
+                    schema: TableSchema { layout },
+                }
+            }
+            1 => Change::DropTable {
+                table_name: decoder.string()?,
+            },
+            2 => {
+                let table_name = decoder.string()?;
+                let index_name = decoder.string()?;
+                let mut column_offsets = Vec::new();
+                for _ in 0..decoder.count()? {
+                    column_offsets.push(decoder.usize()?);
+                }
+                Change::CreateIndex {
+                    table_name,
+                    index_name,
+                    column_offsets,
+                    unique: decoder.bool()?,
+                    kind: decoder.index_kind()?,
+                }
+            }
+            3 => Change::Put {
+                table_name: decoder.string()?,
+                row: decoder.row()?,
+            },
+            4 => Change::Delete {
+                table_name: decoder.string()?,
+                pk: decoder.usize()?,
+            },
+            5 => Change::Truncate {
+                table_name: decoder.string()?,
+            },
+            6 => Change::Analyze {
+                table_name: decoder.string()?,
+            },
+            _ => return Err(corrupt("bad change")),
+        })
+    }
+}
+
+// The length and checksum of a record's contents
+const RECORD_HEADER_SIZE: usize = 8;
+
+// CRC-32 (as in zlib and PNG), to tell whether a record was written in full
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// The changes found in a log when it's opened, to be replayed: those of
+/// each statement, in the order they were made.
+type Replay = Vec<Vec<Change>>;
+
+/// A write-ahead log file, open for appending records.
+crate struct Log {
+    file: File,
+    // how much of the file holds whole records
+    length: u64,
+    // the sequence number of the next record
+    next_sequence_number: u64,
+}
+
+impl Log {
+    /// Opens the log at `path` (creating it if there isn't one), returning
+    /// the changes recorded in it after the one numbered `checkpoint` (the
+    /// last that the database file includes), by statement.
+    crate fn open(
+        path: &Path,
+        checkpoint: u64,
+    ) -> Result<(Self, Replay), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut statements = Vec::new();
+        let mut last_sequence_number = checkpoint;
+        let mut position = 0;
+        while bytes.len() - position >= RECORD_HEADER_SIZE {
+            let mut header = Decoder::new(
+                &bytes[position..position + RECORD_HEADER_SIZE],
+            );
+            let length = header.u32()? as usize;
+            let expected_checksum = header.u32()?;
+            let start = position + RECORD_HEADER_SIZE;
+            if bytes.len() - start < length
+                || checksum(&bytes[start..][..length]) != expected_checksum
+            {
+                break;
+            }
+            let mut record = Decoder::new(&bytes[start..start + length]);
+            let sequence_number = record.u64()?;
+            if sequence_number > checkpoint {
+                let mut changes = Vec::new();
+                for _ in 0..record.count()? {
+                    changes.push(Change::decode(&mut record)?);
+                }
+                statements.push(changes);
+            }
+            last_sequence_number = last_sequence_number.max(sequence_number);
+            position = start + length;
+        }
+        // (a torn record at the end is cut off, so that records appended
+        // from now on aren't stranded after it)
+        let length = position as u64;
+        if length < bytes.len() as u64 {
+            file.set_len(length)?;
+            file.sync_all()?;
+        }
+        file.seek(SeekFrom::Start(length))?;
+        let log = Self {
+            file,
+            length,
+            next_sequence_number: last_sequence_number + 1,
+        };
+        Ok((log, statements))
+    }
+
+    /// The sequence number of the last record (or of the last checkpoint, if
+    /// none have been appended since).
+    crate fn last_sequence_number(&self) -> u64 {
+        self.next_sequence_number - 1
+    }
+
+    /// Records a statement's changes, returning once they're on disk.
+    crate fn append(
+        &mut self,
+        changes: &[Change],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut contents = Encoder::new();
+        contents.u64(self.next_sequence_number);
+        contents.usize(changes.len());
+        for change in changes {
+            change.encode(&mut contents);
+        }
+        let mut record = Encoder::new();
+        record.u32(contents.bytes.len() as u32);
+        record.u32(checksum(&contents.bytes));
+        record.bytes.extend_from_slice(&contents.bytes);
+
+        let written = self
+            .file
+            .write_all(&record.bytes)
+            .and_then(|()| self.file.sync_data());
+        if let Err(error) = written {
+            // (so as not to leave part of a record behind)
+            let _ = self.file.set_len(self.length);
+            let _ = self.file.seek(SeekFrom::Start(self.length));
+            return Err(From::from(error));
+        }
+        self.length += record.bytes.len() as u64;
+        self.next_sequence_number += 1;
+        Ok(())
+    }
+
+    /// Empties the log (once the database file includes everything in it).
+    crate fn clear(&mut self) -> Result<(), Box<dyn Error>> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        self.length = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    use crate::table::{Chamber, ColumnType};
+
+    #[test]
+    fn concerning_checksums() {
+        assert_eq!(checksum(b""), 0);
+        assert_eq!(checksum(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn concerning_logs() {
+        let path = env::temp_dir()
+            .join(format!("epilogue-log-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut schema = TableSchema::new();
+        schema
+            .add_column("title".to_owned(), ColumnType::String)
+            .unwrap();
+        let put = |title: &str| Change::Put {
+            table_name: "books".to_owned(),
+            row: Row(vec![Chamber::Key(1), Chamber::String(title.to_owned())]),
+        };
+
+        let (mut log, statements) = Log::open(&path, 0).unwrap();
+        assert!(statements.is_empty());
+        log.append(&[
+            Change::CreateTable {
+                table_name: "books".to_owned(),
+                schema,
+            },
+            put("Kindred"),
+        ])
+        .unwrap();
+        log.append(&[put("Dawn")]).unwrap();
+        assert_eq!(log.last_sequence_number(), 2);
+        drop(log);
+
+        let replay = |checkpoint| {
+            let (log, statements) = Log::open(&path, checkpoint).unwrap();
+            (log.last_sequence_number(), statements)
+        };
+        let (last_sequence_number, statements) = replay(0);
+        assert_eq!(last_sequence_number, 2);
+        let mut tables = HashMap::new();
+        for change in statements.into_iter().flatten() {
+            change.apply(&mut tables).unwrap();
+        }
+        assert_eq!(
+            tables["books"].rows[&1].0[1],
+            Chamber::String("Dawn".to_owned())
+        );
+        // (records the database file already includes aren't replayed)
+        let (last_sequence_number, statements) = replay(1);
+        assert_eq!(last_sequence_number, 2);
+        assert_eq!(statements.len(), 1);
+        let (last_sequence_number, statements) = replay(7);
+        assert_eq!(last_sequence_number, 7);
+        assert!(statements.is_empty());
+
+        // (a corrupted record is dropped, along with everything after it)
+        let mut bytes = fs::read(&path).unwrap();
+        let length = bytes.len();
+        bytes[length - 1] ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        let (last_sequence_number, statements) = replay(0);
+        assert_eq!(last_sequence_number, 1);
+        assert_eq!(statements.len(), 1);
+        assert!(fs::metadata(&path).unwrap().len() < length as u64);
+
+        let (mut log, _) = Log::open(&path, 0).unwrap();
+        log.clear().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(log.last_sequence_number(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}